@@ -1,10 +1,31 @@
-use clap::{Parser, Subcommand};
+use std::time::Duration;
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+
+use crate::{
+    fs_path::{FsPath, RemotePath},
+    on_conflict::OnConflict,
+    util,
+};
 
 #[derive(Parser)]
 #[command(
-    about = "Use Discord like a File System.\n\n> Directories always end with a '/', if you want to address a directory it's mandatory to put a trailing '/'!", long_about = None
+    about = "Use Discord like a File System.\n\n> Directories always end with a '/', if you want to address a directory it's mandatory to put a trailing '/'!\n> A '\\' escapes the following character in remote paths, use '\\\\' for a literal backslash.", long_about = None
 )]
 pub struct Command {
+    /// Print transfer summaries and stat output as JSON lines on stdout
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Use the filesystem with this name, several can share one data channel
+    #[arg(long, global = true, value_parser = util::parse_fs_name)]
+    pub fs: Option<String>,
+
+    /// Print how many Discord API calls the operation made
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
     /// What operation to execute
     #[command(subcommand)]
     pub operation: Operation,
@@ -14,24 +35,87 @@ pub struct Command {
 pub enum Operation {
     #[command(about = "List filesystem contents", long_about = None)]
     Ls {
+        /// Show the total size of every directory
+        #[arg(long)]
+        du: bool,
+
+        #[command(flatten)]
+        page: ListPage,
+
         /// Start directory (default is '/')
-        path: Option<String>,
+        path: Option<FsPath>,
     },
     #[command(about = "Upload data", long_about = None)]
     Upload {
-        /// Source path to file
-        source: String,
+        /// Upload a directory as a tar archive
+        #[arg(long)]
+        tar: bool,
 
-        /// Destination path (including file name)
-        destination: String,
+        /// Keep symlinks as links in the archive instead of their targets (modification times and permissions are always kept)
+        #[arg(long, requires = "tar")]
+        mirror_structure: bool,
+
+        /// Source paths to files (or a directory with --tar)
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Destination path (including file name for a single file, directory for several)
+        destination: FsPath,
+
+        /// What to do when the destination file already exists, 'resume' also continues an
+        /// interrupted upload of the file
+        #[arg(long, value_enum, default_value_t = OnConflict::Fail)]
+        on_conflict: OnConflict,
+
+        /// Size of the uploaded blocks (e.g. '1MiB', at most the default of 8MiB)
+        #[arg(long, value_parser = util::parse_size)]
+        chunk_size: Option<u64>,
+
+        /// Encrypt the file into a local spool directory first, then push it with resumable uploads
+        #[arg(long, conflicts_with_all = ["tar", "on_conflict", "chunk_size"])]
+        spool: Option<String>,
+    },
+    #[command(about = "Continue pushing a spooled upload", long_about = None)]
+    Push {
+        /// Spool directory of the upload
+        spool: String,
     },
     #[command(about = "Download files", long_about = None)]
     Download {
-        /// Source path (only files)
-        source: String,
+        /// Extract a tar archive into the destination directory, it has to be new or empty
+        #[arg(long)]
+        untar: bool,
+
+        /// Restore the permissions stored in the archive (modification times are always restored)
+        #[arg(long, requires = "untar")]
+        mirror_structure: bool,
+
+        /// Download a directory into a tar archive ('.tar.zst' destinations are compressed)
+        #[arg(long, conflicts_with = "untar")]
+        as_tar: bool,
+
+        /// Fetch and decrypt the file without writing it, to check the key and every block
+        #[arg(long, conflicts_with_all = ["untar", "as_tar", "destination"])]
+        discard: bool,
+
+        /// Source path (only files, or a directory with --as-tar)
+        source: FsPath,
+
+        /// Destination path (directory with --untar)
+        #[arg(required_unless_present = "discard")]
+        destination: Option<String>,
+
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    #[command(about = "Download a file to a temporary copy and open it with the default application", long_about = None)]
+    Open {
+        /// Upload the copy again whenever it's saved, until Enter is pressed
+        #[arg(long)]
+        edit: bool,
 
-        /// Destination path
-        destination: String,
+        /// File path
+        path: FsPath,
     },
     #[command(about = "Delete files", long_about = None)]
     Rm {
@@ -43,27 +127,583 @@ pub enum Operation {
         #[arg(short, long)]
         recursive: bool,
 
+        /// Only delete directory entry and queue the data for 'gc run-queue'
+        #[arg(long, conflicts_with = "quick")]
+        defer: bool,
+
+        /// Delete this many subdirectories of a recursively deleted directory at once
+        #[arg(long, default_value_t = 1, requires = "recursive")]
+        parallel_dirs: usize,
+
         /// Path
-        path: String,
+        path: FsPath,
     },
     #[command(about = "Move files or directories", long_about = None)]
     Mv {
-        // Source path
-        source: String,
+        // Source paths
+        #[arg(required = true)]
+        sources: Vec<FsPath>,
         // Destination path (must not include file/directory name that is being moved)
-        destination: String,
+        destination: FsPath,
+
+        /// What to do when the destination already contains an entry with the same name
+        #[arg(long, value_enum, default_value_t = OnConflict::Fail)]
+        on_conflict: OnConflict,
     },
     #[command(about = "Rename files and directories", long_about = None)]
     Rename {
-        /// Old name (must include path)
-        old: String,
+        /// Old name (must include path), the directory whose entries are renamed with --pattern
+        old: FsPath,
 
         /// New name (must not include path)
-        new: String,
+        #[arg(required_unless_present = "pattern", conflicts_with = "pattern")]
+        new: Option<String>,
+
+        /// Rename every entry of the directory with a substitution like 's/\.jpeg$/.jpg/'
+        #[arg(long, value_parser = util::parse_substitution)]
+        pattern: Option<util::Substitution>,
+
+        /// Also rename entries of subdirectories
+        #[arg(short, long, requires = "pattern")]
+        recursive: bool,
+
+        /// Only show the renames that would happen
+        #[arg(long, requires = "pattern")]
+        dry_run: bool,
+    },
+    #[command(about = "Create links", long_about = None)]
+    Ln {
+        /// Create a symbolic link instead of a hard link
+        #[arg(short, long)]
+        symbolic: bool,
+
+        /// Path the link points to (only files for hard links, relative paths start at the symlink's directory)
+        target: String,
+
+        /// Path of the new link (including link name)
+        link: FsPath,
     },
     #[command(about = "Create directories", long_about = None)]
     Mkdir {
+        /// Create missing parent directories, no error if the directory exists
+        #[arg(short, long)]
+        parents: bool,
+
         /// Path
-        path: String,
+        path: FsPath,
+    },
+    #[command(about = "Pack a directory into a compressed archive file", long_about = None)]
+    Archive {
+        /// Source directory
+        source: FsPath,
+
+        /// Destination path (including file name)
+        destination: FsPath,
+    },
+    #[command(about = "Unpack an archive file into a directory", long_about = None)]
+    Unarchive {
+        /// Source archive file
+        source: FsPath,
+
+        /// Destination directory
+        destination: FsPath,
+    },
+    #[command(about = "Move often edited nodes to fresh messages and delete the old ones", long_about = None)]
+    Compact {
+        /// Only move nodes that were edited at least this long after they were created (e.g. '7d')
+        #[arg(long, default_value = "7d", value_parser = util::parse_duration)]
+        edited_after: Duration,
+
+        /// Only show the nodes that would be moved
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Directory or file to compact, with everything below it
+        #[arg(default_value = "/")]
+        path: FsPath,
+    },
+    #[command(about = "Copy a file, also between remotes configured with DFS_REMOTE_<NAME>", long_about = None)]
+    Cp {
+        /// Source file as [remote:]path
+        source: RemotePath,
+
+        /// Destination as [remote:]path (directories use the source's file name)
+        destination: RemotePath,
+    },
+    #[command(about = "Import a file from an HTTP(S) URL", long_about = None)]
+    Import {
+        /// Source URL
+        source: String,
+
+        /// Destination path (directories use the URL's file name)
+        destination: FsPath,
+    },
+    #[command(about = "Adopt the attachments posted in another channel", long_about = None)]
+    Adopt {
+        /// ID of the channel to scan
+        #[arg(long)]
+        channel: u64,
+
+        /// What to do with attachments whose name is already taken (skip continues an earlier adopt)
+        #[arg(long, value_enum, default_value_t = OnConflict::Skip)]
+        on_conflict: OnConflict,
+
+        /// Destination directory, files are sorted into '<date>/<author>/' below it
+        destination: FsPath,
+    },
+    #[command(about = "Upgrade the filesystem to a newer format version", long_about = None)]
+    Migrate {
+        /// Target format version (e.g. 'v1')
+        #[arg(long)]
+        to: String,
+    },
+    #[command(about = "Measure upload and download throughput", long_about = None)]
+    Bench {
+        /// Amount of synthetic data (e.g. '1GiB')
+        #[arg(long, default_value = "64MiB", value_parser = util::parse_size)]
+        size: u64,
+
+        /// Number of concurrent block transfers
+        #[arg(long, default_value_t = 1)]
+        jobs: usize,
+    },
+    #[command(about = "Compare a local directory with a remote directory", long_about = None)]
+    Check {
+        /// Also compare the content of files with the same size (downloads them)
+        #[arg(long)]
+        checksum_only: bool,
+
+        /// Don't report empty directories that only exist on one side
+        #[arg(long)]
+        skip_empty_dirs: bool,
+
+        /// Local directory
+        local: String,
+
+        /// Remote directory
+        remote: FsPath,
+    },
+    #[command(about = "Check the directory tree for duplicate entries and unreadable nodes", long_about = None)]
+    Fsck {
+        /// Rename duplicate entries to 'name (1)', 'name (2)', ... and save an outdated superblock again
+        #[arg(long)]
+        repair: bool,
+
+        /// Change nothing, write the planned renames and findings to a JSON lines file, file
+        /// nodes are read as well to find dangling entries and wrong link counts
+        #[arg(long, value_name = "FILE", conflicts_with = "repair")]
+        report_only: Option<String>,
+    },
+    #[command(about = "Run the actions of a report written with --report-only", long_about = None)]
+    Apply {
+        /// Report written by 'gc orphans' or 'fsck' with --report-only
+        #[arg(long)]
+        from_report: String,
+    },
+    #[command(about = "Link an existing node under a path after its directory entry got lost", long_about = None)]
+    RepairEntry {
+        /// Message ID of the node to link
+        #[arg(long)]
+        node_id: u64,
+
+        /// Path of the new entry (with a trailing '/' for directories)
+        path: FsPath,
+    },
+    #[command(about = "Delete empty directories", long_about = None)]
+    PruneEmptyDirs {
+        /// Also delete directories that only contain empty directories
+        #[arg(short, long)]
+        repeat: bool,
+
+        /// Only show the directories that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Directory to search (it's kept even if it's empty)
+        path: FsPath,
+    },
+    #[command(about = "Check that a rotating part of the files can still be read and decrypted", long_about = None)]
+    Scrub {
+        /// How much to read in this run, the next one continues after it (e.g. '10GiB')
+        #[arg(long, default_value = "1GiB", value_parser = util::parse_size)]
+        amount: u64,
+
+        /// Upload lost blocks again from the same file on this remote (see 'cp')
+        #[arg(long)]
+        mirror: Option<String>,
+    },
+    #[command(about = "Upload files again in blocks of a new size", long_about = None)]
+    Rewrite {
+        /// Size of the new blocks (e.g. '1MiB', at most the default of 8MiB)
+        #[arg(long, value_parser = util::parse_size)]
+        chunk_size: Option<u64>,
+
+        /// File or directory (files below are rewritten recursively)
+        path: FsPath,
+    },
+    #[command(about = "Recover file data from the channel history when the directory tree is broken", long_about = None)]
+    Salvage {
+        /// Local directory for the recovered files and fragments
+        #[arg(long)]
+        out: String,
+    },
+    #[command(about = "Summarize the messages in the data channel", long_about = None)]
+    ChannelStats,
+    #[command(about = "Estimate the cost of an operation without running it", long_about = None)]
+    Estimate {
+        #[command(subcommand)]
+        estimate: Estimate,
+    },
+    #[command(about = "Manage directory quotas", long_about = None)]
+    Quota {
+        #[command(subcommand)]
+        quota: Quota,
+    },
+    #[command(about = "Manage retention policies", long_about = None)]
+    Retention {
+        #[command(subcommand)]
+        retention: Retention,
+    },
+    #[command(about = "Collect garbage", long_about = None)]
+    Gc {
+        #[command(subcommand)]
+        gc: Gc,
+    },
+    #[command(about = "Append to and follow log files", long_about = None)]
+    Log {
+        #[command(subcommand)]
+        log: Log,
+    },
+    #[command(about = "Export and import recovery bundles of the encryption key", long_about = None)]
+    Key {
+        #[command(subcommand)]
+        key: Key,
+    },
+    #[command(about = "Show reports about the filesystem", long_about = None)]
+    Report {
+        #[command(subcommand)]
+        report: Report,
+    },
+    #[command(about = "Switch between case sensitive and insensitive name lookup", long_about = None)]
+    CaseInsensitive {
+        /// Whether names match regardless of their case ('on' or 'off')
+        #[arg(value_parser = ["on", "off"])]
+        mode: String,
+    },
+    #[command(about = "Choose how nodes are encoded, existing nodes change once they are written again", long_about = None)]
+    Codec {
+        /// Node codec ('le' is the plain format, 'zstd' compresses it)
+        #[arg(value_parser = ["le", "zstd"])]
+        name: String,
+    },
+    #[command(about = "Stripe new blocks over other channels with Reed-Solomon parity", long_about = None)]
+    Erasure {
+        /// Number of shards a block is split into
+        #[arg(long)]
+        data: usize,
+
+        /// Number of parity shards, blocks survive the loss of this many channels
+        #[arg(long)]
+        parity: usize,
+
+        /// Comma separated IDs of the channels holding the shards, one per shard
+        #[arg(long, value_delimiter = ',', required = true)]
+        channels: Vec<u64>,
+    },
+    #[command(about = "Pin a plaintext description of the format, so the data can be read without dfs", long_about = None)]
+    RecoveryNote,
+    #[command(about = "Check the bot token, data channel, superblock, clock, cache and encryption key", long_about = None)]
+    Doctor,
+    #[command(about = "Show filesystem information", long_about = None)]
+    Info {
+        /// Also show the local lifetime statistics (collected with DFS_STATS=on)
+        #[arg(long)]
+        stats: bool,
+    },
+    #[command(about = "Show the locally recorded history of operations (collected with DFS_STATS=on)", long_about = None)]
+    Activity {
+        /// Only show operations started within this time (e.g. '7d')
+        #[arg(long, value_parser = util::parse_duration)]
+        since: Option<Duration>,
+    },
+    #[command(about = "Low level node access for manual repairs", long_about = None, hide = true)]
+    Debug {
+        #[command(subcommand)]
+        debug: Debug,
+    },
+    #[command(about = "Show the metadata of a file, directory or symlink", long_about = None)]
+    Stat {
+        /// List every block of a file with its size, hash and message link
+        #[arg(long)]
+        blocks: bool,
+
+        /// Output format (json is implied by --json)
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+
+        /// Path (symlinks are not followed)
+        path: FsPath,
+    },
+    #[command(
+        about = "Check a path without output, exits with 0 if the check passes and 1 otherwise",
+        long_about = None,
+        group(ArgGroup::new("check").required(true).args(["exists", "directory", "file"]))
+    )]
+    Test {
+        /// Path exists
+        #[arg(short)]
+        exists: bool,
+
+        /// Path is a directory
+        #[arg(short)]
+        directory: bool,
+
+        /// Path is a file
+        #[arg(short)]
+        file: bool,
+
+        /// Path to check (symlinks are followed)
+        path: FsPath,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+// selects the entries of the listed directory, the directories below are listed in full
+#[derive(Clone, Args)]
+pub struct ListPage {
+    /// Skip this many (matching) entries
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+
+    /// Show at most this many (matching) entries
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Only show entries whose name matches a glob like '*.jpg' (directories without their '/')
+    #[arg(long, value_parser = util::parse_glob)]
+    pub filter: Option<Regex>,
+}
+
+impl ListPage {
+    pub fn is_all(&self) -> bool {
+        self.offset == 0 && self.limit.is_none() && self.filter.is_none()
+    }
+}
+
+#[derive(Clone, Args)]
+pub struct DownloadOptions {
+    /// Download the target if the source is a symlink
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// What to do when the destination file already exists
+    #[arg(long, value_enum, default_value_t = OnConflict::Fail)]
+    pub on_conflict: OnConflict,
+
+    /// Blocks to fetch ahead while the current one is written (8 MiB of memory each)
+    #[arg(long, default_value_t = 2)]
+    pub readahead: usize,
+
+    /// Fill blocks that still fail after retrying with zeros and report their byte ranges
+    #[arg(long)]
+    pub allow_holes: bool,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Estimate {
+    #[command(about = "Estimate uploading files (directories count as --tar uploads)", long_about = None)]
+    Upload {
+        /// Size of the uploaded blocks (e.g. '1MiB', at most the default of 8MiB)
+        #[arg(long, value_parser = util::parse_size)]
+        chunk_size: Option<u64>,
+
+        /// Source paths
+        #[arg(required = true)]
+        sources: Vec<String>,
+    },
+    #[command(about = "Estimate deleting a file or directory", long_about = None)]
+    Rm {
+        /// Path
+        path: FsPath,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Quota {
+    #[command(about = "Limit the size of a directory and its contents", long_about = None)]
+    Set {
+        /// Directory path
+        path: FsPath,
+
+        /// Maximum size (e.g. '10GiB')
+        #[arg(value_parser = util::parse_size)]
+        size: u64,
+    },
+    #[command(about = "Remove the quota of a directory", long_about = None)]
+    Rm {
+        /// Directory path
+        path: FsPath,
+    },
+    #[command(about = "Show the quotas that apply to a directory", long_about = None)]
+    Show {
+        /// Directory path
+        path: FsPath,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Retention {
+    #[command(about = "Set the retention policy of a directory", long_about = None)]
+    Set {
+        /// Keep files modified within this many days
+        #[arg(long)]
+        keep_days: Option<u64>,
+
+        /// Keep this many of the newest files
+        #[arg(long)]
+        keep_last: Option<usize>,
+
+        /// Directory path (only files directly inside are pruned)
+        path: FsPath,
+    },
+    #[command(about = "Remove the retention policy of a directory", long_about = None)]
+    Rm {
+        /// Directory path
+        path: FsPath,
+    },
+    #[command(about = "List all retention policies", long_about = None)]
+    Show,
+    #[command(about = "Delete files that are expired according to the policies", long_about = None)]
+    Apply {
+        /// Only show what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Gc {
+    #[command(about = "Delete the data of entries removed with 'rm --defer'", long_about = None)]
+    RunQueue,
+    #[command(about = "Delete the leftovers of interrupted uploads in /.dfs/tmp/", long_about = None)]
+    Staging {
+        /// Leave nodes edited more recently alone, they may belong to a running upload
+        #[arg(long, default_value = "1d", value_parser = util::parse_duration)]
+        min_age: Duration,
+
+        /// Only count the leftovers
+        #[arg(long)]
+        dry_run: bool,
+    },
+    #[command(about = "Find messages in the data channel that nothing refers to", long_about = None)]
+    Orphans {
+        /// Delete the orphaned messages instead of only counting them
+        #[arg(long)]
+        delete: bool,
+
+        /// Leave newer messages alone, they may belong to a running upload (e.g. '1h')
+        #[arg(long, default_value = "1d", value_parser = util::parse_duration)]
+        min_age: Duration,
+
+        /// Change nothing, write the orphans to delete to a JSON lines file
+        #[arg(long, value_name = "FILE", conflicts_with = "delete")]
+        report_only: Option<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Key {
+    #[command(
+        about = "Write the key and channel ID into a bundle encrypted with age or gpg",
+        long_about = None,
+        group(ArgGroup::new("recipient").required(true).args(["age_recipient", "gpg_recipient"]))
+    )]
+    Export {
+        /// age public key to encrypt the bundle to
+        #[arg(long)]
+        age_recipient: Option<String>,
+
+        /// gpg key ID or email to encrypt the bundle to
+        #[arg(long)]
+        gpg_recipient: Option<String>,
+
+        /// Path of the bundle file
+        #[arg(short, long)]
+        output: String,
+    },
+    #[command(about = "Restore the key and channel ID from a bundle into the .env file", long_about = None)]
+    Import {
+        /// age identity file, not needed for gpg bundles
+        #[arg(short, long)]
+        identity: Option<String>,
+
+        /// Path of the bundle file
+        bundle: String,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Debug {
+    #[command(about = "Print the decoded node stored in a message", long_about = None)]
+    DumpNode {
+        /// Also write the raw node bytes to this file
+        #[arg(long)]
+        raw: Option<String>,
+
+        /// Message ID of the node
+        block_id: u64,
+    },
+    #[command(about = "Overwrite a node with bytes from a file, nothing checks the result", long_about = None)]
+    PutNode {
+        /// Confirm overwriting the node
+        #[arg(long)]
+        force: bool,
+
+        /// Message ID of the node
+        block_id: u64,
+
+        /// File with the raw node bytes (e.g. from 'debug dump-node --raw')
+        source: String,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Log {
+    #[command(about = "Append standard input to a file, creating it if needed", long_about = None)]
+    Append {
+        /// Path of the log file
+        path: FsPath,
+    },
+    #[command(about = "Print the last lines of a file", long_about = None)]
+    Tail {
+        /// Number of lines to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+
+        /// Keep printing data as it's appended
+        #[arg(short, long)]
+        follow: bool,
+
+        /// How often to check for appended data with --follow (e.g. '5s')
+        #[arg(long, default_value = "5s", value_parser = util::parse_duration)]
+        interval: Duration,
+
+        /// Path of the log file
+        path: FsPath,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Report {
+    #[command(about = "Show usage growth per top level directory", long_about = None)]
+    Usage {
+        /// Time frame to compare against (e.g. '30d')
+        #[arg(long, default_value = "30d", value_parser = util::parse_duration)]
+        since: Duration,
     },
 }
@@ -1,7 +1,12 @@
+use std::borrow::Cow;
+
+use icu_normalizer::ComposingNormalizerBorrowed;
 use indicatif::HumanCount;
 
+use crate::parse_error::{self, ParseError};
+
 const NAME_LEN_SIZE: usize = std::mem::size_of::<NameLen>();
-const DIRECTORY_ENTRY_SIZE: usize = NAME_LEN + BLOCK_INDEX_SIZE + NAME_LEN_SIZE;
+pub const DIRECTORY_ENTRY_SIZE: usize = NAME_LEN + BLOCK_INDEX_SIZE + NAME_LEN_SIZE;
 
 pub const BLOCK_INDEX_SIZE: usize = std::mem::size_of::<BlockIndex>();
 pub const NAME_LEN: usize = (1 << 10) - BLOCK_INDEX_SIZE - NAME_LEN_SIZE;
@@ -9,6 +14,7 @@ pub const NAME_LEN: usize = (1 << 10) - BLOCK_INDEX_SIZE - NAME_LEN_SIZE;
 pub type BlockIndex = u64;
 type NameLen = u64;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct DirectoryEntry {
     // max (2^10 - 8 - 8 =) 1008 byte names
     name_len: u64,
@@ -19,11 +25,12 @@ pub struct DirectoryEntry {
 }
 
 impl DirectoryEntry {
+    // the name is stored in NFC, so differently composed input finds the same entry
     pub fn new<S: AsRef<str>>(name: S, block: BlockIndex) -> Self {
-        let name = name.as_ref();
+        let name = DirectoryEntry::valid_name(name.as_ref());
         DirectoryEntry {
             name_len: name.len() as u64,
-            name: name.to_string(),
+            name,
             block,
         }
     }
@@ -32,19 +39,57 @@ impl DirectoryEntry {
         self.block
     }
 
-    pub fn set_name<S: AsRef<str>>(&mut self, name: S) {
-        let name = name.as_ref().to_string();
-        assert!(
-            name.len() <= NAME_LEN,
-            "Name exceeds directory entry name size of {}: {}",
-            HumanCount(NAME_LEN as u64),
-            HumanCount(name.len() as u64)
-        );
+    pub fn set_block_id(&mut self, block: BlockIndex) {
+        self.block = block;
+    }
 
-        self.name = name;
+    pub fn set_name<S: AsRef<str>>(&mut self, name: S) {
+        self.name = DirectoryEntry::valid_name(name.as_ref());
         self.name_len = self.name.len() as u64;
     }
 
+    // every name of a new entry goes through here, entries read from a node are kept as they are
+    pub fn validate_name(name: &str) -> Result<String, String> {
+        let name = DirectoryEntry::normalize_name(name).into_owned();
+        let base = name.strip_suffix('/').unwrap_or(&name);
+
+        if base.is_empty() {
+            return Err(String::from("Names must not be empty"));
+        }
+        if base.contains('/') {
+            return Err(format!(
+                "Name '{name}' must not contain '/' (except at the end of directories)"
+            ));
+        }
+        if let Some(ch) = base.chars().find(|ch| ch.is_control()) {
+            return Err(format!(
+                "Name '{}' must not contain control characters ({ch:?})",
+                name.escape_debug()
+            ));
+        }
+        // they would be taken as the current or parent directory in paths
+        if base == "." || base == ".." {
+            return Err(format!("'{name}' is reserved"));
+        }
+        if name.len() > NAME_LEN {
+            return Err(format!(
+                "Name exceeds directory entry name size of {}: {}",
+                HumanCount(NAME_LEN as u64),
+                HumanCount(name.len() as u64)
+            ));
+        }
+
+        Ok(name)
+    }
+
+    pub fn normalize_name(name: &str) -> Cow<'_, str> {
+        ComposingNormalizerBorrowed::new_nfc().normalize(name)
+    }
+
+    fn valid_name(name: &str) -> String {
+        DirectoryEntry::validate_name(name).unwrap_or_else(|err| panic!("{err}"))
+    }
+
     pub fn get_name(&self) -> &String {
         &self.name
     }
@@ -70,48 +115,45 @@ impl DirectoryEntry {
         bytes
     }
 
-    pub fn from_le_bytes(bytes: &[u8]) -> Vec<Self> {
+    pub fn from_le_bytes(bytes: &[u8]) -> Result<Vec<Self>, ParseError> {
         let mut entries = Vec::new();
 
-        let mut bytes = bytes.iter();
-        while bytes.len() > 0 {
-            let mut name_len = [0; NAME_LEN_SIZE];
-            for name_len_byte in name_len.iter_mut().take(NAME_LEN_SIZE) {
-                *name_len_byte = *bytes
-                    .next()
-                    .expect("Malformed input doesn't contain full name size");
-            }
-
-            let name_len = u64::from_le_bytes(name_len);
-            assert!(
-                name_len <= NAME_LEN as u64,
-                "Name length exceeds maximum directory entry name length of {}: {}",
-                HumanCount(NAME_LEN as u64),
-                HumanCount(name_len)
-            );
-            let mut name = String::with_capacity(name_len as usize);
-            for _ in 0..name_len {
-                name.push(
-                    *bytes
-                        .next()
-                        .expect("Malformed input doesn't contain full name")
-                        as char,
-                );
-            }
-            assert!(
-                name_len == name.len() as u64,
-                "Corrupted directory entry has mismatched name length and stored name length: {} != {}",
-                HumanCount(name_len),
-                HumanCount(name.len() as u64)
-            );
-
-            let mut block = [0; BLOCK_INDEX_SIZE];
-            for block_byte in block.iter_mut().take(BLOCK_INDEX_SIZE) {
-                *block_byte = *bytes
-                    .next()
-                    .expect("Malformed input doesn't contain full block id");
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let name_len = parse_error::read_u64(bytes, offset, "name length")?;
+            if name_len > NAME_LEN as u64 {
+                return Err(ParseError::new(
+                    offset,
+                    format!(
+                        "Name length exceeds maximum directory entry name length of {}: {}",
+                        HumanCount(NAME_LEN as u64),
+                        HumanCount(name_len)
+                    ),
+                ));
             }
-            let block = u64::from_le_bytes(block);
+            offset += NAME_LEN_SIZE;
+
+            let name = bytes
+                .get(offset..offset + name_len as usize)
+                .ok_or_else(|| {
+                    ParseError::new(
+                        offset,
+                        format!(
+                            "Truncated name: expected {name_len} bytes, got {}",
+                            bytes.len() - offset
+                        ),
+                    )
+                })?;
+            let name = String::from_utf8(name.to_vec()).map_err(|err| {
+                ParseError::new(
+                    offset + err.utf8_error().valid_up_to(),
+                    "Name is not valid UTF-8",
+                )
+            })?;
+            offset += name_len as usize;
+
+            let block = parse_error::read_u64(bytes, offset, "block id")?;
+            offset += BLOCK_INDEX_SIZE;
 
             entries.push(DirectoryEntry {
                 name_len,
@@ -120,6 +162,87 @@ impl DirectoryEntry {
             });
         }
 
-        entries
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    pub fn name() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[^/\\pC]{1,64}".prop_filter("reserved name", |name| name != "." && name != ".."),
+            // maximum length names in ASCII and multi byte characters
+            "[a-z]{1008}",
+            "€{336}",
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn entries_round_trip(entries in prop::collection::vec((name(), any::<BlockIndex>()), 0..32)) {
+            let entries: Vec<DirectoryEntry> = entries
+                .into_iter()
+                .map(|(name, block)| DirectoryEntry::new(name, block))
+                .collect();
+            let bytes: Vec<u8> = entries.iter().flat_map(DirectoryEntry::to_le_bytes).collect();
+
+            prop_assert_eq!(DirectoryEntry::from_le_bytes(&bytes), Ok(entries));
+        }
+
+        #[test]
+        fn arbitrary_bytes_do_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+            let _ = DirectoryEntry::from_le_bytes(&bytes);
+        }
+    }
+
+    #[test]
+    fn rejects_unaddressable_names() {
+        for name in [
+            "",
+            "/",
+            "a/b",
+            "a//",
+            "/a",
+            "tab\there",
+            "new\nline/",
+            ".",
+            "../",
+        ] {
+            assert!(
+                DirectoryEntry::validate_name(name).is_err(),
+                "{name:?} was accepted"
+            );
+        }
+        assert!(DirectoryEntry::validate_name(&"a".repeat(NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn accepts_files_and_directories() {
+        for name in [
+            "a",
+            "dir/",
+            " spaced ",
+            "...",
+            ".hidden",
+            "übersicht #1.txt",
+        ] {
+            assert_eq!(DirectoryEntry::validate_name(name).as_deref(), Ok(name));
+        }
+        assert!(DirectoryEntry::validate_name(&"a".repeat(NAME_LEN)).is_ok());
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        // 'e' followed by a combining acute accent
+        let entry = DirectoryEntry::new("cafe\u{301}", 1);
+        assert_eq!(entry.get_name(), "caf\u{e9}");
+
+        // the length limit applies to the normalized name
+        let decomposed = "e\u{301}".repeat(NAME_LEN / 2);
+        assert!(DirectoryEntry::validate_name(&decomposed).is_ok());
     }
 }
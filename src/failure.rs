@@ -0,0 +1,84 @@
+use std::panic::PanicHookInfo;
+
+// the common failures a user can fix themselves, they are raised with 'panic_any' like every
+// other error and the handler installed by main prints the hint below the message
+//
+// the hints are kept together here, so they read the same everywhere and can be translated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hint {
+    WrongKey,
+    MissingChannel,
+    DirectoryFull,
+    NameExists,
+    FileTooBig,
+    Recursive,
+    Corrupted,
+}
+
+impl Hint {
+    pub fn text(self) -> &'static str {
+        match self {
+            Hint::WrongKey => {
+                "AES_KEY must be the 32 byte key the data was uploaded with, 'dfs key import' restores it from a recovery bundle"
+            }
+            Hint::MissingChannel => {
+                "check DATA_CHANNEL_ID and that the bot was invited to the server, 'dfs doctor' shows what's wrong"
+            }
+            Hint::DirectoryFull => "move some of its entries into a subdirectory with 'dfs mv'",
+            Hint::NameExists => {
+                "choose another name, remove the entry with 'dfs rm' or pass --on-conflict where the command has it"
+            }
+            Hint::FileTooBig => {
+                "a larger --chunk-size raises the limit where the command has it, otherwise split the file"
+            }
+            Hint::Recursive => "use --recursive to delete a directory with everything in it",
+            Hint::Corrupted => {
+                "run 'dfs fsck' to find the damage and 'dfs fsck --repair' to fix it"
+            }
+        }
+    }
+}
+
+pub struct Failure {
+    pub message: String,
+    pub hint: Hint,
+}
+
+pub fn fail<S: Into<String>>(hint: Hint, message: S) -> ! {
+    std::panic::panic_any(Failure {
+        message: message.into(),
+        hint,
+    })
+}
+
+// 'assert!' for failures with a hint
+pub fn ensure<S: Into<String>>(condition: bool, hint: Hint, message: S) {
+    if !condition {
+        fail(hint, message);
+    }
+}
+
+// prints every error the same way on stderr, the source location only helps with bugs so it's
+// shown for other panics and only with RUST_BACKTRACE set
+pub fn install_handler() {
+    std::panic::set_hook(Box::new(|info: &PanicHookInfo| {
+        let payload = info.payload();
+        if let Some(failure) = payload.downcast_ref::<Failure>() {
+            eprintln!("  Error: {}", failure.message);
+            eprintln!("  Hint: {}", failure.hint.text());
+            return;
+        }
+
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Unknown error");
+        eprintln!("  Error: {message}");
+        if std::env::var_os("RUST_BACKTRACE").is_some()
+            && let Some(location) = info.location()
+        {
+            eprintln!("  at {location}");
+        }
+    }));
+}
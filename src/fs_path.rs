@@ -0,0 +1,298 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    directory_entry::{DirectoryEntry, NAME_LEN},
+    parse_error::ParseError,
+};
+
+// absolute path inside the filesystem, a trailing '/' marks a directory
+//
+// a '\' escapes the following character, '\\' is a literal backslash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsPath {
+    // unescaped entry names, directories keep their trailing '/' like directory entries do
+    segments: Vec<String>,
+}
+
+impl FsPath {
+    pub fn root() -> Self {
+        FsPath {
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn parse(path: &str) -> Result<Self, ParseError> {
+        if !path.starts_with('/') {
+            return Err(ParseError::new(0, "Paths must start with a '/'"));
+        }
+
+        let mut segments = Vec::new();
+        let mut segment = String::new();
+        let mut segment_start = 1;
+        let mut chars = path.char_indices().skip(1);
+        while let Some((pos, ch)) = chars.next() {
+            match ch {
+                '/' => {
+                    if segment.is_empty() {
+                        return Err(ParseError::new(pos, "Consecutive '/' are not permitted"));
+                    }
+
+                    segment.push('/');
+                    FsPath::validate_length(&segment, segment_start)?;
+                    segments.push(std::mem::take(&mut segment));
+                    segment_start = pos + 1;
+                }
+                '\\' => match chars.next() {
+                    Some((pos, '/')) => {
+                        return Err(ParseError::new(pos, "Names must not contain '/'"));
+                    }
+                    Some((pos, ch)) if ch.is_control() => {
+                        return Err(ParseError::new(
+                            pos,
+                            format!("Names must not contain control characters ({ch:?})"),
+                        ));
+                    }
+                    Some((_, ch)) => segment.push(ch),
+                    None => {
+                        return Err(ParseError::new(pos, "Path ends with an unfinished escape"));
+                    }
+                },
+                ch if ch.is_control() => {
+                    return Err(ParseError::new(
+                        pos,
+                        format!("Names must not contain control characters ({ch:?})"),
+                    ));
+                }
+                ch => segment.push(ch),
+            }
+        }
+
+        if !segment.is_empty() {
+            FsPath::validate_length(&segment, segment_start)?;
+            segments.push(segment);
+        }
+
+        Ok(FsPath { segments })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.segments
+            .last()
+            .is_none_or(|segment| segment.ends_with('/'))
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    // directory entry name of the last segment, empty for the root
+    pub fn name(&self) -> &str {
+        self.segments.last().map_or("", String::as_str)
+    }
+
+    pub fn parent(&self) -> FsPath {
+        FsPath {
+            segments: self.segments[..self.segments.len().saturating_sub(1)].to_vec(),
+        }
+    }
+
+    pub fn join<S: AsRef<str>>(&self, name: S) -> FsPath {
+        assert!(self.is_dir(), "Cannot join a name to file path {self}");
+        assert!(
+            !name.as_ref().is_empty() && !name.as_ref().trim_end_matches('/').contains('/'),
+            "Invalid name '{}'",
+            name.as_ref()
+        );
+
+        let mut segments = self.segments.clone();
+        segments.push(name.as_ref().to_string());
+        FsPath { segments }
+    }
+
+    // entry names are stored in NFC, which is what has to fit
+    fn validate_length(segment: &str, pos: usize) -> Result<(), ParseError> {
+        let len = DirectoryEntry::normalize_name(segment).len();
+        if len > NAME_LEN {
+            return Err(ParseError::new(
+                pos,
+                format!("Name exceeds the maximum length of {NAME_LEN} bytes: {len}"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for FsPath {
+    type Err = ParseError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        FsPath::parse(path)
+    }
+}
+
+impl Display for FsPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/")?;
+        for segment in &self.segments {
+            write!(f, "{}", segment.replace('\\', "\\\\"))?;
+        }
+
+        Ok(())
+    }
+}
+
+// '[<remote>:]<path>', remotes are other filesystems configured with 'DFS_REMOTE_<NAME>'
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemotePath {
+    pub remote: Option<String>,
+    pub path: FsPath,
+}
+
+impl FromStr for RemotePath {
+    type Err = ParseError;
+
+    // paths start with '/', so a ':' before the first '/' ends the remote name
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        match path.split_once(':') {
+            Some((remote, rest)) if !remote.is_empty() && !remote.contains('/') => {
+                let path = FsPath::parse(rest)
+                    .map_err(|err| ParseError::new(remote.len() + 1 + err.offset, err.message))?;
+                Ok(RemotePath {
+                    remote: Some(remote.to_string()),
+                    path,
+                })
+            }
+            _ => Ok(RemotePath {
+                remote: None,
+                path: FsPath::parse(path)?,
+            }),
+        }
+    }
+}
+
+impl Display for RemotePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(remote) = &self.remote {
+            write!(f, "{remote}:")?;
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(path: &str) -> ParseError {
+        FsPath::parse(path).unwrap_err()
+    }
+
+    #[test]
+    fn parses_root() {
+        let path = FsPath::parse("/").unwrap();
+        assert!(path.is_root());
+        assert!(path.is_dir());
+        assert_eq!(path.name(), "");
+        assert_eq!(path, FsPath::root());
+        assert_eq!(path.to_string(), "/");
+    }
+
+    #[test]
+    fn trailing_slash_marks_directories() {
+        let dir = FsPath::parse("/photos/2024/").unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(dir.segments(), ["photos/", "2024/"]);
+        assert_eq!(dir.name(), "2024/");
+
+        let file = FsPath::parse("/photos/2024").unwrap();
+        assert!(!file.is_dir());
+        assert_eq!(file.segments(), ["photos/", "2024"]);
+        assert_eq!(file.name(), "2024");
+    }
+
+    #[test]
+    fn keeps_spaces_and_unicode() {
+        let path = FsPath::parse("/my files/ übersicht #1.txt ").unwrap();
+        assert_eq!(path.segments(), ["my files/", " übersicht #1.txt "]);
+        assert_eq!(path.to_string(), "/my files/ übersicht #1.txt ");
+    }
+
+    #[test]
+    fn unescapes_backslashes() {
+        let path = FsPath::parse("/a\\\\b/c\\ d").unwrap();
+        assert_eq!(path.segments(), ["a\\b/", "c d"]);
+        assert_eq!(path.to_string(), "/a\\\\b/c d");
+        assert_eq!(FsPath::parse(&path.to_string()).unwrap(), path);
+    }
+
+    #[test]
+    fn parent_and_join() {
+        let path = FsPath::parse("/a/b/c.txt").unwrap();
+        assert_eq!(path.parent(), FsPath::parse("/a/b/").unwrap());
+        assert_eq!(path.parent().join("c.txt"), path);
+        assert_eq!(FsPath::root().parent(), FsPath::root());
+    }
+
+    #[test]
+    fn rejects_relative_paths() {
+        assert_eq!(parse_err("a/b").offset, 0);
+        assert_eq!(parse_err("").offset, 0);
+    }
+
+    #[test]
+    fn rejects_consecutive_slashes() {
+        let err = parse_err("/a//b");
+        assert_eq!(err.offset, 3);
+        assert!(err.message.contains("Consecutive"));
+    }
+
+    #[test]
+    fn rejects_control_characters() {
+        let err = parse_err("/a/b\nc");
+        assert_eq!(err.offset, 4);
+        assert!(err.message.contains("control"));
+
+        assert!(parse_err("/a\\\u{0}").message.contains("control"));
+    }
+
+    #[test]
+    fn rejects_escaped_slashes() {
+        assert!(parse_err("/a\\/b").message.contains("'/'"));
+    }
+
+    #[test]
+    fn rejects_unfinished_escapes() {
+        let err = parse_err("/a\\");
+        assert_eq!(err.offset, 2);
+        assert!(err.message.contains("escape"));
+    }
+
+    #[test]
+    fn rejects_long_names() {
+        let name = "a".repeat(NAME_LEN + 1);
+        let err = parse_err(&format!("/dir/{name}"));
+        assert_eq!(err.offset, 5);
+        assert!(err.message.contains("maximum length"));
+
+        assert!(FsPath::parse(&format!("/dir/{}", "a".repeat(NAME_LEN))).is_ok());
+    }
+
+    #[test]
+    fn parses_remote_paths() {
+        let path: RemotePath = "backup:/photos/a.jpg".parse().unwrap();
+        assert_eq!(path.remote.as_deref(), Some("backup"));
+        assert_eq!(path.path, FsPath::parse("/photos/a.jpg").unwrap());
+        assert_eq!(path.to_string(), "backup:/photos/a.jpg");
+
+        let path: RemotePath = "/a:b".parse().unwrap();
+        assert_eq!(path.remote, None);
+        assert_eq!(path.path.name(), "a:b");
+
+        assert_eq!("backup:a".parse::<RemotePath>().unwrap_err().offset, 7);
+    }
+}
@@ -3,26 +3,55 @@
 
 mod command;
 mod directory_entry;
+mod failure;
+mod fs_path;
 mod node;
+mod node_codec;
 mod node_kind;
 mod nodefs;
 mod nonce_counter;
+mod on_conflict;
+mod parse_error;
+mod reed_solomon;
+mod sync_io;
 mod util;
 
-use clap::Parser;
-use command::{Command, Operation};
+use clap::{CommandFactory, FromArgMatches};
+use command::{
+    Command, Debug, Estimate, Gc, Key, Log, Operation, OutputFormat, Quota, Report, Retention,
+};
+use failure::Hint;
+use node_kind::NodeKind;
 use nodefs::NodeFS;
+use on_conflict::OnConflict;
 use serenity::prelude::*;
 
+// exit code of bulk operations that completed but skipped entries, panics exit with 101
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 3;
+
 #[tokio::main]
 async fn main() {
-    dotenvy::dotenv().expect("Expected .env file with BOT_TOKEN and DATA_CHANNEL_ID");
+    let matches = Command::command().get_matches();
+    let operation = matches.subcommand_name().unwrap_or_default().to_string();
+    let command = Command::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let verbose = command.verbose;
+    failure::install_handler();
 
-    let command = Command::parse();
+    // restores the .env file, so it runs before it's loaded
+    if let Operation::Key {
+        key: Key::Import { identity, bundle },
+    } = command.operation
+    {
+        NodeFS::key_import(bundle, identity).await;
+        return;
+    }
+
+    dotenvy::dotenv().expect("Expected .env file with BOT_TOKEN and DATA_CHANNEL_ID");
 
     let token = std::env::var("BOT_TOKEN")
         .expect("Requires Discord bot token in environment variable 'BOT_TOKEN'");
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    // only the REST API is used, the gateway is never connected so no intents are needed
+    let intents = GatewayIntents::empty();
     let channel: u64 = std::env::var("DATA_CHANNEL_ID")
         .expect("Requires data channel ID in environment variable 'DATA_CHANNEL_ID'")
         .parse()
@@ -35,29 +64,337 @@ async fn main() {
     let key = std::env::var("AES_KEY")
         .expect("Requires AES encryption key in environment variable 'AES_KEY'");
 
-    let mut nodefs = NodeFS::new(channel, client);
+    let mut nodefs = NodeFS::new(channel, command.fs.clone(), client, command.json);
+    nodefs.track_operation(&operation);
+    // diagnoses the problems that would make setup fail, so it runs before it
+    if let Operation::Doctor = command.operation {
+        let healthy = nodefs.doctor(key).await;
+        nodefs.finish_operation(!healthy);
+        if !healthy {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    failure::ensure(
+        key.len() >= 32,
+        Hint::WrongKey,
+        format!("AES_KEY has {} bytes, it needs 32", key.len()),
+    );
+    nodefs.preflight(&operation).await;
+
+    // reads the channel history on its own, setup would fail on or replace the broken superblock
+    // it's meant to recover from
+    if let Operation::Salvage { out } = &command.operation {
+        nodefs.salvage(out.clone(), key).await;
+        let partial = nodefs.report_failures();
+        nodefs.finish_operation(partial);
+        if partial {
+            std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
+        }
+        return;
+    }
+
     nodefs.setup().await;
 
     match command.operation {
-        Operation::Ls { path } => nodefs.ls(path).await,
+        Operation::Ls { du, page, path } => nodefs.ls(path, du, page).await,
         Operation::Upload {
-            source,
+            tar: _,
+            mirror_structure: _,
+            sources,
             destination,
-        } => nodefs.upload(source, destination, key).await,
+            on_conflict: _,
+            chunk_size: _,
+            spool: Some(spool),
+        } => {
+            let [source] = <[String; 1]>::try_from(sources)
+                .expect("Only a single file can be uploaded through a spool");
+            nodefs.upload_spooled(source, destination, spool, key).await
+        }
+        Operation::Upload {
+            tar: true,
+            mirror_structure,
+            sources,
+            destination,
+            on_conflict,
+            chunk_size,
+            spool: None,
+        } => {
+            assert!(
+                on_conflict == OnConflict::Fail,
+                "Conflict policies are not supported for archives"
+            );
+            let [source] = <[String; 1]>::try_from(sources)
+                .expect("Only a single directory can be uploaded as an archive");
+            nodefs
+                .upload_tar(source, destination, mirror_structure, chunk_size, key)
+                .await
+        }
+        Operation::Upload {
+            tar: false,
+            mirror_structure: _,
+            sources,
+            destination,
+            on_conflict,
+            chunk_size,
+            spool: None,
+        } => {
+            nodefs
+                .upload(sources, destination, on_conflict, chunk_size, key)
+                .await
+        }
+        Operation::Push { spool } => nodefs.push(spool).await,
         Operation::Download {
+            discard: true,
             source,
-            destination,
-        } => nodefs.download(source, destination, key).await,
+            options,
+            ..
+        } => nodefs.download_discard(source, options, key).await,
+        Operation::Download {
+            as_tar: true,
+            source,
+            destination: Some(destination),
+            ..
+        } => nodefs.download_archive(source, destination, key).await,
+        Operation::Download {
+            untar,
+            mirror_structure,
+            as_tar: false,
+            source,
+            destination: Some(destination),
+            options,
+            ..
+        } => {
+            nodefs
+                .download(source, destination, untar, mirror_structure, options, key)
+                .await
+        }
+        Operation::Download {
+            destination: None, ..
+        } => unreachable!("A destination is required unless the data is discarded"),
+        Operation::Open { edit, path } => nodefs.open(path, edit, key).await,
         Operation::Rm {
             path,
             quick,
             recursive,
-        } => nodefs.rm(path, quick, recursive).await,
+            defer,
+            parallel_dirs,
+        } => {
+            nodefs
+                .rm(path, quick, recursive, defer, parallel_dirs, key)
+                .await
+        }
         Operation::Mv {
+            sources,
+            destination,
+            on_conflict,
+        } => nodefs.mv(sources, destination, on_conflict).await,
+        Operation::Rename {
+            old,
+            new,
+            pattern,
+            recursive,
+            dry_run,
+        } => match pattern {
+            Some(pattern) => {
+                nodefs
+                    .rename_pattern(old, pattern, recursive, dry_run)
+                    .await
+            }
+            None => nodefs.rename(old, new.unwrap()).await,
+        },
+        Operation::Ln {
+            symbolic,
+            target,
+            link,
+        } => nodefs.ln(target, link, symbolic).await,
+        Operation::Mkdir { parents, path } => nodefs.mkdir(path, parents).await,
+        Operation::Archive {
+            source,
+            destination,
+        } => nodefs.archive(source, destination, key).await,
+        Operation::Unarchive {
+            source,
+            destination,
+        } => nodefs.unarchive(source, destination, key).await,
+        Operation::Scrub { amount, mirror } => nodefs.scrub(amount, mirror, key).await,
+        Operation::Compact {
+            edited_after,
+            dry_run,
+            path,
+        } => nodefs.compact(path, edited_after, dry_run, key).await,
+        Operation::Cp {
             source,
             destination,
-        } => nodefs.mv(source, destination).await,
-        Operation::Rename { old, new } => nodefs.rename(old, new).await,
-        Operation::Mkdir { path } => nodefs.mkdir(path).await,
+        } => nodefs.cp(source, destination, key).await,
+        Operation::Import {
+            source,
+            destination,
+        } => nodefs.import(source, destination, key).await,
+        Operation::Adopt {
+            channel,
+            on_conflict,
+            destination,
+        } => nodefs.adopt(channel, destination, on_conflict, key).await,
+        Operation::Migrate { to } => nodefs.migrate(to, key).await,
+        Operation::Bench { size, jobs } => nodefs.bench(size, jobs, key).await,
+        Operation::Activity { since } => nodefs.activity(since),
+        Operation::Check {
+            checksum_only,
+            skip_empty_dirs,
+            local,
+            remote,
+        } => {
+            nodefs
+                .check(local, remote, checksum_only, skip_empty_dirs, key)
+                .await
+        }
+        Operation::Fsck {
+            repair,
+            report_only,
+        } => nodefs.fsck(repair, report_only).await,
+        Operation::Apply { from_report } => nodefs.apply_report(from_report).await,
+        Operation::Rewrite { chunk_size, path } => nodefs.rewrite(path, chunk_size, key).await,
+        Operation::PruneEmptyDirs {
+            repeat,
+            dry_run,
+            path,
+        } => nodefs.prune_empty_dirs(path, repeat, dry_run).await,
+        Operation::RepairEntry { node_id, path } => nodefs.repair_entry(path, node_id).await,
+        Operation::ChannelStats => nodefs.channel_stats(key).await,
+        Operation::Salvage { .. } => unreachable!("Salvage runs before the filesystem is set up"),
+        Operation::Estimate { estimate } => match estimate {
+            Estimate::Upload {
+                chunk_size,
+                sources,
+            } => nodefs.estimate_upload(sources, chunk_size).await,
+            Estimate::Rm { path } => nodefs.estimate_rm(path).await,
+        },
+        Operation::Quota { quota } => match quota {
+            Quota::Set { path, size } => nodefs.quota_set(path, size).await,
+            Quota::Rm { path } => nodefs.quota_rm(path).await,
+            Quota::Show { path } => nodefs.quota_show(path).await,
+        },
+        Operation::Retention { retention } => match retention {
+            Retention::Set {
+                keep_days,
+                keep_last,
+                path,
+            } => nodefs.retention_set(path, keep_days, keep_last, key).await,
+            Retention::Rm { path } => nodefs.retention_rm(path, key).await,
+            Retention::Show => nodefs.retention_show(key).await,
+            Retention::Apply { dry_run } => nodefs.retention_apply(dry_run, key).await,
+        },
+        Operation::Gc { gc: Gc::RunQueue } => nodefs.gc_run_queue(key).await,
+        Operation::Gc {
+            gc: Gc::Staging { min_age, dry_run },
+        } => nodefs.gc_staging(min_age, dry_run).await,
+        Operation::Gc {
+            gc:
+                Gc::Orphans {
+                    delete,
+                    min_age,
+                    report_only,
+                },
+        } => nodefs.gc_orphans(delete, min_age, report_only, key).await,
+        Operation::Key {
+            key:
+                Key::Export {
+                    age_recipient,
+                    gpg_recipient,
+                    output,
+                },
+        } => {
+            nodefs
+                .key_export(age_recipient, gpg_recipient, output, key)
+                .await
+        }
+        Operation::Key {
+            key: Key::Import { .. },
+        } => unreachable!("Key import runs before the filesystem is set up"),
+        Operation::Log {
+            log: Log::Append { path },
+        } => nodefs.log_append(path, key).await,
+        Operation::Log {
+            log:
+                Log::Tail {
+                    lines,
+                    follow,
+                    interval,
+                    path,
+                },
+        } => nodefs.log_tail(path, lines, follow, interval, key).await,
+        Operation::Report {
+            report: Report::Usage { since },
+        } => nodefs.report_usage(since, key).await,
+        Operation::CaseInsensitive { mode } => nodefs.case_insensitive(mode == "on").await,
+        Operation::Codec { name } => nodefs.set_codec(name).await,
+        Operation::Erasure {
+            data,
+            parity,
+            channels,
+        } => nodefs.set_erasure(data, parity, channels).await,
+        Operation::RecoveryNote => nodefs.recovery_note().await,
+        Operation::Doctor => unreachable!("Doctor runs before the filesystem is set up"),
+        Operation::Info { stats } => nodefs.info(stats).await,
+        Operation::Debug {
+            debug: Debug::DumpNode { raw, block_id },
+        } => nodefs.debug_dump_node(block_id, raw).await,
+        Operation::Debug {
+            debug:
+                Debug::PutNode {
+                    force,
+                    block_id,
+                    source,
+                },
+        } => nodefs.debug_put_node(block_id, source, force).await,
+        Operation::Stat {
+            blocks,
+            output,
+            path,
+        } => {
+            nodefs
+                .stat(path, blocks, command.json || output == OutputFormat::Json)
+                .await
+        }
+        Operation::Test {
+            exists: _,
+            directory,
+            file,
+            path,
+        } => {
+            let kind = if directory {
+                Some(NodeKind::Directory)
+            } else if file {
+                Some(NodeKind::File)
+            } else {
+                None
+            };
+            std::process::exit(if nodefs.exists(path, kind).await {
+                0
+            } else {
+                1
+            });
+        }
     };
+
+    if verbose {
+        let counts: Vec<String> = util::api_call_counts()
+            .into_iter()
+            .map(|(call, count)| format!("{count} {call}"))
+            .collect();
+        println!(
+            "  Made {} Discord API calls ({})",
+            util::rest_api_calls(),
+            counts.join(", ")
+        );
+    }
+
+    // some entries were skipped, the rest of the operation went through
+    let partial = nodefs.report_failures();
+    nodefs.finish_operation(partial);
+    if partial {
+        std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
+    }
 }
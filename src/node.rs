@@ -1,50 +1,126 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use indicatif::{HumanBytes, HumanCount};
 
 use crate::{
-    directory_entry::{BLOCK_INDEX_SIZE, BlockIndex, DirectoryEntry, NAME_LEN},
-    node_kind::NodeKind::{self, Directory, File},
+    directory_entry::{BLOCK_INDEX_SIZE, BlockIndex, DIRECTORY_ENTRY_SIZE, DirectoryEntry},
+    node_kind::NodeKind::{self, Directory, File, Symlink},
+    parse_error::{self, ParseError},
 };
 
 const SIZE_SIZE: usize = std::mem::size_of::<Size>();
 const KIND_SIZE: usize = std::mem::size_of::<NodeKind>();
+const TIMESTAMP_SIZE: usize = std::mem::size_of::<Timestamp>();
+const LINKS_SIZE: usize = std::mem::size_of::<LinkCount>();
+const HEADER_SIZE: usize =
+    KIND_SIZE + SIZE_SIZE + BLOCK_INDEX_SIZE + 2 * TIMESTAMP_SIZE + LINKS_SIZE + 2 * SIZE_SIZE;
 
-const BLOCK_COUNT: usize =
-    (BLOCK_SIZE - KIND_SIZE - SIZE_SIZE - BLOCK_INDEX_SIZE) / BLOCK_INDEX_SIZE;
+const BLOCK_COUNT: usize = (BLOCK_SIZE - HEADER_SIZE) / BLOCK_INDEX_SIZE;
 
 pub const MAX_FILE_SIZE: usize = BLOCK_SIZE * BLOCK_COUNT;
-pub const ENTRY_COUNT: usize =
-    (BLOCK_SIZE - KIND_SIZE - SIZE_SIZE - BLOCK_INDEX_SIZE) / (NAME_LEN + BLOCK_INDEX_SIZE);
+pub const ENTRY_COUNT: usize = (BLOCK_SIZE - HEADER_SIZE) / DIRECTORY_ENTRY_SIZE;
 pub const BLOCK_SIZE: usize = 1 << 23;
+pub const MAX_TARGET_LEN: usize = 4096;
+
+// from v4 on, node data larger than a segment is split across attachments of the node
+// message, so appending blocks only re-uploads the last segments (at most 8 of 10 attachments)
+pub const NODE_SEGMENT_SIZE: usize = 1 << 20;
+
+// v0: kind, size, parent
+// v1: kind, size, parent, created, modified
+// v2: kind, size, parent, created, modified, links
+// v3: kind, size, parent, created, modified, links, quota, used
+// v4: same fields as v3, the node may be split across several attachments
+// v5: same fields as v4, blocks may be holes
+// v6: same fields as v5, files keep their chunk size where directories keep the quota
+pub const FORMAT_VERSION: Version = 6;
+
+// smallest chunk size a file can be uploaded with
+pub const MIN_CHUNK_SIZE: usize = 1 << 12;
+
+// files with smaller chunks can only be smaller as well
+pub fn max_file_size(chunk_size: Size) -> Size {
+    chunk_size * BLOCK_COUNT as Size
+}
+
+// blocks of only zeros are not uploaded, message IDs are never 0
+pub const HOLE: BlockIndex = 0;
 
 pub type Size = u64;
+pub type Version = u32;
+// seconds since the unix epoch
+pub type Timestamp = u64;
+pub type LinkCount = u64;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Node {
-    // if it's a file or directory
+    // if it's a file, directory or symlink
     pub kind: NodeKind,
 
-    // if file, file size in bytes, if directory, directory entry count
+    // on-disk format version, stored in the upper half of the kind
+    pub version: Version,
+
+    // if file, file size in bytes, if directory, directory entry count, if symlink, target length
     size: Size,
 
     // parent directory, if 0 => root node
     pub parent_block_id: BlockIndex,
 
+    // only stored from v1 onwards
+    pub created: Timestamp,
+    pub modified: Timestamp,
+
+    // directory entries referencing the node, only stored from v2 onwards
+    pub links: LinkCount,
+
+    // directory quota in bytes (0 => no quota) and bytes used below a directory with a quota,
+    // only stored from v3 onwards
+    pub quota: Size,
+    pub used: Size,
+
+    // size of every block but the last, only stored for files from v6 onwards
+    pub chunk_size: Size,
+
     // single level block indices
     // => a file can be 8796067856384B ≈ 8.8TB in size
     blocks: Vec<BlockIndex>,
     entries: Vec<DirectoryEntry>,
+
+    // path the symlink points to, relative paths start at the symlink's directory
+    target: String,
+
+    // lookup mode of the filesystem, not stored in the node
+    pub case_insensitive: bool,
 }
 
 impl Node {
-    pub fn new(kind: NodeKind, parent_block_id: BlockIndex) -> Self {
+    pub fn new(kind: NodeKind, version: Version, parent_block_id: BlockIndex) -> Self {
+        let now = Node::now();
         Node {
             kind,
+            version,
             size: 0,
             parent_block_id,
+            created: now,
+            modified: now,
+            links: 1,
+            quota: 0,
+            used: 0,
+            chunk_size: BLOCK_SIZE as Size,
             blocks: Vec::new(),
             entries: Vec::new(),
+            target: String::new(),
+            case_insensitive: false,
         }
     }
 
+    pub fn now() -> Timestamp {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the unix epoch")
+            .as_secs()
+    }
+
     pub fn entries(&self) -> &Vec<DirectoryEntry> {
         assert!(self.kind == Directory, "Node is not a directory");
 
@@ -54,9 +130,9 @@ impl Node {
     pub fn contains_entry<S: AsRef<str>>(&self, entry_name: S) -> bool {
         assert!(self.kind == Directory, "Node is not a directory");
 
-        self.entries
-            .iter()
-            .any(|entry| entry.get_name() == entry_name.as_ref())
+        self.entries.iter().any(|entry| {
+            Node::name_matches(self.case_insensitive, entry.get_name(), entry_name.as_ref())
+        })
     }
 
     pub fn blocks(&self) -> &Vec<BlockIndex> {
@@ -65,6 +141,28 @@ impl Node {
         &self.blocks
     }
 
+    pub fn target(&self) -> &str {
+        assert!(self.kind == Symlink, "Node is not a symlink");
+
+        &self.target
+    }
+
+    pub fn set_target<S: AsRef<str>>(&mut self, target: S) {
+        assert!(self.kind == Symlink, "Node is not a symlink");
+        assert!(
+            !target.as_ref().is_empty(),
+            "Symlink target must not be empty"
+        );
+        assert!(
+            target.as_ref().len() <= MAX_TARGET_LEN,
+            "Symlink target exceeds the maximum length of {MAX_TARGET_LEN}"
+        );
+
+        self.target = target.as_ref().to_string();
+        self.size = self.target.len() as Size;
+        self.modified = Node::now();
+    }
+
     pub fn size(&self) -> Size {
         self.size
     }
@@ -72,7 +170,7 @@ impl Node {
     pub fn is_full(&self) -> bool {
         assert!(self.kind == Directory, "Node is not a directory");
 
-        self.size == ENTRY_COUNT as u64
+        self.size >= ENTRY_COUNT as u64
     }
 
     pub fn push_data_block(&mut self, block: BlockIndex, size: Size) {
@@ -91,6 +189,36 @@ impl Node {
 
         self.blocks.push(block);
         self.size += size;
+        self.modified = Node::now();
+    }
+
+    // swaps the message of a block whose data was uploaded again, returns the old one
+    pub fn replace_data_block(&mut self, idx: usize, block: BlockIndex) -> BlockIndex {
+        assert!(self.kind == File, "Node is not a file");
+
+        std::mem::replace(&mut self.blocks[idx], block)
+    }
+
+    // size of the nth block, only the last one can be partial
+    pub fn block_size(&self, idx: usize) -> Size {
+        assert!(self.kind == File, "Node is not a file");
+
+        self.size
+            .saturating_sub(idx as Size * self.chunk_size)
+            .min(self.chunk_size)
+    }
+
+    // drops every block after the first `count`, which are expected to be full
+    pub fn truncate_blocks(&mut self, count: usize) -> Vec<BlockIndex> {
+        assert!(self.kind == File, "Node is not a file");
+        assert!(
+            count as Size * self.chunk_size <= self.size,
+            "Kept blocks must be full"
+        );
+
+        self.size = count as Size * self.chunk_size;
+        self.modified = Node::now();
+        self.blocks.split_off(count)
     }
 
     pub fn push_directory_entry<S: AsRef<str>>(&mut self, name: S, block: BlockIndex) {
@@ -101,39 +229,124 @@ impl Node {
             HumanCount(ENTRY_COUNT as u64)
         );
 
-        self.entries.push(DirectoryEntry::new(name, block));
+        // with two entries of the same name only the first one could be reached, new names are
+        // in NFC already so only case insensitive lookups need the full comparison
+        let entry = DirectoryEntry::new(name, block);
+        let exists = if self.case_insensitive {
+            self.contains_entry(entry.get_name())
+        } else {
+            self.entries
+                .iter()
+                .any(|existing| existing.get_name() == entry.get_name())
+        };
+        assert!(
+            !exists,
+            "Directory already contains an entry named {}",
+            entry.get_name()
+        );
+
+        self.entries.push(entry);
         self.size += 1;
+        self.modified = Node::now();
     }
 
     pub fn rename_directory_entry<S1: AsRef<str>, S2: AsRef<str>>(&mut self, old: S1, new: S2) {
         assert!(self.kind == Directory, "Node is not a directory");
 
+        let case_insensitive = self.case_insensitive;
         self.entries
             .iter_mut()
-            .find(|entry| entry.get_name() == old.as_ref())
+            .find(|entry| Node::name_matches(case_insensitive, entry.get_name(), old.as_ref()))
             .expect("Directory entry doesn't exist")
             .set_name(new);
+        self.modified = Node::now();
     }
 
-    pub fn get_directory_entry<S: AsRef<str>>(&mut self, name: S) -> &DirectoryEntry {
+    // renames by position, so entries can swap names
+    pub fn rename_directory_entry_at<S: AsRef<str>>(&mut self, pos: usize, new: S) {
         assert!(self.kind == Directory, "Node is not a directory");
 
         self.entries
-            .iter()
-            .find(|entry| entry.get_name() == name.as_ref())
+            .get_mut(pos)
             .expect("Directory entry doesn't exist")
+            .set_name(new);
+        self.modified = Node::now();
+    }
+
+    pub fn get_directory_entry<S: AsRef<str>>(&mut self, name: S) -> &DirectoryEntry {
+        self.find_directory_entry(name)
+            .expect("Directory entry doesn't exist")
+    }
+
+    pub fn find_directory_entry<S: AsRef<str>>(&self, name: S) -> Option<&DirectoryEntry> {
+        assert!(self.kind == Directory, "Node is not a directory");
+
+        self.entries.iter().find(|entry| {
+            Node::name_matches(self.case_insensitive, entry.get_name(), name.as_ref())
+        })
+    }
+
+    // positions of entries whose name matches an earlier entry, they can't be looked up by name
+    pub fn duplicate_entries(&self) -> Vec<usize> {
+        assert!(self.kind == Directory, "Node is not a directory");
+
+        (0..self.entries.len())
+            .filter(|pos| {
+                self.entries[..*pos].iter().any(|entry| {
+                    Node::name_matches(
+                        self.case_insensitive,
+                        entry.get_name(),
+                        self.entries[*pos].get_name(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    // names keep their case, in case insensitive mode they just match regardless of it
+    fn name_matches(case_insensitive: bool, entry_name: &str, name: &str) -> bool {
+        // ASCII is always in NFC, most names don't need to be normalized
+        if entry_name.is_ascii() && name.is_ascii() {
+            return if case_insensitive {
+                entry_name.eq_ignore_ascii_case(name)
+            } else {
+                entry_name == name
+            };
+        }
+
+        let entry_name = DirectoryEntry::normalize_name(entry_name);
+        let name = DirectoryEntry::normalize_name(name);
+        if case_insensitive {
+            entry_name.to_lowercase() == name.to_lowercase()
+        } else {
+            entry_name == name
+        }
+    }
+
+    // for nodes that moved to another message, the directory itself doesn't change
+    pub fn relink_directory_entries(&mut self, old: BlockIndex, new: BlockIndex) {
+        assert!(self.kind == Directory, "Node is not a directory");
+
+        self.entries
+            .iter_mut()
+            .filter(|entry| entry.block_id() == old)
+            .for_each(|entry| entry.set_block_id(new));
     }
 
     pub fn delete_directory_entry<S: AsRef<str>>(&mut self, name: S) {
         assert!(self.kind == Directory, "Node is not a directory");
 
+        let case_insensitive = self.case_insensitive;
         self.entries.remove(
             self.entries
                 .iter()
-                .position(|entry| entry.get_name() == name.as_ref())
+                .position(|entry| {
+                    Node::name_matches(case_insensitive, entry.get_name(), name.as_ref())
+                })
                 .expect("Directory entry doesn't exist"),
         );
         self.size -= 1;
+        self.modified = Node::now();
     }
 }
 
@@ -141,13 +354,29 @@ impl Node {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut res: Vec<u8> = Vec::new();
 
-        res.extend(self.kind.to_le_bytes().iter());
+        let kind = u64::from_le_bytes(self.kind.to_le_bytes()) | ((self.version as u64) << 32);
+        res.extend(kind.to_le_bytes().iter());
         res.extend(self.size.to_le_bytes().iter());
         res.extend(self.parent_block_id.to_le_bytes().iter());
+        if self.version >= 1 {
+            res.extend(self.created.to_le_bytes().iter());
+            res.extend(self.modified.to_le_bytes().iter());
+        }
+        if self.version >= 2 {
+            res.extend(self.links.to_le_bytes().iter());
+        }
+        if self.version >= 6 && self.kind == File {
+            res.extend(self.chunk_size.to_le_bytes().iter());
+            res.extend(self.used.to_le_bytes().iter());
+        } else if self.version >= 3 {
+            res.extend(self.quota.to_le_bytes().iter());
+            res.extend(self.used.to_le_bytes().iter());
+        }
 
         match self.kind {
             Directory => res.extend(self.entries.iter().flat_map(DirectoryEntry::to_le_bytes)),
             File => res.extend(self.blocks.iter().flat_map(|entry| entry.to_le_bytes())),
+            Symlink => res.extend(self.target.as_bytes()),
         }
 
         assert!(
@@ -159,64 +388,364 @@ impl Node {
         res
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        assert!(
-            bytes.len() <= BLOCK_SIZE,
-            "Data exceeds maximum block size of {}: {}",
-            HumanCount(BLOCK_SIZE as u64),
-            HumanCount(bytes.len() as u64)
-        );
-        assert!(
-            bytes.len() >= KIND_SIZE + SIZE_SIZE + BLOCK_INDEX_SIZE,
-            "Too little data supplied to build a Node: {}",
-            bytes.len()
-        );
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ParseError> {
+        if bytes.len() > BLOCK_SIZE {
+            return Err(ParseError::new(
+                BLOCK_SIZE,
+                format!(
+                    "Data exceeds maximum block size of {}: {}",
+                    HumanCount(BLOCK_SIZE as u64),
+                    HumanCount(bytes.len() as u64)
+                ),
+            ));
+        }
 
         const KIND_POS: usize = 0;
         const SIZE_POS: usize = KIND_SIZE;
         const PARENT_BLOCK_ID_POS: usize = SIZE_POS + SIZE_SIZE;
-        const CONTENT_POS: usize = PARENT_BLOCK_ID_POS + BLOCK_INDEX_SIZE;
+        const CREATED_POS: usize = PARENT_BLOCK_ID_POS + BLOCK_INDEX_SIZE;
+        const MODIFIED_POS: usize = CREATED_POS + TIMESTAMP_SIZE;
+        const LINKS_POS: usize = MODIFIED_POS + TIMESTAMP_SIZE;
+        const QUOTA_POS: usize = LINKS_POS + LINKS_SIZE;
+        const USED_POS: usize = QUOTA_POS + SIZE_SIZE;
+
+        let mut res = Node::new(Directory, 0, 0);
+
+        let kind = parse_error::read_u64(&bytes, KIND_POS, "node kind")?;
+        res.kind =
+            NodeKind::from_le_bytes((kind & u32::MAX as u64).to_le_bytes()).ok_or_else(|| {
+                ParseError::new(
+                    KIND_POS,
+                    format!("Invalid node kind: {}", kind & u32::MAX as u64),
+                )
+            })?;
+        res.version = (kind >> 32) as Version;
+        if res.version > FORMAT_VERSION {
+            return Err(ParseError::new(
+                KIND_POS,
+                format!(
+                    "Unsupported format version v{}, the newest supported is v{FORMAT_VERSION}",
+                    res.version
+                ),
+            ));
+        }
+        res.size = parse_error::read_u64(&bytes, SIZE_POS, "node size")?;
+        res.parent_block_id = parse_error::read_u64(&bytes, PARENT_BLOCK_ID_POS, "parent id")?;
+
+        let content_pos = if res.version >= 2 {
+            res.created = parse_error::read_u64(&bytes, CREATED_POS, "creation time")?;
+            res.modified = parse_error::read_u64(&bytes, MODIFIED_POS, "modification time")?;
+            res.links = parse_error::read_u64(&bytes, LINKS_POS, "link count")?;
+            if res.links == 0 {
+                return Err(ParseError::new(LINKS_POS, "Node without links"));
+            }
 
-        let mut res = Node::new(Directory, 0);
-        let mut u64_bytes = [0; 8];
+            if res.version >= 3 {
+                res.quota = parse_error::read_u64(&bytes, QUOTA_POS, "quota")?;
+                res.used = parse_error::read_u64(&bytes, USED_POS, "used size")?;
+
+                // files have no quota, the field holds their chunk size instead
+                if res.version >= 6 && res.kind == File {
+                    res.chunk_size = std::mem::take(&mut res.quota);
+                    if !(MIN_CHUNK_SIZE as Size..=BLOCK_SIZE as Size).contains(&res.chunk_size) {
+                        return Err(ParseError::new(
+                            QUOTA_POS,
+                            format!("Invalid chunk size: {}", HumanCount(res.chunk_size)),
+                        ));
+                    }
+                }
+
+                HEADER_SIZE
+            } else {
+                // quotas didn't exist yet
+                QUOTA_POS
+            }
+        } else if res.version >= 1 {
+            res.created = parse_error::read_u64(&bytes, CREATED_POS, "creation time")?;
+            res.modified = parse_error::read_u64(&bytes, MODIFIED_POS, "modification time")?;
+
+            // hard links didn't exist yet
+            LINKS_POS
+        } else {
+            // timestamps are unknown
+            res.created = 0;
+            res.modified = 0;
 
-        u64_bytes.copy_from_slice(&bytes[KIND_POS..SIZE_POS]);
-        res.kind = NodeKind::from_le_bytes(u64_bytes);
-        u64_bytes.copy_from_slice(&bytes[SIZE_POS..PARENT_BLOCK_ID_POS]);
-        res.size = u64::from_le_bytes(u64_bytes);
-        u64_bytes.copy_from_slice(&bytes[PARENT_BLOCK_ID_POS..CONTENT_POS]);
-        res.parent_block_id = u64::from_le_bytes(u64_bytes);
+            CREATED_POS
+        };
 
         match res.kind {
             Directory => {
-                res.entries = DirectoryEntry::from_le_bytes(&bytes[CONTENT_POS..]);
-
-                assert!(
-                    res.entries.len() as u64 == res.size,
-                    "Malformed input data has inconsistent amount of entries: {} != {}",
-                    HumanCount(res.entries.len() as u64),
-                    HumanCount(res.size)
-                );
+                res.entries = DirectoryEntry::from_le_bytes(&bytes[content_pos..])
+                    .map_err(|err| err.at(content_pos))?;
+
+                if res.entries.len() as u64 != res.size {
+                    return Err(ParseError::new(
+                        SIZE_POS,
+                        format!(
+                            "Inconsistent amount of entries: expected {}, got {}",
+                            HumanCount(res.size),
+                            HumanCount(res.entries.len() as u64)
+                        ),
+                    ));
+                }
             }
             File => {
-                assert!(
-                    res.size <= MAX_FILE_SIZE as u64,
-                    "Malformed input data reports file sizes larger than the maximum of {} ({}): {} ({})",
-                    HumanBytes(MAX_FILE_SIZE as u64),
-                    HumanCount(MAX_FILE_SIZE as u64),
-                    HumanBytes(res.size),
-                    HumanCount(res.size)
-                );
-
-                res.blocks = bytes[CONTENT_POS..]
-                    .as_chunks::<BLOCK_INDEX_SIZE>()
-                    .0
-                    .iter()
-                    .map(|idx| u64::from_le_bytes(*idx))
-                    .collect()
+                if res.size > MAX_FILE_SIZE as u64 {
+                    return Err(ParseError::new(
+                        SIZE_POS,
+                        format!(
+                            "File size exceeds the maximum of {} ({}): {} ({})",
+                            HumanBytes(MAX_FILE_SIZE as u64),
+                            HumanCount(MAX_FILE_SIZE as u64),
+                            HumanBytes(res.size),
+                            HumanCount(res.size)
+                        ),
+                    ));
+                }
+
+                let (blocks, remainder) = bytes[content_pos..].as_chunks::<BLOCK_INDEX_SIZE>();
+                if !remainder.is_empty() {
+                    return Err(ParseError::new(
+                        bytes.len() - remainder.len(),
+                        format!(
+                            "Truncated block id: expected {BLOCK_INDEX_SIZE} bytes, got {}",
+                            remainder.len()
+                        ),
+                    ));
+                }
+
+                res.blocks = blocks.iter().map(|idx| u64::from_le_bytes(*idx)).collect();
+
+                // every block but the last is a full chunk
+                let expected = res.size.div_ceil(res.chunk_size);
+                if res.blocks.len() as u64 != expected {
+                    return Err(ParseError::new(
+                        SIZE_POS,
+                        format!(
+                            "Inconsistent amount of blocks: expected {} for {}, got {}",
+                            HumanCount(expected),
+                            HumanBytes(res.size),
+                            HumanCount(res.blocks.len() as u64)
+                        ),
+                    ));
+                }
+            }
+            Symlink => {
+                let target = &bytes[content_pos..];
+                if target.len() as u64 != res.size || target.len() > MAX_TARGET_LEN {
+                    return Err(ParseError::new(
+                        SIZE_POS,
+                        format!(
+                            "Inconsistent symlink target length: expected {}, got {}",
+                            HumanCount(res.size),
+                            HumanCount(target.len() as u64)
+                        ),
+                    ));
+                }
+
+                res.target = String::from_utf8(target.to_vec()).map_err(|err| {
+                    ParseError::new(
+                        content_pos + err.utf8_error().valid_up_to(),
+                        "Symlink target is not valid UTF-8",
+                    )
+                })?;
             }
         }
 
-        res
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::directory_entry::tests::name;
+
+    fn directory() -> impl Strategy<Value = Node> {
+        (
+            0..=FORMAT_VERSION,
+            any::<BlockIndex>(),
+            any::<Timestamp>(),
+            prop::collection::vec((name(), any::<BlockIndex>()), 0..32),
+            any::<(Size, Size)>(),
+        )
+            .prop_map(|(version, parent, timestamp, entries, (quota, used))| {
+                let mut node = Node::new(Directory, version, parent);
+                for (name, block) in entries {
+                    if !node.contains_entry(&name) {
+                        node.push_directory_entry(name, block);
+                    }
+                }
+                node.set_timestamps(timestamp);
+
+                // quotas are only stored from v3 onwards
+                if version >= 3 {
+                    node.quota = quota;
+                    node.used = used;
+                }
+
+                node
+            })
+    }
+
+    fn file() -> impl Strategy<Value = Node> {
+        (
+            0..=FORMAT_VERSION,
+            any::<BlockIndex>(),
+            any::<Timestamp>(),
+            prop::collection::vec(any::<BlockIndex>(), 0..64),
+            1..=BLOCK_SIZE as Size,
+            1..=LinkCount::MAX,
+            MIN_CHUNK_SIZE as Size..=BLOCK_SIZE as Size,
+        )
+            .prop_map(
+                |(version, parent, timestamp, blocks, last, links, chunk_size)| {
+                    let mut node = Node::new(File, version, parent);
+
+                    // chunk sizes are only stored from v6 onwards
+                    if version >= 6 {
+                        node.chunk_size = chunk_size;
+                    }
+
+                    // only the last block can be partial
+                    let count = blocks.len();
+                    for (idx, block) in blocks.into_iter().enumerate() {
+                        let size = if idx + 1 == count {
+                            (last - 1) % node.chunk_size + 1
+                        } else {
+                            node.chunk_size
+                        };
+                        node.push_data_block(block, size);
+                    }
+                    node.set_timestamps(timestamp);
+
+                    // link counts are only stored from v2 onwards
+                    if version >= 2 {
+                        node.links = links;
+                    }
+
+                    node
+                },
+            )
+    }
+
+    fn symlink() -> impl Strategy<Value = Node> {
+        (
+            0..=FORMAT_VERSION,
+            any::<BlockIndex>(),
+            any::<Timestamp>(),
+            "\\PC{1,256}",
+        )
+            .prop_map(|(version, parent, timestamp, target)| {
+                let mut node = Node::new(Symlink, version, parent);
+                node.set_target(target);
+                node.set_timestamps(timestamp);
+
+                node
+            })
+    }
+
+    impl Node {
+        // timestamps are only stored from v1 onwards
+        fn set_timestamps(&mut self, timestamp: Timestamp) {
+            let timestamp = if self.version >= 1 { timestamp } else { 0 };
+            self.created = timestamp;
+            self.modified = timestamp;
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn directory_round_trip(node in directory()) {
+            prop_assert_eq!(Node::from_bytes(node.to_bytes()), Ok(node));
+        }
+
+        #[test]
+        fn file_round_trip(node in file()) {
+            prop_assert_eq!(Node::from_bytes(node.to_bytes()), Ok(node));
+        }
+
+        #[test]
+        fn symlink_round_trip(node in symlink()) {
+            prop_assert_eq!(Node::from_bytes(node.to_bytes()), Ok(node));
+        }
+
+        #[test]
+        fn arbitrary_bytes_do_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+            let _ = Node::from_bytes(bytes);
+        }
+
+        #[test]
+        fn truncated_nodes_are_rejected(node in directory(), cut in any::<prop::sample::Index>()) {
+            let mut bytes = node.to_bytes();
+            bytes.truncate(cut.index(bytes.len()));
+
+            prop_assert!(Node::from_bytes(bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn finds_duplicate_entries() {
+        let mut node = Node::new(Directory, FORMAT_VERSION, 1);
+        node.push_directory_entry("a", 2);
+        node.push_directory_entry("b/", 3);
+        node.push_directory_entry("A", 4);
+        assert!(node.duplicate_entries().is_empty());
+
+        // duplicates can only come from existing nodes, pushing them is rejected
+        node.entries.push(DirectoryEntry::new("a", 5));
+        node.entries.push(DirectoryEntry::new("b/", 6));
+        node.entries.push(DirectoryEntry::new("a", 7));
+        assert_eq!(node.duplicate_entries(), [3, 4, 5]);
+
+        node.case_insensitive = true;
+        assert_eq!(node.duplicate_entries(), [2, 3, 4, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already contains")]
+    fn rejects_pushing_duplicates() {
+        let mut node = Node::new(Directory, FORMAT_VERSION, 1);
+        node.push_directory_entry("a", 2);
+        node.push_directory_entry("a", 3);
+    }
+
+    #[test]
+    fn full_directory_fits_block() {
+        let mut node = Node::new(Directory, FORMAT_VERSION, 1);
+        for block in 0..ENTRY_COUNT {
+            node.push_directory_entry(format!("{block:0>1008}"), block as BlockIndex);
+        }
+        assert!(node.is_full());
+
+        let bytes = node.to_bytes();
+        assert!(bytes.len() <= BLOCK_SIZE);
+        assert_eq!(Node::from_bytes(bytes), Ok(node));
+    }
+
+    #[test]
+    fn full_file_fits_block() {
+        let mut node = Node::new(File, FORMAT_VERSION, 1);
+        for block in 0..BLOCK_COUNT {
+            node.push_data_block(block as BlockIndex, BLOCK_SIZE as Size);
+        }
+        assert_eq!(node.size(), MAX_FILE_SIZE as Size);
+
+        let bytes = node.to_bytes();
+        assert!(bytes.len() <= BLOCK_SIZE);
+        assert_eq!(Node::from_bytes(bytes), Ok(node));
+    }
+
+    #[test]
+    fn rejects_more_blocks_than_the_size_needs() {
+        let mut node = Node::new(File, FORMAT_VERSION, 1);
+        node.push_data_block(2, BLOCK_SIZE as Size);
+        node.push_data_block(3, 1);
+        node.size = 1;
+        assert_eq!(node.block_size(1), 0);
+        assert!(Node::from_bytes(node.to_bytes()).is_err());
     }
 }
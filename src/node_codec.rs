@@ -0,0 +1,112 @@
+use crate::{
+    node::{BLOCK_SIZE, Node},
+    parse_error::ParseError,
+};
+
+// zstd frames start with 0xFD2FB528, node kinds and versions never produce these bytes
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+// encodes node payloads, the filesystem's codec is named in the superblock and writes every
+// node, reading detects the codec from the bytes, so nodes keep working while a filesystem
+// changes codecs and only get the new encoding once they are written again
+pub trait NodeCodec: Sync {
+    // stored in the superblock as 'codec:<name>' unless it's the default
+    fn name(&self) -> &'static str;
+
+    // whether the bytes were written by this codec
+    fn detect(&self, bytes: &[u8]) -> bool;
+
+    fn encode(&self, node: &Node) -> Vec<u8>;
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Node, ParseError>;
+}
+
+// the little endian format every node was written with before codecs existed
+pub struct LittleEndian;
+
+impl NodeCodec for LittleEndian {
+    fn name(&self) -> &'static str {
+        "le"
+    }
+
+    // the fallback, anything another codec doesn't detect is parsed as this format
+    fn detect(&self, _: &[u8]) -> bool {
+        true
+    }
+
+    fn encode(&self, node: &Node) -> Vec<u8> {
+        node.to_bytes()
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Node, ParseError> {
+        Node::from_bytes(bytes)
+    }
+}
+
+// the little endian format compressed with zstd, directory entries are padded to 1 KiB so
+// directories shrink the most, at the cost of re-uploading every segment of a changed node
+pub struct Zstd;
+
+impl NodeCodec for Zstd {
+    fn name(&self) -> &'static str {
+        "zstd"
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(&ZSTD_MAGIC)
+    }
+
+    fn encode(&self, node: &Node) -> Vec<u8> {
+        zstd::bulk::compress(&node.to_bytes(), 0).expect("Failed to compress node")
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> Result<Node, ParseError> {
+        // the capacity also stops nodes that decompress to more than a node can hold
+        let bytes = zstd::bulk::decompress(&bytes, BLOCK_SIZE)
+            .map_err(|err| ParseError::new(0, format!("Invalid zstd node data: {err}")))?;
+        Node::from_bytes(bytes)
+    }
+}
+
+// detection order, the fallback comes last
+pub static CODECS: [&dyn NodeCodec; 2] = [&Zstd, &LittleEndian];
+
+pub static DEFAULT: &dyn NodeCodec = &LittleEndian;
+
+pub fn by_name(name: &str) -> Option<&'static dyn NodeCodec> {
+    CODECS.iter().find(|codec| codec.name() == name).copied()
+}
+
+pub fn decode(bytes: Vec<u8>) -> Result<Node, ParseError> {
+    CODECS
+        .iter()
+        .find(|codec| codec.detect(&bytes))
+        .unwrap_or(&DEFAULT)
+        .decode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{node::FORMAT_VERSION, node_kind::NodeKind::Directory};
+
+    #[test]
+    fn every_codec_round_trips_and_is_detected() {
+        let mut node = Node::new(Directory, FORMAT_VERSION, 1);
+        node.push_directory_entry("a", 2);
+        node.push_directory_entry("b/", 3);
+
+        for codec in CODECS {
+            let bytes = codec.encode(&node);
+            assert_eq!(decode(bytes.clone()), Ok(node.clone()), "{}", codec.name());
+            assert_eq!(codec.decode(bytes), Ok(node.clone()), "{}", codec.name());
+        }
+        assert!(Zstd.encode(&node).len() < LittleEndian.encode(&node).len());
+    }
+
+    #[test]
+    fn little_endian_nodes_are_not_detected_as_zstd() {
+        let node = Node::new(Directory, FORMAT_VERSION, 1);
+        assert!(!Zstd.detect(&LittleEndian.encode(&node)));
+    }
+}
@@ -1,8 +1,9 @@
 #[repr(u64)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeKind {
     Directory = 0,
     File = 1,
+    Symlink = 2,
 }
 
 impl NodeKind {
@@ -10,11 +11,12 @@ impl NodeKind {
         (self as u64).to_le_bytes()
     }
 
-    pub fn from_le_bytes(bytes: [u8; 8]) -> Self {
+    pub fn from_le_bytes(bytes: [u8; 8]) -> Option<Self> {
         match u64::from_le_bytes(bytes) {
-            0 => NodeKind::Directory,
-            1 => NodeKind::File,
-            _ => panic!("Invalid bytes for NodeKind"),
+            0 => Some(NodeKind::Directory),
+            1 => Some(NodeKind::File),
+            2 => Some(NodeKind::Symlink),
+            _ => None,
         }
     }
 }
@@ -1,39 +1,202 @@
-use std::cmp::min;
+mod activity;
+mod adopt;
+mod archive;
+mod bench;
+mod cache;
+mod channel_stats;
+mod check;
+mod codec;
+mod compact;
+mod copy;
+mod debug;
+mod doctor;
+mod erasure;
+mod estimate;
+mod failures;
+mod fsck;
+mod gc;
+mod import;
+mod key;
+mod log;
+mod migrate;
+mod open;
+mod permissions;
+mod plan;
+mod prune;
+mod quota;
+mod recovery;
+mod rename;
+mod repair;
+mod report;
+mod retention;
+mod rewrite;
+mod salvage;
+mod scrub;
+mod spool;
+mod staging;
+mod stat;
+mod stats;
+mod superblock;
+mod upload_limit;
+
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, atomic::AtomicU64},
+    time::{Duration, Instant},
+};
 
 use aes_gcm_siv::{
     Aes256GcmSiv,
     aead::{Aead, KeyInit},
 };
-use indicatif::{HumanBytes, HumanCount, MultiProgress};
+use futures::{StreamExt, stream};
+use indicatif::{HumanBytes, HumanCount, MultiProgress, ProgressBar, ProgressDrawTarget};
 use serenity::{
     Client,
-    all::{ChannelId, CreateAttachment, CreateMessage, EditMessage, MessageId},
+    all::{
+        Attachment, ChannelId, CreateAttachment, CreateMessage, EditAttachments, EditMessage,
+        GuildId, Message, MessageId, UserId,
+    },
 };
 use tokio::{
     fs,
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, SeekFrom},
 };
 
 use crate::{
-    directory_entry::BlockIndex,
-    node::{self, Node},
-    node_kind::NodeKind::{Directory, File},
+    command::{DownloadOptions, ListPage},
+    directory_entry::{self, BlockIndex, DirectoryEntry},
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node::{self, Node, Size, Timestamp, Version},
+    node_codec::{self, NodeCodec},
+    node_kind::NodeKind::{self, Directory, File, Symlink},
     nonce_counter::NonceCounter,
+    on_conflict::OnConflict,
+    parse_error::ParseError,
+    sync_io::SyncIo,
     util,
 };
 
+// internal metadata lives in a hidden directory under the root
+const METADATA_DIR_NAME: &str = ".dfs/";
+
+// like the Linux kernel, resolving a path follows at most 40 symlinks
+const MAX_SYMLINK_HOPS: usize = 40;
+
+// deeper trees are taken for cycles from corrupted entries, 'DFS_MAX_DEPTH' overrides it
+const DEFAULT_MAX_DEPTH: usize = 1024;
+
+// a data block that fails to download is fetched this many times before the transfer fails
+const BLOCK_ATTEMPTS: usize = 3;
+
+// uploads that can be resumed save their node every this many blocks, an interrupted upload
+// loses at most the blocks since
+const CHECKPOINT_BLOCKS: usize = 16;
+
+// how file blocks are fetched, with `allow_holes` blocks that can't be read are filled with
+// zeros instead of failing the transfer
+#[derive(Clone, Copy, Default)]
+struct BlockFetch {
+    readahead: usize,
+    allow_holes: bool,
+}
+
+impl From<&DownloadOptions> for BlockFetch {
+    fn from(options: &DownloadOptions) -> Self {
+        BlockFetch {
+            readahead: options.readahead,
+            allow_holes: options.allow_holes,
+        }
+    }
+}
+
 pub struct NodeFS {
     root_node_id: BlockIndex,
+    format_version: Version,
+
+    // name of the filesystem within the channel, None for the one in the channel topic
+    namespace: Option<String>,
+    case_insensitive: bool,
     data_channel: ChannelId,
 
+    // encoding of the nodes this filesystem writes
+    codec: &'static dyn NodeCodec,
+
+    // new data blocks are striped over other channels, None keeps them in the data channel
+    erasure: Option<erasure::Erasure>,
+
+    // bot that wrote the filesystem, messages by anyone else are not trusted. filesystems from
+    // before it was recorded have none and trust every author
+    owner: Option<UserId>,
+
+    // the superblock has no CRC or was read from the backup, 'fsck --repair' and 'migrate' save
+    // it again
+    superblock_outdated: bool,
+
+    // server of the data channel, its boost tier decides the attachment size limit
+    guild_id: Option<GuildId>,
+    upload_limit: OnceLock<Size>,
+    recorded_upload_limit: Option<Size>,
+
+    // print transfer summaries as JSON lines
+    json: bool,
+
+    // local copies of node data, None if caching is turned off
+    cache_dir: Option<PathBuf>,
+
+    // local lifetime statistics, None unless they are turned on
+    stats_file: Option<PathBuf>,
+
+    // the running operation as (name, start, start instant) and the bytes it transferred
+    operation: OnceLock<(String, Timestamp, Instant)>,
+    transferred: Arc<AtomicU64>,
+
+    // walks over the directory tree stop below this many levels
+    max_depth: usize,
+
+    // entries a bulk operation skipped, as (entry, reason)
+    failures: Mutex<Vec<(String, String)>>,
+
+    // serializes link count updates of hard linked files during parallel deletes
+    unlink_lock: tokio::sync::Mutex<()>,
+
     client: Client,
 }
 
 impl NodeFS {
-    pub fn new(data_channel_id: u64, client: serenity::Client) -> Self {
+    pub fn new(
+        data_channel_id: u64,
+        namespace: Option<String>,
+        client: serenity::Client,
+        json: bool,
+    ) -> Self {
         NodeFS {
             root_node_id: 0,
+            format_version: node::FORMAT_VERSION,
+            namespace,
+            case_insensitive: false,
             data_channel: ChannelId::new(data_channel_id),
+            codec: node_codec::DEFAULT,
+            erasure: None,
+            owner: None,
+            superblock_outdated: false,
+            guild_id: None,
+            upload_limit: OnceLock::new(),
+            recorded_upload_limit: None,
+            json,
+            cache_dir: NodeFS::node_cache_dir(data_channel_id),
+            stats_file: NodeFS::stats_file(data_channel_id),
+            operation: OnceLock::new(),
+            transferred: Arc::new(AtomicU64::new(0)),
+            max_depth: std::env::var("DFS_MAX_DEPTH").map_or(DEFAULT_MAX_DEPTH, |depth| {
+                depth
+                    .parse()
+                    .expect("Expected a number of levels in 'DFS_MAX_DEPTH'")
+            }),
+            failures: Mutex::new(Vec::new()),
+            unlink_lock: tokio::sync::Mutex::new(()),
             client,
         }
     }
@@ -43,138 +206,592 @@ impl NodeFS {
         let spinner = util::spinner();
         spinner.set_message(String::from("Starting up"));
 
-        if let Some(topic) = util::get_guild_channel(&self.client, self.data_channel)
-            .await
-            .expect("Data channel should be guild channel")
-            .topic
-        {
-            let block_id = topic.parse::<u64>().expect(
-                "Only the root message ID should be in the channel topic and be a valid u64",
+        if self.load_superblock().await {
+            assert!(
+                self.format_version <= node::FORMAT_VERSION,
+                "Filesystem has unsupported format version v{}, the newest supported is v{}",
+                self.format_version,
+                node::FORMAT_VERSION
             );
-            self.root_node_id = block_id;
         } else {
             // root node has parent of 0
             let (_, root_node_block_id) = self.create_directory_node(0).await;
-
-            // store root node id in discord topic
-            util::edit_channel_topic(
-                &self.client,
-                self.data_channel,
-                root_node_block_id.to_string(),
-            )
-            .await
-            .expect("Failed to save root node block id in channel topic");
-
             self.root_node_id = root_node_block_id;
+            self.owner = Some(
+                util::get_current_user(&self.client)
+                    .await
+                    .expect("Failed to get the bot user"),
+            );
+
+            // store root node id in the superblock
+            self.save_superblock().await;
+            self.save_recovery_note().await;
         }
 
         // cleanup
         spinner.finish_and_clear();
     }
 
-    pub async fn ls(&self, path: Option<String>) {
-        if let Some(path) = path {
-            let (_, name) = NodeFS::split_path(path.as_str(), true, true);
-            let (path_node, _) = self.traverse_path(path.as_str()).await;
-            self.__list(0, name, path_node).await;
-        } else {
-            self.__list(0, "/", self.get_directory_node(self.root_node_id).await)
-                .await;
+    pub async fn ls(&self, path: Option<FsPath>, du: bool, page: ListPage) {
+        let (name, node, node_id) = match path.filter(|path| !path.is_root()) {
+            Some(path) => {
+                let (_, name) = NodeFS::split_path(&path, true, true);
+                let (node, node_id) = self.traverse_path(&path).await;
+                (name.to_string(), node, node_id)
+            }
+            None => (
+                String::from("/"),
+                self.get_directory_node(self.root_node_id).await,
+                self.root_node_id,
+            ),
+        };
+        let footer = (node.kind == Directory && !page.is_all()).then(|| {
+            let (shown, matching) = NodeFS::page_entries(node.entries(), &page);
+            match shown.len() {
+                0 => format!("  No entries to show of {matching} matching"),
+                count => format!(
+                    "  Showing entries {}-{} of {matching} matching",
+                    page.offset + 1,
+                    page.offset + count
+                ),
+            }
+        });
+
+        // directory sizes are only known once their subtree was listed, so the lines are
+        // collected and printed at the end
+        let mut lines = du.then(Vec::new);
+        self.__list(0, &name, node, node_id, &page, lines.as_mut())
+            .await;
+
+        for line in lines.into_iter().flatten() {
+            println!("{line}");
+        }
+        if let Some(footer) = footer {
+            println!("{footer}");
         }
     }
 
-    pub async fn upload(&self, source: String, destination: String, key: String) {
-        self.__upload(source, destination, key, &MultiProgress::new())
-            .await
+    // the entries of a page and how many entries match its filter
+    fn page_entries<'a>(
+        entries: &'a [DirectoryEntry],
+        page: &ListPage,
+    ) -> (Vec<&'a DirectoryEntry>, usize) {
+        let matching: Vec<&DirectoryEntry> = entries
+            .iter()
+            .filter(|entry| {
+                page.filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.is_match(entry.get_name().trim_end_matches('/')))
+            })
+            .collect();
+        let count = matching.len();
+        let shown = matching
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        (shown, count)
     }
 
-    async fn __upload(
+    pub async fn upload(
+        &self,
+        sources: Vec<String>,
+        destination: FsPath,
+        on_conflict: OnConflict,
+        chunk_size: Option<Size>,
+        key: String,
+    ) {
+        let chunk_size = self.check_chunk_size(chunk_size);
+        self.__upload(
+            sources,
+            destination,
+            on_conflict,
+            chunk_size,
+            key,
+            &MultiProgress::new(),
+        )
+        .await
+    }
+
+    pub async fn upload_tar(
         &self,
         source: String,
-        destination: String,
+        destination: FsPath,
+        mirror_structure: bool,
+        chunk_size: Option<Size>,
+        key: String,
+    ) {
+        let chunk_size = self.check_chunk_size(chunk_size);
+        self.__upload_tar(
+            source,
+            destination,
+            mirror_structure,
+            chunk_size,
+            key,
+            &MultiProgress::new(),
+        )
+        .await
+    }
+
+    // the block size of new files, the default is the largest
+    fn check_chunk_size(&self, chunk_size: Option<Size>) -> Size {
+        let chunk_size = chunk_size.unwrap_or(node::BLOCK_SIZE as Size);
+        assert!(
+            (node::MIN_CHUNK_SIZE as Size..=node::BLOCK_SIZE as Size).contains(&chunk_size),
+            "Chunk size must be between {} and {}",
+            HumanBytes(node::MIN_CHUNK_SIZE as u64),
+            HumanBytes(node::BLOCK_SIZE as u64)
+        );
+        assert!(
+            chunk_size == node::BLOCK_SIZE as Size || self.format_version >= 6,
+            "Custom chunk sizes need format v6, run 'migrate --to v6' first"
+        );
+
+        chunk_size
+    }
+
+    async fn __upload(
+        &self,
+        sources: Vec<String>,
+        destination: FsPath,
+        on_conflict: OnConflict,
+        chunk_size: Size,
         key: String,
         progress: &MultiProgress,
     ) {
         // show progress informaton
         let spinner = progress.add(util::spinner());
-        spinner.set_message(format!("Uploading {source} to {destination}"));
+        spinner.set_message(format!(
+            "Uploading {} files to {destination}",
+            sources.len()
+        ));
+
+        // several files go into a directory, a single one may also be given a new name
+        let (dir_path, names) = if sources.len() == 1 && !destination.is_dir() {
+            let (dir_path, name) = NodeFS::split_path(&destination, false, false);
+            (dir_path, vec![name.to_string()])
+        } else {
+            assert!(
+                destination.is_dir(),
+                "Destination must be a directory when uploading several files"
+            );
+            let names = sources
+                .iter()
+                .map(|source| util::local_file_name(source).expect("Source must have a file name"))
+                .collect();
+            (destination.clone(), names)
+        };
+
+        // open every source upfront so the total size is known, sources that can't be
+        // uploaded are skipped
+        let mut files = Vec::with_capacity(sources.len());
+        for (source, name) in sources.iter().zip(names) {
+            match NodeFS::open_source(source, &name, chunk_size).await {
+                Ok((file, filesize)) => files.push((source, name, file, filesize)),
+                Err(err) => self.record_failure(progress, source.clone(), err),
+            }
+        }
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&dir_path).await;
+        let quotas = self.quota_directories(dir_node_id).await;
+
+        // show progress bar shared by all files
+        let progress_bar = progress.add(util::progress_bar(
+            files.iter().map(|(_, _, _, filesize)| filesize).sum(),
+        ));
+
+        let staging_node_id = self.staging_directory().await;
+
+        let mut uploaded = 0;
+        let mut bytes = 0;
+        let mut blocks = 0;
+        let mut charged: i64 = 0;
+        for (source, name, mut file, filesize) in files {
+            spinner.set_message(format!("Uploading {source} to {dir_path}{name}"));
+
+            let Some(file_name) = NodeFS::conflict_name(&dir_node, &name, on_conflict) else {
+                progress
+                    .println(format!(
+                        "  Skipped {source}, {dir_path}{name} already exists"
+                    ))
+                    .unwrap();
+                progress_bar.dec_length(filesize);
+                continue;
+            };
+            let existing = dir_node
+                .find_directory_entry(&file_name)
+                .map(|entry| entry.block_id());
+            failure::ensure(
+                existing.is_some() || !dir_node.is_full(),
+                Hint::DirectoryFull,
+                "The directory is full",
+            );
+
+            // resumed files keep their directory entry
+            if let (Some(file_node_id), OnConflict::Resume) = (existing, on_conflict) {
+                progress_bar.dec_length(filesize);
+                self.resume_upload(file, filesize, file_node_id, &quotas, &key, progress)
+                    .await;
+                continue;
+            }
+
+            // an interrupted upload left its node staged, it's resumed there and linked after
+            let staged = match on_conflict {
+                OnConflict::Resume => {
+                    self.find_staged_upload(staging_node_id, dir_node_id, &file_name)
+                        .await
+                }
+                _ => None,
+            };
+            if let Some(file_node_id) = staged {
+                progress_bar.dec_length(filesize);
+                NodeFS::check_quota(&quotas, (charged + filesize as i64).max(0) as Size);
+                let old_size = self
+                    .resume_upload(file, filesize, file_node_id, &quotas, &key, progress)
+                    .await;
+                let file_node = self.get_file_node(file_node_id).await;
+
+                uploaded += 1;
+                charged += file_node.size() as i64;
+                self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+                dir_node.push_directory_entry(&file_name, file_node_id);
+                self.edit_directory_node(dir_node_id, dir_node.clone())
+                    .await;
+                // the resumed part is charged by resume_upload
+                self.charge_quota(&quotas, old_size as i64).await;
+                continue;
+            }
+
+            // overwritten files only count with the size they grow by
+            let existing_node = match existing {
+                Some(node_id) => Some((
+                    self.try_get_node(node_id)
+                        .await
+                        .unwrap_or_else(|err| panic!("Node {node_id} is corrupted: {err}")),
+                    node_id,
+                )),
+                None => None,
+            };
+            let existing_size = existing_node
+                .as_ref()
+                .filter(|(node, _)| node.kind == File)
+                .map_or(0, |(node, _)| node.size());
+            NodeFS::check_quota(
+                &quotas,
+                (charged + filesize as i64 - existing_size as i64).max(0) as Size,
+            );
+
+            // create file node and upload file in at most block sized chunks
+            let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+            file_node.chunk_size = chunk_size;
+            self.stage_upload(staging_node_id, file_node_id, &file_name)
+                .await;
+            self.upload_blocks_saving(
+                &mut file,
+                &mut file_node,
+                Some(file_node_id),
+                &key,
+                &progress_bar,
+            )
+            .await;
 
-        // Open source file
-        let mut file = fs::File::open(&source).await.expect("Failed to open file");
+            uploaded += 1;
+            bytes += file_node.size();
+            blocks += file_node.blocks().len();
+            let delta = file_node.size() as i64 - existing_size as i64;
+            charged += delta;
+            self.edit_file_node(file_node_id, file_node).await;
+
+            // every file is linked as soon as it's uploaded, so files that finished stay when a
+            // later one fails
+            self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+            if existing_node.is_some() {
+                dir_node.delete_directory_entry(&file_name);
+            }
+            dir_node.push_directory_entry(&file_name, file_node_id);
+            self.edit_directory_node(dir_node_id, dir_node.clone())
+                .await;
+            self.charge_quota(&quotas, delta).await;
+
+            // the old data is only deleted once the new file is in place
+            if let Some((node, node_id)) = existing_node {
+                match node.kind {
+                    File => self.unlink_file(node, node_id, file_name, progress).await,
+                    _ => self.delete_block(node_id).await,
+                }
+            }
+        }
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        if sources.len() == 1 && uploaded == 1 {
+            spinner.finish_with_message(format!("Finished uploading {}", sources[0]));
+        } else {
+            spinner.finish_with_message(format!(
+                "Uploaded {uploaded} of {} files to {dir_path}",
+                sources.len()
+            ));
+        }
+        if uploaded > 0 {
+            self.print_summary(
+                progress,
+                util::TransferSummary {
+                    operation: "Uploaded",
+                    bytes,
+                    blocks,
+                    elapsed: progress_bar.elapsed(),
+                },
+            );
+        }
+    }
+
+    async fn open_source(
+        source: &str,
+        name: &str,
+        chunk_size: Size,
+    ) -> Result<(fs::File, Size), String> {
+        DirectoryEntry::validate_name(name)?;
+
+        let file = fs::File::open(source)
+            .await
+            .map_err(|err| format!("Failed to open file: {err}"))?;
         let filesize = file
             .metadata()
             .await
-            .expect("Failed to fetch source file size")
+            .map_err(|err| format!("Failed to fetch source file size: {err}"))?
             .len();
-        assert!(
-            filesize <= node::MAX_FILE_SIZE as u64,
-            "File exceeds maximum file size of {} ({}): {} ({})",
-            HumanBytes(node::MAX_FILE_SIZE as u64),
-            HumanCount(node::MAX_FILE_SIZE as u64),
-            HumanBytes(filesize),
-            HumanCount(filesize)
-        );
+        let max_file_size = node::max_file_size(chunk_size);
+        if filesize > max_file_size {
+            return Err(format!(
+                "Exceeds maximum file size of {} ({}): {} ({})",
+                HumanBytes(max_file_size),
+                HumanCount(max_file_size),
+                HumanBytes(filesize),
+                HumanCount(filesize)
+            ));
+        }
 
-        let (file_path, file_name) = NodeFS::split_path(destination.as_str(), false, false);
+        Ok((file, filesize))
+    }
 
-        // get target directory
-        let (mut dir_node, dir_node_id) = self.traverse_path(file_path).await;
-        assert!(!dir_node.is_full(), "The directory is full");
+    // uploads the rest of a file that has been partially uploaded before, returns the size it
+    // had before
+    async fn resume_upload(
+        &self,
+        mut file: fs::File,
+        filesize: Size,
+        file_node_id: BlockIndex,
+        quotas: &[(String, Node, BlockIndex)],
+        key: &str,
+        progress: &MultiProgress,
+    ) -> Size {
+        let mut file_node = self.get_file_node(file_node_id).await;
+        let old_size = file_node.size();
         assert!(
-            !dir_node.contains_entry(file_name),
-            "The file already exists"
+            old_size <= filesize,
+            "The uploaded file is larger than the source, it can't be resumed"
         );
+        NodeFS::check_quota(quotas, filesize - old_size);
 
-        // create file node
-        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        // a partial last block is uploaded again
+        let chunk_size = file_node.chunk_size;
+        let kept = (old_size / chunk_size) as usize;
+        let dropped = file_node.truncate_blocks(kept);
+        file.seek(SeekFrom::Start(file_node.size()))
+            .await
+            .expect("Failed to seek in source file");
 
         // show progress bar
         let progress_bar = progress.add(util::progress_bar(filesize));
+        progress_bar.set_position(file_node.size());
+
+        self.upload_blocks_saving(
+            &mut file,
+            &mut file_node,
+            Some(file_node_id),
+            key,
+            &progress_bar,
+        )
+        .await;
 
-        // encrypt the uploaded data
-        let cypher =
-            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
-        let mut nonce = NonceCounter::new();
-
-        // upload file in at most block sized chunks
-        let mut read_bytes = 0;
-        while read_bytes != filesize {
-            let chunk_size = std::cmp::min(filesize - read_bytes, node::BLOCK_SIZE as u64);
-            let mut chunk = vec![0; chunk_size as usize];
-            file.read_exact(&mut chunk)
+        // the dropped block is still referenced until the node is saved
+        let size = file_node.size();
+        let blocks = file_node.blocks().len() - kept;
+        self.edit_file_node(file_node_id, file_node).await;
+        for block_id in dropped
+            .into_iter()
+            .filter(|block_id| *block_id != node::HOLE)
+        {
+            self.delete_data_block(block_id).await;
+        }
+        self.charge_quota(quotas, size as i64 - old_size as i64)
+            .await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        progress
+            .println(format!(
+                "  Resumed at {} of {}",
+                HumanBytes(kept as u64 * chunk_size),
+                HumanBytes(filesize)
+            ))
+            .unwrap();
+        self.print_summary(
+            progress,
+            util::TransferSummary {
+                operation: "Uploaded",
+                bytes: size - kept as u64 * chunk_size,
+                blocks,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+
+        old_size
+    }
+
+    async fn __upload_tar(
+        &self,
+        source: String,
+        destination: FsPath,
+        mirror_structure: bool,
+        chunk_size: Size,
+        key: String,
+        progress: &MultiProgress,
+    ) {
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Archiving {source} to {destination}"));
+
+        // check source directory
+        assert!(
+            fs::metadata(&source)
                 .await
-                .expect("Error reading from file");
-            read_bytes += chunk_size as u64;
+                .expect("Failed to open directory")
+                .is_dir(),
+            "Source must be a directory"
+        );
+        let source_name =
+            util::local_file_name(&source).expect("Source directory must have a name");
 
-            let chunk = cypher
-                .encrypt(&nonce.get_nonce(), chunk.as_slice())
-                .expect("Failed to encrypt data");
+        let (file_path, file_name) = NodeFS::split_path(&destination, false, false);
 
-            let block_id = self.create_data_block(chunk).await;
-            file_node.push_data_block(block_id, chunk_size as u64);
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&file_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(file_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
 
-            progress_bar.inc(chunk_size);
+        // the archive is at least as large as the files in it, unless symlinks are archived as
+        // links instead of what they point to
+        let quotas = self.quota_directories(dir_node_id).await;
+        if !quotas.is_empty() && !mirror_structure {
+            let (sizes, _) = NodeFS::local_file_sizes(PathBuf::from(&source)).await;
+            NodeFS::check_quota(&quotas, sizes.values().map(|(size, _)| size).sum());
+        }
+
+        // create file node
+        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        file_node.chunk_size = chunk_size;
+        let staging_node_id = self.staging_directory().await;
+        self.stage_node(staging_node_id, file_node_id).await;
+
+        // show progress bar, the archive size is unknown upfront
+        let progress_bar = progress.add(util::stream_progress());
+
+        // build the archive in a blocking task and upload it while it's being written
+        let (reader, writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        let writer = SyncIo::new(writer);
+        let source_path = source.clone();
+        let archiver = tokio::task::spawn_blocking(move || {
+            // headers always carry modification times and permissions
+            let mut builder = tar::Builder::new(writer);
+            builder.follow_symlinks(!mirror_structure);
+            builder.append_dir_all(source_name, source_path)?;
+            builder.into_inner().map(|_| ())
+        });
+
+        self.upload_blocks(reader, &mut file_node, &key, &progress_bar)
+            .await;
+        archiver
+            .await
+            .expect("Archiving task panicked")
+            .expect("Failed to archive directory");
+
+        // the exact archive size is only known now, the upload is deleted again if it's too large
+        let size = file_node.size();
+        if let Some(err) = NodeFS::quota_exceeded(&quotas, size) {
+            self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+            self.delete_file(file_node, file_node_id, file_name, progress)
+                .await;
+            panic!("{err}");
         }
 
         // update nodes
+        let blocks = file_node.blocks().len();
+        self.edit_file_node(file_node_id, file_node).await;
+        self.unstage_nodes(staging_node_id, &[file_node_id]).await;
         dir_node.push_directory_entry(file_name, file_node_id);
         self.edit_directory_node(dir_node_id, dir_node).await;
-        self.edit_file_node(file_node_id, file_node).await;
+        self.charge_quota(&quotas, size as i64).await;
 
         // cleanup
         progress_bar.finish_and_clear();
         spinner.finish_with_message(format!("Finished uploading {source}"));
+        self.print_summary(
+            progress,
+            util::TransferSummary {
+                operation: "Uploaded",
+                bytes: size,
+                blocks,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
     }
 
-    pub async fn download(&self, source: String, destination: String, key: String) {
-        self.__download(source, destination, key, &MultiProgress::new())
+    pub async fn download(
+        &self,
+        source: FsPath,
+        destination: String,
+        untar: bool,
+        mirror_structure: bool,
+        options: DownloadOptions,
+        key: String,
+    ) {
+        let progress = MultiProgress::new();
+        if untar {
+            assert!(
+                options.on_conflict == OnConflict::Fail,
+                "Conflict policies are not supported when extracting archives"
+            );
+            self.__download_untar(
+                source,
+                destination,
+                mirror_structure,
+                options,
+                key,
+                &progress,
+            )
             .await
+        } else {
+            self.__download(source, destination, options, key, &progress)
+                .await
+        }
     }
 
     async fn __download(
         &self,
-        source: String,
+        source: FsPath,
         destination: String,
+        options: DownloadOptions,
         key: String,
         progress: &MultiProgress,
     ) {
@@ -182,81 +799,324 @@ impl NodeFS {
         let spinner = progress.add(util::spinner());
         spinner.set_message(format!("Downloading {source} to {destination}"));
 
+        // get source file
+        let (source_node, _) = self
+            .get_download_source(&source, options.follow_symlinks)
+            .await;
+
         // open destination file
-        let mut file = fs::File::create(destination)
+        let exists = fs::try_exists(&destination)
             .await
-            .expect("Failed to create file");
+            .expect("Failed to check destination");
+        let (file, first_block) = match options.on_conflict {
+            _ if !exists => (fs::File::create(&destination).await, 0),
+            OnConflict::Fail => {
+                failure::fail(Hint::NameExists, format!("{destination} already exists"))
+            }
+            OnConflict::Skip => {
+                spinner
+                    .finish_with_message(format!("Skipped {source}, {destination} already exists"));
+                return;
+            }
+            OnConflict::Overwrite => (fs::File::create(&destination).await, 0),
+            OnConflict::Rename => {
+                let path = Path::new(&destination);
+                let name = path
+                    .file_name()
+                    .expect("Destination must have a file name")
+                    .to_string_lossy();
+                let mut renamed = None;
+                for n in 1.. {
+                    let candidate = path.with_file_name(OnConflict::numbered_name(&name, n));
+                    if !fs::try_exists(&candidate)
+                        .await
+                        .expect("Failed to check destination")
+                    {
+                        renamed = Some(candidate);
+                        break;
+                    }
+                }
+                let renamed = renamed.unwrap();
+                progress
+                    .println(format!("  Downloading to {}", renamed.display()))
+                    .unwrap();
 
-        // get source file
-        let (source_node, _) = self.traverse_path(&source).await;
-        assert!(source_node.kind != Directory, "Can't download directories");
+                (fs::File::create(renamed).await, 0)
+            }
+            OnConflict::Resume => {
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&destination)
+                    .await
+                    .expect("Failed to open file");
+                let len = file
+                    .metadata()
+                    .await
+                    .expect("Failed to fetch destination file size")
+                    .len();
+                assert!(
+                    len <= source_node.size(),
+                    "{destination} is larger than {source}, it can't be resumed"
+                );
+
+                // a partial last block is downloaded again
+                let kept = (len / source_node.chunk_size) as usize;
+                file.set_len(kept as u64 * source_node.chunk_size)
+                    .await
+                    .expect("Failed to truncate file");
+                file.seek(SeekFrom::End(0))
+                    .await
+                    .expect("Failed to seek in file");
+
+                (Ok(file), kept)
+            }
+        };
+        let mut file = file.expect("Failed to create file");
 
         // show progress bar
-        let mut byte_progress = 0;
         let progress_bar = progress.add(util::progress_bar(source_node.size()));
-
-        // encrypt the uploaded data
-        let cypher =
-            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
-        let mut nonce = NonceCounter::new();
+        progress_bar.set_position(first_block as u64 * source_node.chunk_size);
 
         // read all data blocks and write them to the destination
-        for block_id in source_node.blocks() {
-            let block = self.get_data_block(*block_id).await;
+        let lost = self
+            .download_blocks_from(
+                &source_node,
+                first_block,
+                BlockFetch::from(&options),
+                &mut file,
+                &key,
+                &progress_bar,
+            )
+            .await;
 
-            // encrypt the uploaded data, using bot token as key
-            let block = cypher
-                .decrypt(&nonce.get_nonce(), block.as_slice())
-                .expect("Failed to decrypt data");
+        // cleanup
+        progress_bar.finish_and_clear();
+        self.report_lost_blocks(progress, &source, &lost);
+        spinner.finish_with_message(format!("Finished downloading {source}"));
+        self.print_summary(
+            progress,
+            util::TransferSummary {
+                operation: "Downloaded",
+                bytes: source_node.size() - first_block as u64 * source_node.chunk_size,
+                blocks: source_node.blocks().len() - first_block,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+    }
 
-            file.write_all(&block)
-                .await
-                .expect("Failed to write downloaded data");
+    // every block is authenticated when it's decrypted, so a run that finishes proves the key and
+    // that all blocks are intact and reachable
+    pub async fn download_discard(&self, source: FsPath, options: DownloadOptions, key: String) {
+        let progress = MultiProgress::new();
 
-            let chunk_size =
-                min(node::BLOCK_SIZE as u64, source_node.size() - byte_progress) as u64;
-            byte_progress += chunk_size;
-            progress_bar.inc(chunk_size);
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Verifying {source}"));
+
+        let (source_node, _) = self
+            .get_download_source(&source, options.follow_symlinks)
+            .await;
+
+        // show progress bar
+        let progress_bar = progress.add(util::progress_bar(source_node.size()));
+
+        let lost = self
+            .download_blocks_from(
+                &source_node,
+                0,
+                BlockFetch::from(&options),
+                &mut tokio::io::sink(),
+                &key,
+                &progress_bar,
+            )
+            .await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        self.report_lost_blocks(&progress, &source, &lost);
+        spinner.finish_with_message(if lost.is_empty() {
+            format!("Verified {source}, every block decrypted and authenticated")
+        } else {
+            format!("Verified {source}, {} blocks are lost", lost.len())
+        });
+        self.print_summary(
+            &progress,
+            util::TransferSummary {
+                operation: "Verified",
+                bytes: source_node.size(),
+                blocks: source_node.blocks().len(),
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+    }
+
+    async fn __download_untar(
+        &self,
+        source: FsPath,
+        destination: String,
+        mirror_structure: bool,
+        options: DownloadOptions,
+        key: String,
+        progress: &MultiProgress,
+    ) {
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Extracting {source} to {destination}"));
+
+        // get source file
+        let (source_node, _) = self
+            .get_download_source(&source, options.follow_symlinks)
+            .await;
+
+        // unpacking replaces existing files, so only new or empty destinations are accepted
+        if let Ok(mut entries) = fs::read_dir(&destination).await {
+            if entries
+                .next_entry()
+                .await
+                .expect("Failed to read destination")
+                .is_some()
+            {
+                failure::fail(Hint::NameExists, format!("{destination} is not empty"));
+            }
+        } else if fs::try_exists(&destination)
+            .await
+            .expect("Failed to check destination")
+        {
+            failure::fail(
+                Hint::NameExists,
+                format!("{destination} already exists and is not a directory"),
+            );
         }
 
+        // show progress bar
+        let progress_bar = progress.add(util::progress_bar(source_node.size()));
+
+        // extract the archive in a blocking task while it's being downloaded, the padding after
+        // its end is read as well so the stream stays open until the download finished
+        let (reader, mut writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        let reader = SyncIo::new(reader);
+        let mut extractor = tokio::task::spawn_blocking(move || {
+            let mut archive = tar::Archive::new(reader);
+            archive.set_preserve_permissions(mirror_structure);
+            let extracted = archive.unpack(destination);
+            let mut reader = archive.into_inner();
+            let extracted = extracted
+                .and_then(|_| std::io::copy(&mut reader, &mut std::io::sink()).map(|_| ()));
+            // the stream is handed back instead of closed, so after a failed extraction the
+            // download waits for room rather than failing to write
+            (extracted, reader)
+        });
+
+        // the error of a failed extraction is reported instead of the stalled download
+        let lost = tokio::select! {
+            biased;
+            extracted = &mut extractor => {
+                let (extracted, _) = extracted.expect("Extracting task panicked");
+                extracted.expect("Failed to extract archive");
+                unreachable!("The archive is read until the download closes it")
+            }
+            lost = self.download_blocks_from(
+                &source_node,
+                0,
+                BlockFetch::from(&options),
+                &mut writer,
+                &key,
+                &progress_bar,
+            ) => lost,
+        };
+
+        // closing the stream marks the end of the archive
+        drop(writer);
+        let (extracted, _) = extractor.await.expect("Extracting task panicked");
+        extracted.expect("Failed to extract archive");
+
         // cleanup
         progress_bar.finish_and_clear();
-        spinner.finish_with_message(format!("Finished downloading {source}"));
+        self.report_lost_blocks(progress, &source, &lost);
+        spinner.finish_with_message(format!("Finished extracting {source}"));
+        self.print_summary(
+            progress,
+            util::TransferSummary {
+                operation: "Downloaded",
+                bytes: source_node.size(),
+                blocks: source_node.blocks().len(),
+                elapsed: progress_bar.elapsed(),
+            },
+        );
     }
 
-    pub async fn rm(&self, path: String, quick: bool, recursive: bool) {
-        self.__rm(path, quick, recursive, &MultiProgress::new())
+    pub async fn rm(
+        &self,
+        path: FsPath,
+        quick: bool,
+        recursive: bool,
+        defer: bool,
+        parallel_dirs: usize,
+        key: String,
+    ) {
+        if defer {
+            return self.rm_deferred(path, recursive, key).await;
+        }
+
+        self.__rm(path, quick, recursive, parallel_dirs, &MultiProgress::new())
             .await
     }
 
-    async fn __rm(&self, path: String, quick: bool, recursive: bool, progress: &MultiProgress) {
+    async fn __rm(
+        &self,
+        path: FsPath,
+        quick: bool,
+        recursive: bool,
+        parallel_dirs: usize,
+        progress: &MultiProgress,
+    ) {
         // would be caught later but can give a nicer error here
-        assert!(path != "/", "Cannot delete root directory");
+        assert!(!path.is_root(), "Cannot delete root directory");
 
         // show progress informaton
         let spinner = progress.add(util::spinner());
         spinner.set_message(format!("Deleting {path}"));
 
-        let (_, file_name) = NodeFS::split_path(path.as_str(), true, false);
+        let (dir_path, file_name) = NodeFS::split_path(&path, true, false);
 
-        // get target directory
-        let (target_node, target_node_id) = self.traverse_path(path.as_str()).await;
-        let dir_node_id = target_node.parent_block_id;
-        let mut dir_node = self.get_directory_node(dir_node_id).await;
+        // get target directory, hard linked files don't know all of their parents
+        let (target_node, target_node_id) = self.traverse_path(&path).await;
+        let (mut dir_node, dir_node_id) = self.traverse_path(&dir_path).await;
 
         match target_node.kind {
-            Directory if !recursive => panic!("Directories must be deleted recursively"),
-            File if recursive => panic!("Files cannot be deleted recursively"),
+            Directory if !recursive => {
+                failure::fail(Hint::Recursive, "Directories must be deleted recursively")
+            }
+            File | Symlink if recursive => panic!("Files cannot be deleted recursively"),
             _ => {}
         }
 
-        // delete nodes and data blocks
-        if !quick {
+        // measure before the data is gone
+        let quotas = self.quota_directories(dir_node_id).await;
+        let size = match target_node.kind {
+            Directory if !quotas.is_empty() => {
+                self.disk_usage(self.get_directory_node(target_node_id).await)
+                    .await
+            }
+            File => target_node.size(),
+            _ => 0,
+        };
+
+        // delete nodes and data blocks
+        if !quick {
             if recursive {
-                self.delete_directory(target_node, target_node_id, file_name, progress)
-                    .await;
+                self.delete_directory(
+                    target_node,
+                    target_node_id,
+                    file_name,
+                    parallel_dirs,
+                    progress,
+                )
+                .await;
+            } else if target_node.kind == Symlink {
+                self.delete_block(target_node_id).await;
             } else {
-                self.delete_file(target_node, target_node_id, file_name, progress)
+                self.unlink_file(target_node, target_node_id, file_name, progress)
                     .await;
             }
         }
@@ -264,48 +1124,255 @@ impl NodeFS {
         // delete file directory entry
         dir_node.delete_directory_entry(file_name);
         self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, -(size as i64)).await;
 
         // cleanup
         spinner.finish_with_message(format!("Deleted {path}"));
     }
 
-    pub async fn mv(&self, source: String, destination: String) {
-        if source == destination {
-            return;
+    pub async fn mv(&self, sources: Vec<FsPath>, destination: FsPath, on_conflict: OnConflict) {
+        struct Move<'a> {
+            source: &'a FsPath,
+            node: Node,
+            node_id: BlockIndex,
+            name: String,
+            reparent: bool,
+            source_quotas: Vec<(String, Node, BlockIndex)>,
+            target_quotas: Vec<(String, Node, BlockIndex)>,
+            size: Size,
         }
-        assert!(source != "/", "Cannot move root directory");
+
+        let progress = MultiProgress::new();
 
         // show progress informaton
-        let spinner = util::spinner();
-        spinner.set_message(format!("Moving {source} to {destination}"));
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Moving {} entries to {destination}", sources.len()));
 
-        let (_, source_name) = NodeFS::split_path(source.as_str(), true, false);
-        let (source_node, source_node_id) = self.traverse_path(source.as_str()).await;
-        let mut source_parent_node = self.get_directory_node(source_node.parent_block_id).await;
-        let (mut target_node, target_node_id) = self.traverse_path(destination).await;
+        let (mut target_node, target_node_id) = self.traverse_path(&destination).await;
         assert!(target_node.kind == Directory, "Must move into a directory");
-        assert!(!target_node.is_full(), "The directory is full");
-        assert!(
-            !target_node.contains_entry(source_name),
-            "Destination directory already contains entry with the same name"
-        );
+        let target_quota_chain = self.quota_directories(target_node_id).await;
+
+        // every directory node is edited once, after all entries were moved in memory
+        let mut parents: Vec<(Node, BlockIndex)> = Vec::new();
+        let mut moves: Vec<Move> = Vec::new();
+        let mut replaced: Vec<(String, Node, BlockIndex)> = Vec::new();
+        for source in &sources {
+            assert!(!source.is_root(), "Cannot move root directory");
+
+            let (source_path, source_name) = NodeFS::split_path(source, true, false);
+            let (source_node, source_node_id) = self.traverse_path(source).await;
+
+            // a directory moved into its own subtree would be cut off from the root
+            if source_node.kind == Directory {
+                let is_ancestor = NodeFS::is_ancestor(
+                    source_node_id,
+                    target_node_id,
+                    self.root_node_id,
+                    |id| async move { self.get_directory_node(id).await.parent_block_id },
+                )
+                .await;
+                assert!(
+                    !is_ancestor,
+                    "Cannot move {source} into itself, {destination} is inside of it"
+                );
+            }
+
+            let (source_parent_node, source_parent_node_id) =
+                self.traverse_path(&source_path).await;
+            if source_parent_node_id == target_node_id {
+                progress
+                    .println(format!("  {source} is already in {destination}"))
+                    .unwrap();
+                continue;
+            }
+
+            let mut name = source_name.to_string();
+            if let Some(existing) = target_node.find_directory_entry(&name) {
+                let existing_name = existing.get_name().clone();
+                let existing_node_id = existing.block_id();
+                match on_conflict {
+                    OnConflict::Fail => {
+                        panic!("Destination directory already contains entry with the same name")
+                    }
+                    OnConflict::Resume => panic!("Moves can't be resumed"),
+                    OnConflict::Skip => {
+                        progress
+                            .println(format!(
+                                "  Skipping {source}, {destination}{existing_name} already exists"
+                            ))
+                            .unwrap();
+                        continue;
+                    }
+                    OnConflict::Rename => {
+                        name = (1..)
+                            .map(|n| OnConflict::numbered_name(source_name, n))
+                            .find(|name| !target_node.contains_entry(name))
+                            .unwrap();
+                    }
+                    OnConflict::Overwrite => {
+                        assert!(
+                            existing_node_id != source_node_id,
+                            "{source} and {destination}{existing_name} are the same"
+                        );
+                        assert!(
+                            !moves.iter().any(|entry| entry.node_id == existing_node_id),
+                            "Multiple sources are named {existing_name}"
+                        );
+
+                        // names only match within the same kind, directories must be empty like with POSIX mv
+                        let existing_node = self
+                            .try_get_node(existing_node_id)
+                            .await
+                            .unwrap_or_else(|err| {
+                                panic!("Node {existing_node_id} is corrupted: {err}")
+                            });
+                        assert!(
+                            existing_node.kind != Directory || existing_node.entries().is_empty(),
+                            "Cannot overwrite non-empty directory {destination}{existing_name}"
+                        );
+
+                        target_node.delete_directory_entry(&existing_name);
+                        replaced.push((existing_name, existing_node, existing_node_id));
+                    }
+                }
+            }
 
-        // move entry and save
-        source_parent_node.delete_directory_entry(source_name);
-        target_node.push_directory_entry(source_name, source_node_id);
-        self.edit_directory_node(source_node.parent_block_id, source_parent_node)
+            // only quotas that don't contain both directories change
+            let mut source_quotas = self.quota_directories(source_parent_node_id).await;
+            let mut target_quotas = target_quota_chain.clone();
+            let source_quota_ids: Vec<BlockIndex> =
+                source_quotas.iter().map(|(_, _, id)| *id).collect();
+            let target_quota_ids: Vec<BlockIndex> =
+                target_quotas.iter().map(|(_, _, id)| *id).collect();
+            source_quotas.retain(|(_, _, id)| !target_quota_ids.contains(id));
+            target_quotas.retain(|(_, _, id)| !source_quota_ids.contains(id));
+            let size = match source_node.kind {
+                Directory if !source_quotas.is_empty() || !target_quotas.is_empty() => {
+                    self.disk_usage(self.get_directory_node(source_node_id).await)
+                        .await
+                }
+                File => source_node.size(),
+                _ => 0,
+            };
+
+            // move entry in memory
+            failure::ensure(
+                !target_node.is_full(),
+                Hint::DirectoryFull,
+                "The directory is full",
+            );
+            target_node.push_directory_entry(&name, source_node_id);
+            match parents
+                .iter_mut()
+                .find(|(_, id)| *id == source_parent_node_id)
+            {
+                Some((parent_node, _)) => parent_node.delete_directory_entry(source_name),
+                None => {
+                    let mut parent_node = source_parent_node;
+                    parent_node.delete_directory_entry(source_name);
+                    parents.push((parent_node, source_parent_node_id));
+                }
+            }
+
+            moves.push(Move {
+                source,
+                reparent: source_node.parent_block_id == source_parent_node_id,
+                node: source_node,
+                node_id: source_node_id,
+                name,
+                source_quotas,
+                target_quotas,
+                size,
+            });
+        }
+
+        // freed space of overwritten entries makes room for what's moved in
+        let replaced_size: Size = replaced
+            .iter()
+            .filter(|(_, node, _)| node.kind == File)
+            .map(|(_, node, _)| node.size())
+            .sum();
+        for quota in &target_quota_chain {
+            let size: Size = moves
+                .iter()
+                .filter(|entry| entry.target_quotas.iter().any(|(_, _, id)| *id == quota.2))
+                .map(|entry| entry.size)
+                .sum();
+            NodeFS::check_quota(
+                std::slice::from_ref(quota),
+                size.saturating_sub(replaced_size),
+            );
+        }
+
+        // delete overwritten entries
+        for (name, node, node_id) in replaced {
+            match node.kind {
+                File => self.unlink_file(node, node_id, name, &progress).await,
+                _ => self.delete_block(node_id).await,
+            }
+        }
+        self.charge_quota(&target_quota_chain, -(replaced_size as i64))
             .await;
+
+        // keep the parent up to date for '..' in paths, a moved directory can also be a source parent
+        for entry in moves.iter_mut().filter(|entry| entry.reparent) {
+            match parents.iter_mut().find(|(_, id)| *id == entry.node_id) {
+                Some((parent_node, _)) => {
+                    parent_node.parent_block_id = target_node_id;
+                    entry.reparent = false;
+                }
+                None => entry.node.parent_block_id = target_node_id,
+            }
+        }
+
+        // save directories
+        for (parent_node, parent_node_id) in parents {
+            self.edit_directory_node(parent_node_id, parent_node).await;
+        }
         self.edit_directory_node(target_node_id, target_node).await;
 
+        let moved = moves.len();
+        for entry in moves {
+            self.charge_quota(&entry.source_quotas, -(entry.size as i64))
+                .await;
+            self.charge_quota(&entry.target_quotas, entry.size as i64)
+                .await;
+
+            if entry.reparent {
+                self.edit_node(entry.node_id, entry.node).await;
+            }
+
+            if entry.source.name() != entry.name {
+                progress
+                    .println(format!(
+                        "  Moved {} to {destination}{}",
+                        entry.source, entry.name
+                    ))
+                    .unwrap();
+            }
+        }
+
         // cleanup
-        spinner.finish_with_message(format!("Moved {source}"));
+        spinner.finish_with_message(format!(
+            "Moved {moved} of {} entries to {destination}",
+            sources.len()
+        ));
     }
 
-    pub async fn rename(&self, old: String, new: String) {
+    pub async fn rename(&self, old: FsPath, new: String) {
         assert!(new != "/", "New name must not only be a '/'");
 
+        // the new name has to be a valid single path segment
+        let new_path = FsPath::parse(&format!("/{new}"))
+            .unwrap_or_else(|err| panic!("Invalid new name '{new}': {err}"));
+        assert!(
+            new_path.segments().len() == 1,
+            "New name must not include a path"
+        );
+        let new = new_path.name().to_string();
+
         let slash_pos = new.chars().position(|ch| ch == '/');
-        if old.ends_with('/') {
+        if old.is_dir() {
             assert!(
                 slash_pos.unwrap() == new.len() - 1,
                 "New directory name must only have '/' at the end"
@@ -313,15 +1380,31 @@ impl NodeFS {
         } else {
             assert!(slash_pos.is_none(), "New file name must not end with '/'");
         }
+        if let Err(err) = DirectoryEntry::validate_name(&new) {
+            panic!("Invalid new name '{new}': {err}");
+        }
 
         // show progress information
         let spinner = util::spinner();
         spinner.set_message(format!("Renaming {old} to {new}"));
 
-        let (target_path, target_name) = NodeFS::split_path(old.as_str(), true, false);
+        let (target_path, target_name) = NodeFS::split_path(&old, true, false);
 
         // get target directory
-        let (mut dir_node, dir_node_id) = self.traverse_path(target_path).await;
+        let (mut dir_node, dir_node_id) = self.traverse_path(&target_path).await;
+
+        // renaming to a different case of the same name is fine in case insensitive mode
+        failure::ensure(
+            !dir_node.contains_entry(&new)
+                || dir_node
+                    .find_directory_entry(&new)
+                    .map(|entry| entry.block_id())
+                    == dir_node
+                        .find_directory_entry(target_name)
+                        .map(|entry| entry.block_id()),
+            Hint::NameExists,
+            format!("An entry named {new} already exists"),
+        );
 
         // rename entry and save
         dir_node.rename_directory_entry(target_name, new);
@@ -331,204 +1414,1188 @@ impl NodeFS {
         spinner.finish_with_message(format!("Renamed {old}"));
     }
 
-    pub async fn mkdir(&self, path: String) {
-        let (target_path, target_path_name) = NodeFS::split_path(path.as_str(), true, true);
+    pub async fn mkdir(&self, path: FsPath, parents: bool) {
+        if parents {
+            return self.mkdir_parents(path).await;
+        }
+
+        let (target_path, target_path_name) = NodeFS::split_path(&path, true, true);
 
         // show progress information
         let spinner = util::spinner();
         spinner.set_message(format!("Creating {path}"));
 
-        // get target directory
-        let (mut dir_node, dir_node_id) = self.traverse_path(target_path).await;
-        assert!(!dir_node.is_full(), "The directory is full");
-        assert!(
-            !dir_node.contains_entry(target_path_name),
-            "The file already exists"
-        );
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&target_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(target_path_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
+
+        let (_, new_dir_node_id) = self.create_directory_node(dir_node_id).await;
+
+        // add new directory
+        dir_node.push_directory_entry(target_path_name, new_dir_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+
+        // cleanup
+        spinner.finish_with_message(format!("Created {path}"));
+    }
+
+    // every directory on the way is read once, only the last existing one and the new ones are edited
+    async fn mkdir_parents(&self, path: FsPath) {
+        assert!(path.is_dir(), "Directories are required");
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Creating {path}"));
+
+        // find the deepest existing directory
+        let mut dir_node = self.get_root_directory_node().await;
+        let mut dir_node_id = self.root_node_id;
+        let mut segments = path.segments().iter();
+        let mut prefix = FsPath::root();
+        let mut missing = Vec::new();
+        for segment in segments.by_ref() {
+            prefix = prefix.join(segment);
+            if segment == "./" {
+                continue;
+            }
+            if segment == "../" {
+                if dir_node_id != self.root_node_id {
+                    dir_node_id = dir_node.parent_block_id;
+                    dir_node = self.get_directory_node(dir_node_id).await;
+                }
+                continue;
+            }
+
+            // symlinks are stored without a trailing '/'
+            let Some(entry) = dir_node
+                .find_directory_entry(segment)
+                .or_else(|| dir_node.find_directory_entry(segment.strip_suffix('/').unwrap()))
+            else {
+                missing.push(segment.as_str());
+                break;
+            };
+
+            let entry_node_id = entry.block_id();
+            let entry_node = self
+                .try_get_node(entry_node_id)
+                .await
+                .unwrap_or_else(|err| panic!("Node {entry_node_id} is corrupted: {err}"));
+            (dir_node, dir_node_id) = match entry_node.kind {
+                Directory => (entry_node, entry_node_id),
+                Symlink => self.resolve_path(&prefix).await,
+                File => panic!("{prefix} is not a directory"),
+            };
+        }
+        missing.extend(segments.map(String::as_str));
+
+        if missing.is_empty() {
+            spinner.finish_with_message(format!("{path} already exists"));
+            return;
+        }
+        assert!(
+            !missing.contains(&"./") && !missing.contains(&"../"),
+            "Cannot create '.' or '..' in {path}"
+        );
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+
+        // new directories are created top down as each one needs its parent's ID
+        let mut created = Vec::new();
+        let mut parent_node_id = dir_node_id;
+        for _ in &missing {
+            let (node, node_id) = self.create_directory_node(parent_node_id).await;
+            created.push((node, node_id));
+            parent_node_id = node_id;
+        }
+
+        // link every new directory into its parent
+        dir_node.push_directory_entry(missing[0], created[0].1);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        let child_node_ids: Vec<BlockIndex> = created.iter().skip(1).map(|(_, id)| *id).collect();
+        for (((mut node, node_id), name), child_node_id) in created
+            .into_iter()
+            .zip(missing.iter().skip(1))
+            .zip(child_node_ids)
+        {
+            node.push_directory_entry(name, child_node_id);
+            self.edit_directory_node(node_id, node).await;
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Created {path} ({} new directories)",
+            missing.len()
+        ));
+    }
+
+    pub async fn ln(&self, target: String, link: FsPath, symbolic: bool) {
+        let (link_path, link_name) = NodeFS::split_path(&link, false, false);
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Linking {link} to {target}"));
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&link_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(link_name)
+                && !dir_node.contains_entry(format!("{link_name}/")),
+            Hint::NameExists,
+            "The file already exists",
+        );
+
+        // every hard link counts towards the quotas it's in
+        let quotas = self.quota_directories(dir_node_id).await;
+        let mut size = 0;
+        let link_node_id = if symbolic {
+            // dangling symlinks are allowed, the target is only resolved when the symlink is used
+            let (_, symlink_node_id) = self.create_symlink_node(dir_node_id, &target).await;
+            symlink_node_id
+        } else {
+            assert!(
+                self.format_version >= 2,
+                "Hard links require format v2, run 'migrate --to v2' first"
+            );
+
+            // only files can be hard linked, the link shares the file node
+            let target = FsPath::parse(&target)
+                .unwrap_or_else(|err| panic!("Invalid target path '{target}': {err}"));
+            let (mut file_node, file_node_id) = self.traverse_path(&target).await;
+            assert!(file_node.kind == File, "Only files can be hard linked");
+            size = file_node.size();
+            NodeFS::check_quota(&quotas, size);
+
+            file_node.links += 1;
+            self.edit_file_node(file_node_id, file_node).await;
+            file_node_id
+        };
+
+        // add new link
+        dir_node.push_directory_entry(link_name, link_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, size as i64).await;
+
+        // cleanup
+        spinner.finish_with_message(format!("Linked {link} to {target}"));
+    }
+
+    pub async fn case_insensitive(&mut self, enable: bool) {
+        if enable == self.case_insensitive {
+            println!(
+                "  Case insensitive lookup is already {}",
+                if enable { "enabled" } else { "disabled" }
+            );
+            return;
+        }
+
+        if enable {
+            // show progress information
+            let spinner = util::spinner();
+            spinner.set_message(String::from("Checking for names that only differ by case"));
+
+            // names that only differ by case would become unaddressable
+            let mut conflicts = Vec::new();
+            let mut stack = vec![(
+                FsPath::root(),
+                self.get_root_directory_node().await,
+                self.root_node_id,
+                0,
+            )];
+            let mut visited = Default::default();
+            while let Some((path, dir_node, dir_node_id, depth)) = stack.pop() {
+                if let Err(err) = self.check_traversal(&mut visited, dir_node_id, depth) {
+                    panic!("Stopped at {path}, it's {err}");
+                }
+                let mut names: Vec<(String, &str)> = dir_node
+                    .entries()
+                    .iter()
+                    .map(|entry| (entry.get_name().to_lowercase(), entry.get_name().as_str()))
+                    .collect();
+                names.sort();
+                for pair in names.windows(2) {
+                    if pair[0].0 == pair[1].0 {
+                        conflicts.push(format!("{path} contains {} and {}", pair[0].1, pair[1].1));
+                    }
+                }
+
+                for entry in dir_node.entries() {
+                    if let Ok(entry_node) = self.try_get_node(entry.block_id()).await
+                        && entry_node.kind == Directory
+                    {
+                        stack.push((
+                            path.join(entry.get_name()),
+                            entry_node,
+                            entry.block_id(),
+                            depth + 1,
+                        ));
+                    }
+                }
+            }
+
+            // cleanup
+            spinner.finish_and_clear();
+
+            if !conflicts.is_empty() {
+                for conflict in &conflicts {
+                    println!("  {conflict}");
+                }
+                panic!(
+                    "{} names only differ by case, rename them before enabling case insensitive lookup",
+                    conflicts.len()
+                );
+            }
+        }
+
+        self.case_insensitive = enable;
+        self.save_superblock().await;
+
+        println!(
+            "  {} case insensitive lookup",
+            if enable { "Enabled" } else { "Disabled" }
+        );
+    }
+
+    // like the shell 'test', a missing trailing '/' doesn't matter and symlinks are followed
+    pub async fn exists(&self, path: FsPath, kind: Option<NodeKind>) -> bool {
+        let dir_path = if path.is_dir() {
+            path.clone()
+        } else {
+            FsPath::parse(&format!("{path}/")).expect("Path should stay valid with a trailing '/'")
+        };
+
+        match kind {
+            Some(Directory) => self.path_exists(&dir_path).await,
+            Some(kind) => {
+                !path.is_dir()
+                    && matches!(
+                        self.try_lookup_path(&path, true).await,
+                        Ok(Some((node, _))) if node.kind == kind
+                    )
+            }
+            None => {
+                (!path.is_dir() && self.path_exists(&path).await)
+                    || self.path_exists(&dir_path).await
+            }
+        }
+    }
+
+    // a path that can't be resolved, e.g. through a file, doesn't exist either
+    async fn path_exists(&self, path: &FsPath) -> bool {
+        matches!(self.try_lookup_path(path, true).await, Ok(Some(_)))
+    }
+
+    pub async fn info(&self, stats: bool) {
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(String::from("Fetching root node"));
+
+        let root_node = self.get_root_directory_node().await;
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        println!("  DFS {}", env!("CARGO_PKG_VERSION"));
+        println!(
+            "  Format version    v{} (newest supported v{})",
+            self.format_version,
+            node::FORMAT_VERSION
+        );
+        println!(
+            "  Root node         {} (stored in the superblock)",
+            self.root_node_id
+        );
+        println!(
+            "  Root entries      {} / {}",
+            HumanCount(root_node.size()),
+            HumanCount(node::ENTRY_COUNT as u64)
+        );
+        println!("  Data channel      {}", self.data_channel.get());
+        println!(
+            "  Block size        {} ({})",
+            HumanBytes(node::BLOCK_SIZE as u64),
+            HumanCount(node::BLOCK_SIZE as u64)
+        );
+        println!(
+            "  Max file size     {} ({})",
+            HumanBytes(node::MAX_FILE_SIZE as u64),
+            HumanCount(node::MAX_FILE_SIZE as u64)
+        );
+        println!(
+            "  Max name length   {}",
+            HumanCount(directory_entry::NAME_LEN as u64)
+        );
+        println!(
+            "  Name lookup       {}",
+            if self.case_insensitive {
+                "case insensitive (case preserving)"
+            } else {
+                "case sensitive"
+            }
+        );
+        println!("  Encryption        AES-256-GCM-SIV (counter nonces)");
+        match &self.erasure {
+            Some(erasure) => println!(
+                "  Erasure coding    {} data + {} parity shards over {} channels",
+                erasure.data,
+                erasure.parity,
+                erasure.channels.len()
+            ),
+            None => println!("  Erasure coding    none"),
+        }
+        match &self.cache_dir {
+            Some(cache_dir) => println!(
+                "  Cache             {} (nodes, revalidated on every read)",
+                cache_dir.display()
+            ),
+            None => println!("  Cache             none"),
+        }
+
+        if stats {
+            println!();
+            self.print_stats();
+        }
+    }
+}
+
+impl NodeFS {
+    // returns the size of everything below, with 'lines' the output is collected and every
+    // directory shows its total size, hard linked files count once per link
+    //
+    // the tree is walked with an explicit stack, so corrupted trees can't overflow it
+    async fn __list(
+        &self,
+        indent: usize,
+        curr_name: &str,
+        curr_dir: Node,
+        curr_dir_id: BlockIndex,
+        page: &ListPage,
+        mut lines: Option<&mut Vec<String>>,
+    ) -> Size {
+        enum Step {
+            Node(usize, String, Node, BlockIndex),
+            Entry(usize, String, BlockIndex),
+            // a directory is done, its total is added to the enclosing one
+            Total,
+        }
+
+        let print = |lines: &mut Option<&mut Vec<String>>, line: String| match lines {
+            Some(lines) => lines.push(line),
+            None => println!("{line}"),
+        };
+
+        // one (line, total) per open directory below the outermost total
+        let mut totals: Vec<(usize, Size)> = vec![(0, 0)];
+        let mut visited = HashSet::new();
+        let mut stack = vec![Step::Node(
+            indent,
+            curr_name.to_string(),
+            curr_dir,
+            curr_dir_id,
+        )];
+        while let Some(step) = stack.pop() {
+            let (indent, curr_name, curr_dir, curr_dir_id) = match step {
+                Step::Node(indent, name, node, node_id) => (indent, name, node, node_id),
+                Step::Entry(indent, name, node_id) => {
+                    // show progress information
+                    let spinner = util::spinner();
+                    spinner.set_message(format!("{:indent$}Fetching {name}", ""));
+
+                    let entry_node = self.try_get_node(node_id).await;
+
+                    // cleanup
+                    spinner.finish_and_clear();
+
+                    match entry_node {
+                        Ok(entry_node) => (indent, name, entry_node, node_id),
+                        Err(err) => {
+                            print(
+                                &mut lines,
+                                format!(
+                                    "  {:indent$}{name} - - - - - - - corrupted node {node_id}: {err}",
+                                    ""
+                                ),
+                            );
+                            continue;
+                        }
+                    }
+                }
+                Step::Total => {
+                    let (line_pos, total) = totals.pop().unwrap();
+                    if let Some(lines) = lines.as_deref_mut() {
+                        lines[line_pos].push_str(&format!(", {} total", HumanBytes(total)));
+                    }
+                    totals.last_mut().unwrap().1 += total;
+                    continue;
+                }
+            };
+
+            if curr_dir.kind == Symlink {
+                print(
+                    &mut lines,
+                    format!("  {:indent$}{curr_name} -> {}", "", curr_dir.target()),
+                );
+                continue;
+            }
+
+            let count = match curr_dir.kind {
+                Directory if curr_dir.quota > 0 => format!(
+                    "{} entries, {} of {} quota used",
+                    HumanCount(curr_dir.size()),
+                    HumanBytes(curr_dir.used),
+                    HumanBytes(curr_dir.quota)
+                ),
+                Directory => format!("{} entries", HumanCount(curr_dir.size())),
+                File if curr_dir.links > 1 => format!(
+                    "{} ({}), {} links",
+                    HumanBytes(curr_dir.size()),
+                    HumanCount(curr_dir.size()),
+                    curr_dir.links
+                ),
+                File | Symlink => format!(
+                    "{} ({})",
+                    HumanBytes(curr_dir.size()),
+                    HumanCount(curr_dir.size())
+                ),
+            };
+
+            if curr_dir.kind == File {
+                print(
+                    &mut lines,
+                    format!("  {:indent$}{curr_name} - - - - - - - {count}", ""),
+                );
+                totals.last_mut().unwrap().1 += curr_dir.size();
+                continue;
+            }
+
+            // a directory can only be entered once, unless the tree has a cycle
+            if let Err(err) = self.check_traversal(&mut visited, curr_dir_id, totals.len() - 1) {
+                print(
+                    &mut lines,
+                    format!("  {:indent$}{curr_name} - - - - - - - {err}", ""),
+                );
+                continue;
+            }
+
+            // the page only selects the entries of the listed directory
+            let entries: Vec<&DirectoryEntry> = if totals.len() == 1 {
+                NodeFS::page_entries(curr_dir.entries(), page).0
+            } else {
+                curr_dir.entries().iter().collect()
+            };
+
+            // the directory line is filled in once its total size is known
+            let line = format!("  {:indent$}{curr_name} - - - - - - - {count}", "");
+            let line_pos = match lines.as_deref_mut() {
+                Some(lines) => {
+                    lines.push(line);
+                    lines.len() - 1
+                }
+                None => {
+                    println!("{line}");
+                    0
+                }
+            };
+            totals.push((line_pos, 0));
+
+            // entries are popped in order, every entry is indented one further than the one before
+            stack.push(Step::Total);
+            for (pos, entry) in entries.into_iter().enumerate().rev() {
+                stack.push(Step::Entry(
+                    indent + pos + 1,
+                    entry.get_name().clone(),
+                    entry.block_id(),
+                ));
+            }
+        }
+
+        totals[0].1
+    }
+
+    // guards walks over directory trees, corruption can make a directory its own descendant
+    fn check_traversal(
+        &self,
+        visited: &mut HashSet<BlockIndex>,
+        dir_node_id: BlockIndex,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > self.max_depth {
+            return Err(format!(
+                "deeper than {} levels, possible cycle detected (raise DFS_MAX_DEPTH if it's intended)",
+                self.max_depth
+            ));
+        }
+        if !visited.insert(dir_node_id) {
+            return Err(format!(
+                "possible cycle detected, directory node {dir_node_id} was already visited"
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_file<S: AsRef<str>>(
+        &self,
+        node: Node,
+        node_id: BlockIndex,
+        name: S,
+        progress: &MultiProgress,
+    ) {
+        assert!(
+            node.kind == File,
+            "Attempt to delete non file node as file node"
+        );
+
+        let spinner = progress.add(util::file_delete_progress(node.blocks().len() as u64));
+        spinner.set_message(name.as_ref().to_string());
+
+        // delete file data blocks, holes have no message
+        for block_id in node.blocks() {
+            if *block_id != node::HOLE {
+                self.delete_data_block(*block_id).await;
+            }
+
+            spinner.inc(1);
+        }
+
+        // delete file node
+        self.delete_block(node_id).await;
+
+        progress.remove(&spinner);
+    }
+
+    // deletes the file once the last hard link to it is removed
+    async fn unlink_file<S: AsRef<str>>(
+        &self,
+        node: Node,
+        node_id: BlockIndex,
+        name: S,
+        progress: &MultiProgress,
+    ) {
+        // parallel deletes can unlink the same file, the count is read again under the lock
+        let node = if node.links > 1 {
+            let _guard = self.unlink_lock.lock().await;
+            let mut node = self.get_file_node(node_id).await;
+            if node.links > 1 {
+                node.links -= 1;
+                self.edit_file_node(node_id, node).await;
+                return;
+            }
+            node
+        } else {
+            node
+        };
+
+        self.delete_file(node, node_id, name, progress).await;
+    }
+
+    // with 'parallel' above one the subdirectories are deleted by concurrent tasks, they
+    // don't share directory nodes so every node is still written by a single task
+    async fn delete_directory<S: AsRef<str>>(
+        &self,
+        node: Node,
+        node_id: BlockIndex,
+        name: S,
+        parallel: usize,
+        progress: &MultiProgress,
+    ) {
+        assert!(
+            node.kind == Directory,
+            "Attempt to delete non directory node as directory node"
+        );
+
+        let name = name.as_ref().to_string();
+        let visited = Mutex::new(HashSet::new());
+        if parallel <= 1 {
+            return self
+                .delete_subtree(node, node_id, name, 0, &visited, progress)
+                .await;
+        }
+
+        if let Err(err) = self.check_traversal(&mut visited.lock().unwrap(), node_id, 0) {
+            panic!("Stopped deleting at {name}, it's {err}");
+        }
+        let mut subtrees = Vec::new();
+        for directory_entry in node.entries() {
+            subtrees.extend(self.delete_entry(directory_entry, &name, progress).await);
+        }
+        stream::iter(subtrees)
+            .map(|(node, node_id, name)| {
+                self.delete_subtree(node, node_id, name, 1, &visited, progress)
+            })
+            .buffer_unordered(parallel)
+            .collect::<Vec<()>>()
+            .await;
+
+        self.delete_block(node_id).await;
+    }
+
+    async fn delete_subtree(
+        &self,
+        node: Node,
+        node_id: BlockIndex,
+        name: String,
+        depth: usize,
+        visited: &Mutex<HashSet<BlockIndex>>,
+        progress: &MultiProgress,
+    ) {
+        // a directory node is deleted after everything inside of it, the stack replaces recursion
+        enum Step {
+            Directory(Node, BlockIndex, String, usize),
+            Delete(BlockIndex),
+        }
+
+        let mut stack = vec![Step::Directory(node, node_id, name, depth)];
+        while let Some(step) = stack.pop() {
+            let (node, node_id, name, depth) = match step {
+                Step::Directory(node, node_id, name, depth) => (node, node_id, name, depth),
+                Step::Delete(node_id) => {
+                    self.delete_block(node_id).await;
+                    continue;
+                }
+            };
+            if let Err(err) = self.check_traversal(&mut visited.lock().unwrap(), node_id, depth) {
+                panic!("Stopped deleting at {name}, it's {err}");
+            }
+            stack.push(Step::Delete(node_id));
+
+            // delete all directory contents
+            for directory_entry in node.entries() {
+                if let Some((entry_node, entry_node_id, curr_name)) =
+                    self.delete_entry(directory_entry, &name, progress).await
+                {
+                    stack.push(Step::Directory(
+                        entry_node,
+                        entry_node_id,
+                        curr_name,
+                        depth + 1,
+                    ));
+                }
+            }
+        }
+    }
+
+    // deletes files and symlinks right away, directories are returned to be walked
+    async fn delete_entry(
+        &self,
+        directory_entry: &DirectoryEntry,
+        dir_name: &str,
+        progress: &MultiProgress,
+    ) -> Option<(Node, BlockIndex, String)> {
+        let entry_node_id = directory_entry.block_id();
+        let curr_name = format!("{dir_name}{}", directory_entry.get_name());
+
+        // without a readable node its data blocks can't be found
+        let entry_node = match self.try_get_node(entry_node_id).await {
+            Ok(entry_node) => entry_node,
+            Err(err) => {
+                self.record_failure(
+                    progress,
+                    curr_name,
+                    format!("Corrupted node {entry_node_id}, its data is left behind: {err}"),
+                );
+                return None;
+            }
+        };
+
+        match entry_node.kind {
+            Directory => return Some((entry_node, entry_node_id, curr_name)),
+            File => {
+                self.unlink_file(entry_node, entry_node_id, curr_name, progress)
+                    .await
+            }
+            // symlinks have no data blocks
+            Symlink => self.delete_block(entry_node_id).await,
+        }
+
+        None
+    }
+
+    async fn upload_blocks<R: AsyncRead + Unpin>(
+        &self,
+        reader: R,
+        file_node: &mut Node,
+        key: &str,
+        progress_bar: &ProgressBar,
+    ) {
+        self.upload_blocks_saving(reader, file_node, None, key, progress_bar)
+            .await
+    }
+
+    // with the node ID the node is saved every few blocks, so the upload can be resumed
+    async fn upload_blocks_saving<R: AsyncRead + Unpin>(
+        &self,
+        mut reader: R,
+        file_node: &mut Node,
+        checkpoint: Option<BlockIndex>,
+        key: &str,
+        progress_bar: &ProgressBar,
+    ) {
+        self.fit_chunk_size(file_node, progress_bar).await;
+
+        // encrypt the uploaded data, resumed uploads continue with the next nonce
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+        let mut nonce = NonceCounter::starting_at(file_node.blocks().len() as u64);
+
+        loop {
+            let mut chunk = Vec::with_capacity(file_node.chunk_size as usize);
+            (&mut reader)
+                .take(file_node.chunk_size)
+                .read_to_end(&mut chunk)
+                .await
+                .expect("Error reading from source");
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk_size = chunk.len() as u64;
+
+            // holes still use up their nonce so the following blocks keep theirs
+            let nonce = nonce.get_nonce();
+            if self.format_version >= 5 && chunk.iter().all(|byte| *byte == 0) {
+                file_node.push_data_block(node::HOLE, chunk_size);
+                progress_bar.inc(chunk_size);
+                continue;
+            }
+
+            let chunk = cypher
+                .encrypt(&nonce, chunk.as_slice())
+                .expect("Failed to encrypt data");
+
+            let block_id = self.create_data_block(chunk).await;
+            file_node.push_data_block(block_id, chunk_size);
+
+            progress_bar.inc(chunk_size);
+            if let Some(file_node_id) = checkpoint
+                && file_node.blocks().len().is_multiple_of(CHECKPOINT_BLOCKS)
+            {
+                self.edit_file_node(file_node_id, file_node.clone()).await;
+            }
+        }
+    }
+
+    async fn download_blocks<W: AsyncWrite + Unpin>(
+        &self,
+        file_node: &Node,
+        writer: &mut W,
+        key: &str,
+        progress_bar: &ProgressBar,
+    ) {
+        self.download_blocks_from(
+            file_node,
+            0,
+            BlockFetch::default(),
+            writer,
+            key,
+            progress_bar,
+        )
+        .await;
+    }
+
+    // up to `readahead` following blocks are fetched while the current one is written
+    //
+    // returns the blocks that were filled with zeros as (index, byte offset, size), there are only
+    // any with `allow_holes`
+    async fn download_blocks_from<W: AsyncWrite + Unpin>(
+        &self,
+        file_node: &Node,
+        first_block: usize,
+        fetch: BlockFetch,
+        writer: &mut W,
+        key: &str,
+        progress_bar: &ProgressBar,
+    ) -> Vec<(usize, Size, Size)> {
+        // decrypt the downloaded data
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+        let mut nonce = NonceCounter::starting_at(first_block as u64);
+
+        let mut lost = Vec::new();
+        let mut blocks = stream::iter(file_node.blocks().iter().enumerate().skip(first_block))
+            .map(|(idx, block_id)| async move {
+                let block = if *block_id == node::HOLE {
+                    None
+                } else if fetch.allow_holes {
+                    Some(self.try_get_data_block(*block_id).await)
+                } else {
+                    Some(Ok(self.get_data_block(*block_id).await))
+                };
+                (idx, block)
+            })
+            .buffered(fetch.readahead + 1);
+        while let Some((idx, block)) = blocks.next().await {
+            let nonce = nonce.get_nonce();
+            let block = match block {
+                Some(Ok(block)) => match cypher.decrypt(&nonce, block.as_slice()) {
+                    Ok(block) => Some(block),
+                    Err(_) if fetch.allow_holes => None,
+                    Err(_) => failure::fail(Hint::WrongKey, "Failed to decrypt data"),
+                },
+                Some(Err(_)) => None,
+                None => Some(vec![0; file_node.block_size(idx) as usize]),
+            };
+            let block = block.unwrap_or_else(|| {
+                let size = file_node.block_size(idx);
+                lost.push((idx, idx as Size * file_node.chunk_size, size));
+                vec![0; size as usize]
+            });
+
+            writer
+                .write_all(&block)
+                .await
+                .expect("Failed to write downloaded data");
 
-        let (_, new_dir_node_id) = self.create_directory_node(dir_node_id).await;
+            progress_bar.inc(block.len() as u64);
+        }
 
-        // add new directory
-        dir_node.push_directory_entry(target_path_name, new_dir_node_id);
-        self.edit_directory_node(dir_node_id, dir_node).await;
+        writer
+            .flush()
+            .await
+            .expect("Failed to write downloaded data");
 
-        // cleanup
-        spinner.finish_with_message(format!("Created {path}"));
+        lost
     }
-}
 
-impl NodeFS {
-    async fn __list(&self, mut indent: usize, curr_name: &str, curr_dir: Node) {
-        let count = match curr_dir.kind {
-            Directory => format!("{} entries", HumanCount(curr_dir.size())),
-            File => format!(
-                "{} ({})",
-                HumanBytes(curr_dir.size()),
-                HumanCount(curr_dir.size())
-            ),
-        };
+    fn report_lost_blocks(
+        &self,
+        progress: &MultiProgress,
+        source: &FsPath,
+        lost: &[(usize, Size, Size)],
+    ) {
+        for (idx, offset, size) in lost {
+            self.record_failure(
+                progress,
+                source.to_string(),
+                format!(
+                    "Block {idx} couldn't be read, bytes {offset}..{} are filled with zeros",
+                    offset + size
+                ),
+            );
+        }
+    }
 
-        println!("  {:indent$}{curr_name} - - - - - - - {count}", "");
+    fn metadata_path(name: &str) -> FsPath {
+        FsPath::root().join(METADATA_DIR_NAME).join(name)
+    }
 
-        if curr_dir.kind == File {
+    async fn ensure_metadata_directory(&self) {
+        let mut root_node = self.get_root_directory_node().await;
+        if root_node.contains_entry(METADATA_DIR_NAME) {
             return;
         }
+        assert!(!root_node.is_full(), "The root directory is full");
 
-        // recursively list directory hierarchy
-        for entry in curr_dir.entries() {
-            indent += 1;
-            // show progress information
-            let spinner = util::spinner();
-            spinner.set_message(format!("{:indent$}Fetching {}", "", entry.get_name()));
+        let (_, dir_node_id) = self.create_directory_node(self.root_node_id).await;
+        root_node.push_directory_entry(METADATA_DIR_NAME, dir_node_id);
+        self.edit_directory_node(self.root_node_id, root_node).await;
+    }
 
-            let entry_node = self.get_node(entry.block_id()).await;
+    // reads a whole (small) file into memory
+    async fn read_file_bytes(&self, path: &FsPath, key: &str) -> Option<Vec<u8>> {
+        let (file_node, _) = self.lookup_path(path, false).await?;
+        assert!(file_node.kind == File, "{path} is not a file");
 
-            // cleanup
-            spinner.finish_and_clear();
+        let mut data = Vec::with_capacity(file_node.size() as usize);
+        self.download_blocks(&file_node, &mut data, key, &ProgressBar::hidden())
+            .await;
 
-            Box::pin(self.__list(indent, entry.get_name().as_str(), entry_node)).await;
-        }
+        Some(data)
     }
 
-    async fn delete_file<S: AsRef<str>>(
+    // metadata file of the filesystem with the given root, which may be another one in the channel
+    async fn read_metadata_file_of(
         &self,
-        node: Node,
-        node_id: BlockIndex,
-        name: S,
-        progress: &MultiProgress,
-    ) {
-        assert!(
-            node.kind == File,
-            "Attempt to delete non file node as file node"
-        );
+        root_node_id: BlockIndex,
+        name: &str,
+        key: &str,
+    ) -> Option<Vec<u8>> {
+        let root_node = self.get_directory_node(root_node_id).await;
+        let metadata_node_id = root_node
+            .find_directory_entry(METADATA_DIR_NAME)?
+            .block_id();
+        let file_node_id = self
+            .get_directory_node(metadata_node_id)
+            .await
+            .find_directory_entry(name)?
+            .block_id();
+        let file_node = self
+            .try_get_node(file_node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Node {file_node_id} is corrupted: {err}"));
+        assert!(file_node.kind == File, "Metadata file {name} is not a file");
 
-        let spinner = progress.add(util::file_delete_progress(node.blocks().len() as u64));
-        spinner.set_message(name.as_ref().to_string());
+        let mut data = Vec::with_capacity(file_node.size() as usize);
+        self.download_blocks(&file_node, &mut data, key, &ProgressBar::hidden())
+            .await;
 
-        // delete file data blocks
-        for block_id in node.blocks() {
-            self.delete_block(*block_id).await;
+        Some(data)
+    }
 
-            spinner.inc(1);
+    // creates or replaces a (small) file, the old file is only deleted once the new one is linked
+    async fn write_file_bytes(&self, path: &FsPath, data: &[u8], key: &str) {
+        let (dir_path, file_name) = NodeFS::split_path(path, false, false);
+        let (mut dir_node, dir_node_id) = self.traverse_path(&dir_path).await;
+        let old_file_node_id = dir_node
+            .find_directory_entry(file_name)
+            .map(|entry| entry.block_id());
+        if old_file_node_id.is_none() {
+            failure::ensure(
+                !dir_node.is_full(),
+                Hint::DirectoryFull,
+                "The directory is full",
+            );
         }
 
-        // delete file node
-        self.delete_block(node_id).await;
+        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        self.upload_blocks(data, &mut file_node, key, &ProgressBar::hidden())
+            .await;
+        self.edit_file_node(file_node_id, file_node).await;
 
-        progress.remove(&spinner);
+        if old_file_node_id.is_some() {
+            dir_node.delete_directory_entry(file_name);
+        }
+        dir_node.push_directory_entry(file_name, file_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+
+        if let Some(old_file_node_id) = old_file_node_id {
+            let old_file_node = self.get_file_node(old_file_node_id).await;
+            self.delete_file(
+                old_file_node,
+                old_file_node_id,
+                file_name,
+                &MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            )
+            .await;
+        }
     }
 
-    async fn delete_directory<S: AsRef<str>>(
+    async fn get_download_source(
         &self,
-        node: Node,
-        node_id: BlockIndex,
-        name: S,
-        progress: &MultiProgress,
-    ) {
+        source: &FsPath,
+        follow_symlinks: bool,
+    ) -> (Node, BlockIndex) {
+        let (source_node, source_node_id) = if follow_symlinks {
+            self.resolve_path(source).await
+        } else {
+            self.traverse_path(source).await
+        };
+        assert!(source_node.kind != Directory, "Can't download directories");
         assert!(
-            node.kind == Directory,
-            "Attempt to delete non directory node as directory node"
+            source_node.kind != Symlink,
+            "{source} is a symlink to {}, use '--follow-symlinks' to download its target",
+            source_node.target()
         );
 
-        // delete all directory contents (recursively)
-        for directory_entry in node.entries() {
-            let entry_node_id = directory_entry.block_id();
-            let entry_node = self.get_node(entry_node_id).await;
+        (source_node, source_node_id)
+    }
 
-            let curr_name = format!("{}{}", name.as_ref(), directory_entry.get_name());
+    fn print_summary(&self, progress: &MultiProgress, summary: util::TransferSummary) {
+        self.record_transfer(&summary);
 
-            match entry_node.kind {
-                Directory => {
-                    Box::pin(self.delete_directory(entry_node, entry_node_id, curr_name, progress))
-                        .await;
-                }
-                File => {
-                    self.delete_file(entry_node, entry_node_id, curr_name, progress)
-                        .await;
-                }
-            }
+        // JSON goes to stdout, progress is drawn on stderr
+        if self.json {
+            println!("{}", summary.to_json());
+        } else {
+            progress.println(format!("  {summary}")).unwrap();
         }
+    }
 
-        // delete directory node
-        self.delete_block(node_id).await;
+    // name to create in the directory, None if the entry should be skipped
+    fn conflict_name(dir_node: &Node, name: &str, on_conflict: OnConflict) -> Option<String> {
+        // checked before anything is uploaded for the entry
+        if let Err(err) = DirectoryEntry::validate_name(name) {
+            panic!("Invalid name '{name}': {err}");
+        }
+
+        if !dir_node.contains_entry(name) {
+            return Some(name.to_string());
+        }
+
+        match on_conflict {
+            OnConflict::Fail => failure::fail(Hint::NameExists, "The file already exists"),
+            OnConflict::Skip => None,
+            OnConflict::Overwrite | OnConflict::Resume => Some(name.to_string()),
+            OnConflict::Rename => (1..)
+                .map(|n| OnConflict::numbered_name(name, n))
+                .find(|name| !dir_node.contains_entry(name)),
+        }
     }
 
-    fn split_path(path: &str, allow_dirs: bool, require_dir: bool) -> (&str, &str) {
+    fn split_path(path: &FsPath, allow_dirs: bool, require_dir: bool) -> (FsPath, &str) {
         if require_dir {
             assert!(allow_dirs, "Directories required but not allowed");
         }
         if !allow_dirs {
-            assert!(!path.ends_with('/'), "Directories not allowed");
+            assert!(!path.is_dir(), "Directories not allowed");
         }
         if require_dir {
-            assert!(path.ends_with('/'), "Directories are required");
+            assert!(path.is_dir(), "Directories are required");
         }
+        assert!(!path.is_root(), "Target path must have trailing filename");
 
-        // ignore trailing '/' for dirs to find parent folder
-        let bound = if require_dir || (allow_dirs && path.ends_with('/')) {
-            path.len() - 1
-        } else {
-            path.len()
-        };
+        (path.parent(), path.name())
+    }
 
-        let trailing_slash_pos = path[..bound]
-            .rfind('/')
-            .expect("Target path must have trailing filename");
+    // the last path segment is not followed if it's a symlink
+    async fn traverse_path(&self, path: &FsPath) -> (Node, BlockIndex) {
+        self.lookup_path(path, false)
+            .await
+            .unwrap_or_else(|| panic!("{path} doesn't exist"))
+    }
 
-        path.split_at(trailing_slash_pos + 1)
+    // like `traverse_path` but follows a symlink in the last path segment as well
+    async fn resolve_path(&self, path: &FsPath) -> (Node, BlockIndex) {
+        self.lookup_path(path, true)
+            .await
+            .unwrap_or_else(|| panic!("{path} doesn't exist"))
     }
 
-    async fn traverse_path<S: AsRef<str>>(&self, path: S) -> (Node, BlockIndex) {
-        assert!(
-            path.as_ref().starts_with('/'),
-            "Paths must start with a '/'"
-        );
+    async fn lookup_path(
+        &self,
+        path: &FsPath,
+        follow_symlinks: bool,
+    ) -> Option<(Node, BlockIndex)> {
+        self.try_lookup_path(path, follow_symlinks)
+            .await
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
 
+    // like lookup_path, but a path that can't be resolved is an error instead of a panic, e.g.
+    // a file used as a directory
+    async fn try_lookup_path(
+        &self,
+        path: &FsPath,
+        follow_symlinks: bool,
+    ) -> Result<Option<(Node, BlockIndex)>, String> {
         // edge case of '/'
-        if path.as_ref() == "/" {
-            return (self.get_root_directory_node().await, self.root_node_id);
+        if path.is_root() {
+            return Ok(Some((
+                self.get_root_directory_node().await,
+                self.root_node_id,
+            )));
         }
 
-        let path_segments: Vec<&str> = path.as_ref().split_inclusive('/').collect();
-
-        // if the path ends with a '/' it points to a directory
-        let path_to_dir = path_segments.last().unwrap().ends_with('/');
-
+        let mut segments: VecDeque<String> = path.segments().iter().cloned().collect();
         let mut dir = self.get_root_directory_node().await;
+        let mut dir_id = self.root_node_id;
+        let mut symlink_hops = 0;
+
         // traverse path
-        // exclude first segment of leading '/' and last of filename
-        for segment in path_segments[..path_segments.len() - 1].iter().skip(1) {
-            assert!(!segment.is_empty(), "Consecutive '/' are not permitted");
+        while let Some(segment) = segments.pop_front() {
+            // only symlink targets can still contain empty segments
+            if segment == "/" {
+                return Err(String::from("Consecutive '/' are not permitted"));
+            }
 
-            // this panics if a path segment in the middle is not a directory as it's supposed to
-            dir = self
-                .get_directory_node(dir.get_directory_entry(segment).block_id())
-                .await;
-        }
+            // if the segment ends with a '/' it points to a directory
+            let to_dir = segment.ends_with('/');
+            let is_last = segments.is_empty();
 
-        // get destination directory or file
-        if path_to_dir {
-            let dir_node_block_id = dir
-                .get_directory_entry(path_segments.last().unwrap())
-                .block_id();
-            (
-                self.get_directory_node(dir_node_block_id).await,
-                dir_node_block_id,
-            )
-        } else {
-            let file_node_block_id = dir
-                .get_directory_entry(path_segments.last().unwrap())
-                .block_id();
-            (
-                self.get_file_node(file_node_block_id).await,
-                file_node_block_id,
-            )
+            if segment == "./" || segment == "." {
+                continue;
+            }
+            if segment == "../" || segment == ".." {
+                if dir_id != self.root_node_id {
+                    dir_id = dir.parent_block_id;
+                    dir = self.get_directory_node(dir_id).await;
+                }
+                continue;
+            }
+
+            // symlinks are stored like files, without a trailing '/'
+            let Some(entry) = dir
+                .find_directory_entry(&segment)
+                .or_else(|| dir.find_directory_entry(segment.strip_suffix('/')?))
+            else {
+                return Ok(None);
+            };
+            let node_id = entry.block_id();
+            let node = self
+                .try_get_node(node_id)
+                .await
+                .unwrap_or_else(|err| panic!("Node {node_id} is corrupted: {err}"));
+
+            // symlinks in the middle of the path or used as directories are always followed
+            if node.kind == Symlink && (follow_symlinks || !is_last || to_dir) {
+                symlink_hops += 1;
+                if symlink_hops > MAX_SYMLINK_HOPS {
+                    return Err(format!(
+                        "Too many levels of symlinks in {path}, the symlinks likely form a loop"
+                    ));
+                }
+
+                let target = node.target();
+                if let Some(target) = target.strip_prefix('/') {
+                    dir = self.get_root_directory_node().await;
+                    dir_id = self.root_node_id;
+                    if target.is_empty() {
+                        // a symlink to '/' has no segments to resolve
+                        if !is_last && !to_dir {
+                            return Err(format!("{path} is not a directory"));
+                        }
+                        if is_last {
+                            return Ok(Some((dir, dir_id)));
+                        }
+                        continue;
+                    }
+                }
+
+                let mut target_segments: Vec<String> = target
+                    .trim_start_matches('/')
+                    .split_inclusive('/')
+                    .map(String::from)
+                    .collect();
+                if to_dir && !target.ends_with('/') {
+                    target_segments.last_mut().unwrap().push('/');
+                }
+                for target_segment in target_segments.into_iter().rev() {
+                    segments.push_front(target_segment);
+                }
+                continue;
+            }
+
+            if is_last {
+                if to_dir && node.kind != Directory {
+                    return Err(format!("{path} is not a directory"));
+                }
+                if !to_dir && node.kind == Directory {
+                    return Err(format!("{path} is a directory"));
+                }
+                return Ok(Some((node, node_id)));
+            }
+
+            // a path segment in the middle has to be a directory
+            if node.kind != Directory {
+                return Err(format!("{path} is not a valid path"));
+            }
+            dir = node;
+            dir_id = node_id;
         }
+
+        // only reached if the path ends with '.' or '..' segments
+        Ok(Some((dir, dir_id)))
     }
 
     async fn create_directory_node(&self, parent_node_id: BlockIndex) -> (Node, BlockIndex) {
-        let node = Node::new(Directory, parent_node_id);
-        let attachment = CreateAttachment::bytes(node.to_bytes(), "node");
+        let mut node = Node::new(Directory, self.format_version, parent_node_id);
+        node.case_insensitive = self.case_insensitive;
+        let attachment = CreateAttachment::bytes(self.codec.encode(&node), "node");
 
         let block_id = util::send_message(
             &self.client,
@@ -547,23 +2614,24 @@ impl NodeFS {
             "Tried to update non directory node as directory node"
         );
 
-        let attachment = CreateAttachment::bytes(node.to_bytes(), "node");
-        util::edit_message(
-            &self.client,
-            self.data_channel,
-            MessageId::new(node_id),
-            EditMessage::new().new_attachment(attachment),
-        )
-        .await
-        .expect("Failed to edit directory node");
+        self.write_node_bytes(node_id, self.codec.encode(&node))
+            .await
+            .expect("Failed to edit directory node");
     }
 
     async fn get_directory_node(&self, node_id: BlockIndex) -> Node {
-        let node = Node::from_bytes(
-            util::read_attachment(&self.client, self.data_channel, MessageId::new(node_id))
-                .await
-                .expect("Failed to get directory node"),
-        );
+        let node = self
+            .parse_node(
+                self.read_node_bytes(node_id)
+                    .await
+                    .expect("Failed to get directory node"),
+            )
+            .unwrap_or_else(|err| {
+                failure::fail(
+                    Hint::Corrupted,
+                    format!("Directory node {node_id} is corrupted: {err}"),
+                )
+            });
 
         assert!(
             node.kind == Directory,
@@ -574,15 +2642,18 @@ impl NodeFS {
     }
 
     async fn get_root_directory_node(&self) -> Node {
-        let node = Node::from_bytes(
-            util::read_attachment(
-                &self.client,
-                self.data_channel,
-                MessageId::new(self.root_node_id),
+        let node = self
+            .parse_node(
+                self.read_node_bytes(self.root_node_id)
+                    .await
+                    .expect("Failed to get root node"),
             )
-            .await
-            .expect("Failed to get root node"),
-        );
+            .unwrap_or_else(|err| {
+                failure::fail(
+                    Hint::Corrupted,
+                    format!("Root node {} is corrupted: {err}", self.root_node_id),
+                )
+            });
 
         assert!(node.kind == Directory, "Root node is corrupted");
 
@@ -590,8 +2661,9 @@ impl NodeFS {
     }
 
     async fn create_file_node(&self, parent_node_id: BlockIndex) -> (Node, BlockIndex) {
-        let node = Node::new(File, parent_node_id);
-        let attachment = CreateAttachment::bytes(node.to_bytes(), "node");
+        let mut node = Node::new(File, self.format_version, parent_node_id);
+        node.case_insensitive = self.case_insensitive;
+        let attachment = CreateAttachment::bytes(self.codec.encode(&node), "node");
 
         let block_id = util::send_message(
             &self.client,
@@ -610,30 +2682,75 @@ impl NodeFS {
             "Tried to update non file node as file node"
         );
 
-        let attachment = CreateAttachment::bytes(node.to_bytes(), "node");
-        util::edit_message(
+        self.write_node_bytes(node_id, self.codec.encode(&node))
+            .await
+            .expect("Failed to edit file node");
+    }
+
+    async fn get_file_node(&self, node_id: BlockIndex) -> Node {
+        let node = self
+            .parse_node(
+                self.read_node_bytes(node_id)
+                    .await
+                    .expect("Failed to get file node"),
+            )
+            .unwrap_or_else(|err| {
+                failure::fail(
+                    Hint::Corrupted,
+                    format!("File node {node_id} is corrupted: {err}"),
+                )
+            });
+
+        assert!(node.kind == File, "Tried to get non file node as file node");
+
+        node
+    }
+
+    async fn create_symlink_node(
+        &self,
+        parent_node_id: BlockIndex,
+        target: &str,
+    ) -> (Node, BlockIndex) {
+        let mut node = Node::new(Symlink, self.format_version, parent_node_id);
+        node.case_insensitive = self.case_insensitive;
+        node.set_target(target);
+        let attachment = CreateAttachment::bytes(self.codec.encode(&node), "node");
+
+        let block_id = util::send_message(
             &self.client,
             self.data_channel,
-            MessageId::new(node_id),
-            EditMessage::new().new_attachment(attachment),
+            CreateMessage::new().content("").add_file(attachment),
         )
         .await
-        .expect("Failed to edit file node");
+        .expect("Failed to create symlink node");
+
+        (node, block_id.get())
     }
 
-    async fn get_file_node(&self, node_id: BlockIndex) -> Node {
-        let node = Node::from_bytes(
-            util::read_attachment(&self.client, self.data_channel, MessageId::new(node_id))
-                .await
-                .expect("Failed to get file node"),
+    async fn edit_symlink_node(&self, node_id: BlockIndex, node: Node) {
+        assert!(
+            node.kind == Symlink,
+            "Tried to update non symlink node as symlink node"
         );
 
-        assert!(node.kind == File, "Tried to get non file node as file node");
+        self.write_node_bytes(node_id, self.codec.encode(&node))
+            .await
+            .expect("Failed to edit symlink node");
+    }
 
-        node
+    async fn edit_node(&self, node_id: BlockIndex, node: Node) {
+        match node.kind {
+            Directory => self.edit_directory_node(node_id, node).await,
+            File => self.edit_file_node(node_id, node).await,
+            Symlink => self.edit_symlink_node(node_id, node).await,
+        }
     }
 
     async fn create_data_block(&self, data: Vec<u8>) -> BlockIndex {
+        if let Some(erasure) = &self.erasure {
+            return self.create_stripe(erasure, data).await;
+        }
+
         let attachment = CreateAttachment::bytes(data, "data");
         util::send_message(
             &self.client,
@@ -646,9 +2763,62 @@ impl NodeFS {
     }
 
     async fn get_data_block(&self, block_id: u64) -> Vec<u8> {
-        util::read_attachment(&self.client, self.data_channel, MessageId::new(block_id))
+        self.try_get_data_block(block_id)
             .await
-            .expect("Failed to get data block")
+            .unwrap_or_else(|err| panic!("Failed to get data block {block_id}: {err}"))
+    }
+
+    // network errors are retried a few times, a block with the wrong author or without data
+    // won't get better
+    async fn try_get_data_block(&self, block_id: u64) -> Result<Vec<u8>, String> {
+        let mut attempt = 1;
+        loop {
+            let block = async {
+                let message =
+                    util::get_message(&self.client, self.data_channel, MessageId::new(block_id))
+                        .await
+                        .map_err(|err| (true, err.to_string()))?;
+                self.check_author(&message).map_err(|err| (false, err))?;
+                let attachment = message.attachments.first().ok_or((
+                    false,
+                    String::from("should contain an attachment of block data"),
+                ))?;
+                self.download_block(attachment).await
+            };
+            match block.await {
+                Ok(block) => return Ok(block),
+                Err((true, _)) if attempt < BLOCK_ATTEMPTS => {
+                    util::count_block_retry();
+                    tokio::time::sleep(Duration::from_secs(attempt as u64)).await;
+                    attempt += 1;
+                }
+                Err((_, err)) => return Err(err),
+            }
+        }
+    }
+
+    // stripes are rebuilt from their shards, the attachment of other blocks is the data itself
+    async fn download_block(&self, attachment: &Attachment) -> Result<Vec<u8>, (bool, String)> {
+        let data = util::download_attachment(attachment)
+            .await
+            .map_err(|err| (true, err.to_string()))?;
+        if attachment.filename != erasure::STRIPE_NAME {
+            return Ok(data);
+        }
+
+        // parity already covers shards that failed to download
+        self.read_stripe(&data).await.map_err(|err| (false, err))
+    }
+
+    // blocks edited or injected by other users would otherwise be used as they are
+    fn check_author(&self, message: &Message) -> Result<(), String> {
+        match self.owner {
+            Some(owner) if message.author.id != owner => Err(format!(
+                "was posted by {} ({}) instead of the filesystem's bot ({owner}), it may have been tampered with",
+                message.author.name, message.author.id
+            )),
+            _ => Ok(()),
+        }
     }
 
     async fn delete_block(&self, block_id: u64) {
@@ -657,11 +2827,225 @@ impl NodeFS {
             .expect("Failed to delete block");
     }
 
-    async fn get_node(&self, node_id: BlockIndex) -> Node {
-        Node::from_bytes(
-            util::read_attachment(&self.client, self.data_channel, MessageId::new(node_id))
-                .await
-                .expect("Failed to get node"),
+    // stripes take their shards along
+    async fn delete_data_block(&self, block_id: u64) {
+        self.try_delete_data_block(block_id)
+            .await
+            .expect("Failed to delete block");
+    }
+
+    async fn try_delete_data_block(&self, block_id: u64) -> serenity::Result<()> {
+        self.delete_shards(block_id).await;
+        util::delete_message(&self.client, self.data_channel, MessageId::new(block_id)).await
+    }
+
+    // for operations that can skip corrupted nodes and continue
+    async fn try_get_node(&self, node_id: BlockIndex) -> Result<Node, ParseError> {
+        match self.read_node_bytes(node_id).await {
+            Ok(bytes) => self.parse_node(bytes),
+            // foreign messages are reported like corrupted nodes
+            Err(serenity::Error::Other(reason)) => Err(ParseError::new(0, reason)),
+            Err(err) => panic!("Failed to get node: {err}"),
+        }
+    }
+
+    async fn read_node_bytes(&self, node_id: BlockIndex) -> serenity::Result<Vec<u8>> {
+        let message =
+            util::get_message(&self.client, self.data_channel, MessageId::new(node_id)).await?;
+        self.node_bytes_from_message(&message).await
+    }
+
+    // segments are ordered by the index in their name, a single 'node' attachment is the whole node
+    async fn node_bytes_from_message(&self, message: &Message) -> serenity::Result<Vec<u8>> {
+        if self.check_author(message).is_err() {
+            return Err(serenity::Error::Other(
+                "Node message was not posted by the filesystem's bot, it may have been tampered with",
+            ));
+        }
+        if let Some(bytes) = self.cached_node(message).await {
+            return Ok(bytes);
+        }
+
+        let mut segments: Vec<_> = message
+            .attachments
+            .iter()
+            .map(|attachment| {
+                let idx = attachment
+                    .filename
+                    .split('.')
+                    .nth(1)
+                    .map_or(0, |idx| idx.parse::<usize>().unwrap_or(usize::MAX));
+                (idx, attachment)
+            })
+            .collect();
+        assert!(
+            !segments.is_empty(),
+            "Node message '{}' should contain an attachment of node data",
+            message.id
+        );
+        segments.sort_by_key(|(idx, _)| *idx);
+
+        let mut bytes = Vec::new();
+        for (_, attachment) in segments {
+            bytes.extend(util::download_attachment(attachment).await?);
+        }
+
+        self.cache_node(message, &bytes).await;
+
+        Ok(bytes)
+    }
+
+    async fn write_node_bytes(&self, node_id: BlockIndex, bytes: Vec<u8>) -> serenity::Result<()> {
+        let message_id = MessageId::new(node_id);
+        if self.format_version < 4 || bytes.len() <= node::NODE_SEGMENT_SIZE {
+            let attachment = CreateAttachment::bytes(bytes, "node");
+            return util::edit_message(
+                &self.client,
+                self.data_channel,
+                message_id,
+                EditMessage::new().new_attachment(attachment),
+            )
+            .await;
+        }
+
+        // segment names include a hash of their content, unchanged segments are kept as they are
+        let message = util::get_message(&self.client, self.data_channel, message_id).await?;
+        let mut attachments = EditAttachments::new();
+        for (idx, segment) in bytes.chunks(node::NODE_SEGMENT_SIZE).enumerate() {
+            let name = format!("node.{idx}.{:016x}", NodeFS::segment_hash(segment));
+            attachments = match message
+                .attachments
+                .iter()
+                .find(|attachment| attachment.filename == name)
+            {
+                Some(attachment) => attachments.keep(attachment.id),
+                None => attachments.add(CreateAttachment::bytes(segment.to_vec(), name)),
+            };
+        }
+
+        util::edit_message(
+            &self.client,
+            self.data_channel,
+            message_id,
+            EditMessage::new().attachments(attachments),
         )
+        .await
+    }
+
+    // 64 bit FNV-1a, only used to detect changed segments
+    fn segment_hash(segment: &[u8]) -> u64 {
+        segment.iter().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    // whether `ancestor` is `node_id` itself or one of the directories above it
+    //
+    // broken parent links could form a loop, every node is only visited once
+    async fn is_ancestor<F, Fut>(
+        ancestor: BlockIndex,
+        node_id: BlockIndex,
+        root_node_id: BlockIndex,
+        mut parent_of: F,
+    ) -> bool
+    where
+        F: FnMut(BlockIndex) -> Fut,
+        Fut: Future<Output = BlockIndex>,
+    {
+        let mut visited = Vec::new();
+        let mut node_id = node_id;
+        loop {
+            if node_id == ancestor {
+                return true;
+            }
+            if node_id == root_node_id || node_id == 0 || visited.contains(&node_id) {
+                return false;
+            }
+
+            visited.push(node_id);
+            node_id = parent_of(node_id).await;
+        }
+    }
+
+    fn parse_node(&self, bytes: Vec<u8>) -> Result<Node, ParseError> {
+        let mut node = node_codec::decode(bytes)?;
+        node.case_insensitive = self.case_insensitive;
+
+        if node.version != self.format_version {
+            return Err(ParseError::new(
+                0,
+                format!(
+                    "Node has format version v{} in a v{} filesystem, run 'migrate --to v{}' to finish an interrupted migration",
+                    node.version,
+                    self.format_version,
+                    node.version.max(self.format_version)
+                ),
+            ));
+        }
+
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const ROOT: BlockIndex = 1;
+
+    // directories 2..=depth+1 nested below the root, each one inside the previous
+    fn chain(depth: BlockIndex) -> HashMap<BlockIndex, BlockIndex> {
+        (2..depth + 2).map(|id| (id, id - 1)).collect()
+    }
+
+    async fn is_ancestor(
+        parents: &HashMap<BlockIndex, BlockIndex>,
+        ancestor: BlockIndex,
+        node_id: BlockIndex,
+    ) -> bool {
+        NodeFS::is_ancestor(ancestor, node_id, ROOT, |id| {
+            let parent = parents[&id];
+            async move { parent }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn detects_moves_into_deeply_nested_descendants() {
+        let parents = chain(1000);
+
+        // '/a/' into '/a/b/.../'
+        assert!(is_ancestor(&parents, 2, 1001).await);
+        assert!(is_ancestor(&parents, 500, 1001).await);
+        assert!(is_ancestor(&parents, 1000, 1001).await);
+        assert!(is_ancestor(&parents, ROOT, 1001).await);
+
+        // into itself
+        assert!(is_ancestor(&parents, 1001, 1001).await);
+    }
+
+    #[tokio::test]
+    async fn allows_moves_out_of_and_next_to_the_subtree() {
+        let mut parents = chain(100);
+        // a sibling branch below directory 50
+        parents.insert(200, 50);
+        parents.insert(201, 200);
+
+        assert!(!is_ancestor(&parents, 101, 50).await);
+        assert!(!is_ancestor(&parents, 51, 201).await);
+        assert!(!is_ancestor(&parents, 101, ROOT).await);
+        assert!(is_ancestor(&parents, 50, 201).await);
+    }
+
+    #[tokio::test]
+    async fn stops_at_broken_parent_loops() {
+        let mut parents = chain(10);
+        // 5 claims to be inside 8, which is inside 5
+        parents.insert(5, 8);
+
+        assert!(!is_ancestor(&parents, 2, 8).await);
+        assert!(is_ancestor(&parents, 6, 8).await);
     }
 }
@@ -0,0 +1,110 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use indicatif::{HumanBytes, HumanDuration};
+
+use crate::node::{Node, Timestamp};
+
+use super::NodeFS;
+
+// local history of the operations run against a data channel, it's collected together with the
+// statistics and holds one '<start> <operation> <result> <bytes> <millis>' line per run
+impl NodeFS {
+    pub(super) fn activity_file(stats_file: &Path) -> PathBuf {
+        stats_file.with_extension("activity")
+    }
+
+    // called once the operation returned, panics are logged by the hook from 'track_operation'
+    pub fn finish_operation(&self, partial: bool) {
+        let (Some(path), Some((operation, start, started))) =
+            (&self.stats_file, self.operation.get())
+        else {
+            return;
+        };
+
+        NodeFS::append_activity(
+            &NodeFS::activity_file(path),
+            *start,
+            operation,
+            if partial { "partial" } else { "ok" },
+            self.transferred.load(Ordering::Relaxed),
+            started.elapsed(),
+        );
+    }
+
+    // synchronous like the statistics, it also runs from the panic hook
+    pub(super) fn append_activity(
+        path: &Path,
+        start: Timestamp,
+        operation: &str,
+        result: &str,
+        bytes: u64,
+        elapsed: Duration,
+    ) {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let Ok(mut file) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        else {
+            return;
+        };
+        let _ = writeln!(
+            file,
+            "{start} {operation} {result} {bytes} {}",
+            elapsed.as_millis()
+        );
+    }
+
+    pub fn activity(&self, since: Option<Duration>) {
+        let Some(path) = &self.stats_file else {
+            println!("  Activity isn't recorded, set DFS_STATS=on to record it");
+            return;
+        };
+
+        let now = Node::now();
+        let oldest = since.map_or(0, |since| now.saturating_sub(since.as_secs()));
+        let data = std::fs::read_to_string(NodeFS::activity_file(path)).unwrap_or_default();
+        let entries: Vec<Vec<&str>> = data
+            .lines()
+            .map(|line| line.split(' ').collect::<Vec<&str>>())
+            .filter(|parts| parts.len() == 5)
+            .filter(|parts| {
+                parts[0]
+                    .parse::<Timestamp>()
+                    .is_ok_and(|start| start >= oldest)
+            })
+            .collect();
+        if entries.is_empty() {
+            println!("  No recorded activity");
+            return;
+        }
+
+        println!(
+            "  {:<14}{:<18}{:<10}{:>12}{:>12}",
+            "Started", "Operation", "Result", "Data", "Took"
+        );
+        for parts in entries {
+            let start: Timestamp = parts[0].parse().unwrap();
+            let bytes: u64 = parts[3].parse().unwrap_or(0);
+            let millis: u64 = parts[4].parse().unwrap_or(0);
+            println!(
+                "  {:<14}{:<18}{:<10}{:>12}{:>12}",
+                format!(
+                    "{} ago",
+                    HumanDuration(Duration::from_secs(now.saturating_sub(start)))
+                ),
+                parts[1],
+                parts[2],
+                HumanBytes(bytes).to_string(),
+                HumanDuration(Duration::from_millis(millis)).to_string()
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use indicatif::{HumanBytes, HumanCount, MultiProgress, ProgressBar};
+use serenity::all::ChannelId;
+
+use crate::{
+    directory_entry::{BlockIndex, NAME_LEN},
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node,
+    node_kind::NodeKind::File,
+    on_conflict::OnConflict,
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // attachments are stored as '<destination><YYYY-MM-DD>/<author>/<file name>', they are
+    // re-uploaded since blocks have to be encrypted and live in the data channel
+    pub async fn adopt(
+        &self,
+        channel: u64,
+        destination: FsPath,
+        on_conflict: OnConflict,
+        key: String,
+    ) {
+        assert!(destination.is_dir(), "Destination must be a directory");
+        assert!(
+            on_conflict != OnConflict::Resume,
+            "Adopted attachments can't be resumed, use 'skip' to continue an earlier adopt"
+        );
+        let channel = ChannelId::new(channel);
+        assert!(
+            channel != self.data_channel,
+            "The data channel can't be adopted"
+        );
+
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Scanning channel {channel}"));
+        let started = std::time::Instant::now();
+
+        // messages are scanned oldest first, 100 at a time
+        let mut directories: HashMap<String, BlockIndex> = HashMap::new();
+        let mut scanner = util::ChannelScanner::new(channel);
+        let mut scanned = 0;
+        let mut adopted = 0;
+        let mut skipped = 0;
+        let mut bytes = 0;
+        let mut blocks = 0;
+        while let Some(messages) = scanner
+            .next_page(&self.client)
+            .await
+            .expect("Failed to get messages")
+        {
+            for message in messages {
+                scanned += 1;
+                if message.attachments.is_empty() {
+                    continue;
+                }
+
+                let date = &message.timestamp.to_string()[..10];
+                let dir_path = destination
+                    .join(format!("{date}/"))
+                    .join(format!("{}/", NodeFS::adopted_name(&message.author.name)));
+                let dir_node_id = match directories.get(&dir_path.to_string()) {
+                    Some(dir_node_id) => *dir_node_id,
+                    None => {
+                        if self.lookup_path(&dir_path, true).await.is_none() {
+                            self.mkdir_parents(dir_path.clone()).await;
+                        }
+                        let (_, dir_node_id) = self.resolve_path(&dir_path).await;
+                        directories.insert(dir_path.to_string(), dir_node_id);
+                        dir_node_id
+                    }
+                };
+
+                for attachment in &message.attachments {
+                    let name = NodeFS::adopted_name(&attachment.filename);
+                    spinner.set_message(format!(
+                        "Adopting {dir_path}{name} ({} scanned messages)",
+                        HumanCount(scanned)
+                    ));
+                    if attachment.size as usize > node::MAX_FILE_SIZE {
+                        self.record_failure(
+                            &progress,
+                            format!("{dir_path}{name}"),
+                            format!(
+                                "Exceeds maximum file size of {}",
+                                HumanBytes(node::MAX_FILE_SIZE as u64)
+                            ),
+                        );
+                        continue;
+                    }
+
+                    let mut dir_node = self.get_directory_node(dir_node_id).await;
+                    let Some(file_name) = NodeFS::conflict_name(&dir_node, &name, on_conflict)
+                    else {
+                        skipped += 1;
+                        continue;
+                    };
+                    let existing = match dir_node.find_directory_entry(&file_name) {
+                        Some(entry) => match self.try_get_node(entry.block_id()).await {
+                            Ok(node) => Some((node, entry.block_id())),
+                            Err(err) => {
+                                self.record_failure(
+                                    &progress,
+                                    format!("{dir_path}{file_name}"),
+                                    format!(
+                                        "The existing node {} is corrupted: {err}",
+                                        entry.block_id()
+                                    ),
+                                );
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+                    let existing_size = existing
+                        .as_ref()
+                        .filter(|(node, _)| node.kind == File)
+                        .map_or(0, |(node, _)| node.size());
+                    failure::ensure(
+                        existing.is_some() || !dir_node.is_full(),
+                        Hint::DirectoryFull,
+                        format!("{dir_path} is full"),
+                    );
+
+                    let data = match util::download_attachment(attachment).await {
+                        Ok(data) => data,
+                        Err(err) => {
+                            self.record_failure(
+                                &progress,
+                                format!("{dir_path}{file_name}"),
+                                format!("Failed to download {}: {err}", attachment.url),
+                            );
+                            continue;
+                        }
+                    };
+                    let quotas = self.quota_directories(dir_node_id).await;
+                    NodeFS::check_quota(&quotas, (data.len() as u64).saturating_sub(existing_size));
+
+                    let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+                    self.upload_blocks(
+                        data.as_slice(),
+                        &mut file_node,
+                        &key,
+                        &ProgressBar::hidden(),
+                    )
+                    .await;
+                    let size = file_node.size();
+                    bytes += size;
+                    blocks += file_node.blocks().len();
+                    self.edit_file_node(file_node_id, file_node).await;
+
+                    // every file is linked right away so an interrupted adopt can be continued
+                    if existing.is_some() {
+                        dir_node.delete_directory_entry(&file_name);
+                    }
+                    dir_node.push_directory_entry(&file_name, file_node_id);
+                    self.edit_directory_node(dir_node_id, dir_node).await;
+                    self.charge_quota(&quotas, size as i64 - existing_size as i64)
+                        .await;
+                    match existing {
+                        Some((node, node_id)) if node.kind == File => {
+                            self.unlink_file(node, node_id, &file_name, &progress).await
+                        }
+                        Some((_, node_id)) => self.delete_block(node_id).await,
+                        None => {}
+                    }
+
+                    adopted += 1;
+                }
+            }
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Adopted {adopted} attachments from {} messages ({skipped} skipped)",
+            HumanCount(scanned)
+        ));
+        if adopted > 0 {
+            self.print_summary(
+                &progress,
+                util::TransferSummary {
+                    operation: "Adopted",
+                    bytes,
+                    blocks,
+                    elapsed: started.elapsed(),
+                },
+            );
+        }
+    }
+
+    // Discord names may contain anything, entry names can't contain '/' or control characters
+    fn adopted_name(name: &str) -> String {
+        let mut adopted = String::new();
+        for ch in name.chars() {
+            let ch = if ch == '/' || ch.is_control() {
+                '_'
+            } else {
+                ch
+            };
+            if adopted.len() + ch.len_utf8() > NAME_LEN - 1 {
+                break;
+            }
+            adopted.push(ch);
+        }
+
+        // '.' and '..' would be read as path segments
+        if adopted.is_empty() || adopted == "." || adopted == ".." {
+            String::from("_")
+        } else {
+            adopted
+        }
+    }
+}
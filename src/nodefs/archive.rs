@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Component, Path},
+    sync::mpsc,
+};
+
+use indicatif::{MultiProgress, ProgressBar};
+use tokio::io::DuplexStream;
+
+use crate::{
+    directory_entry::BlockIndex,
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node::{self, Node},
+    node_kind::NodeKind::{Directory, File, Symlink},
+    sync_io::SyncIo,
+    util,
+};
+
+use super::NodeFS;
+
+enum ArchiveEntry {
+    Directory(String),
+    File(String, u64, DuplexStream),
+    Symlink(String, String),
+}
+
+impl NodeFS {
+    pub async fn archive(&self, source: FsPath, destination: FsPath, key: String) {
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Archiving {source} to {destination}"));
+
+        let (_, source_name) = NodeFS::split_path(&source, true, true);
+        let (source_node, source_node_id) = self.traverse_path(&source).await;
+
+        let (file_path, file_name) = NodeFS::split_path(&destination, false, false);
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&file_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(file_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
+
+        // create file node
+        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        let staging_node_id = self.staging_directory().await;
+        self.stage_node(staging_node_id, file_node_id).await;
+
+        // show progress bar, the archive size is unknown upfront
+        let progress_bar = progress.add(util::stream_progress());
+
+        // the tar builder and zstd encoder run in a blocking task, fed with the
+        // decrypted remote files and writing into the upload stream
+        let (reader, writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        let writer = SyncIo::new(writer);
+        let (sender, receiver) = mpsc::channel::<ArchiveEntry>();
+        let archiver = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let mut builder = tar::Builder::new(zstd::Encoder::new(writer, 0)?);
+            NodeFS::build_archive(&mut builder, receiver)?;
+            builder.into_inner()?.finish()?;
+            Ok(())
+        });
+
+        tokio::join!(
+            self.archive_entries(
+                source_node,
+                source_node_id,
+                source_name,
+                sender,
+                &key,
+                &progress
+            ),
+            self.upload_blocks(reader, &mut file_node, &key, &progress_bar),
+        );
+        archiver
+            .await
+            .expect("Archiving task panicked")
+            .expect("Failed to archive directory");
+
+        // the archive size is only known now
+        let size = file_node.size();
+        let quotas = self.quota_directories(dir_node_id).await;
+        NodeFS::check_quota(&quotas, size);
+
+        // update nodes
+        self.edit_file_node(file_node_id, file_node).await;
+        self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+        dir_node.push_directory_entry(file_name, file_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, size as i64).await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!("Finished archiving {source}"));
+    }
+
+    // like 'archive' but the archive is written to a local file, compressed if it ends with '.zst'
+    pub async fn download_archive(&self, source: FsPath, destination: String, key: String) {
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Archiving {source} to {destination}"));
+
+        let (_, source_name) = NodeFS::split_path(&source, true, true);
+        let (source_node, source_node_id) = self.traverse_path(&source).await;
+
+        let file = std::fs::File::create_new(&destination)
+            .unwrap_or_else(|err| panic!("Failed to create {destination}: {err}"));
+        let compress = destination.ends_with(".zst");
+        let (sender, receiver) = mpsc::channel::<ArchiveEntry>();
+        let archiver = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            if compress {
+                let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+                NodeFS::build_archive(&mut builder, receiver)?;
+                builder.into_inner()?.finish()?.sync_all()
+            } else {
+                let mut builder = tar::Builder::new(file);
+                NodeFS::build_archive(&mut builder, receiver)?;
+                builder.into_inner()?.sync_all()
+            }
+        });
+
+        self.archive_entries(
+            source_node,
+            source_node_id,
+            source_name,
+            sender,
+            &key,
+            &progress,
+        )
+        .await;
+        archiver
+            .await
+            .expect("Archiving task panicked")
+            .expect("Failed to write archive");
+
+        // cleanup
+        spinner.finish_with_message(format!("Finished archiving {source} to {destination}"));
+    }
+
+    pub async fn unarchive(&self, source: FsPath, destination: FsPath, key: String) {
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Unarchiving {source} to {destination}"));
+
+        assert!(destination.is_dir(), "Directories are required");
+
+        // get source file and target directory
+        let (source_node, _) = self.traverse_path(&source).await;
+        assert!(source_node.kind == File, "Can only unarchive files");
+        let (dir_node, dir_node_id) = self.traverse_path(&destination).await;
+
+        // show progress bar
+        let progress_bar = progress.add(util::progress_bar(source_node.size()));
+
+        // the zstd decoder and tar reader run in a blocking task, fed with the
+        // downloaded archive and handing out entries to be uploaded
+        let (reader, mut writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        let reader = SyncIo::new(reader);
+        let (sender, receiver) = tokio::sync::mpsc::channel::<ArchiveEntry>(1);
+        let extractor = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let mut archive = tar::Archive::new(zstd::Decoder::new(reader)?);
+
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = NodeFS::archive_path(&entry.path()?)?;
+
+                let mut data_writer = None;
+                let archive_entry = match entry.header().entry_type() {
+                    tar::EntryType::Directory => ArchiveEntry::Directory(format!("{path}/")),
+                    tar::EntryType::Regular => {
+                        let (data_reader, writer) = tokio::io::duplex(node::BLOCK_SIZE);
+                        data_writer = Some(writer);
+                        ArchiveEntry::File(path, entry.size(), data_reader)
+                    }
+                    tar::EntryType::Symlink => {
+                        let target = entry.link_name()?.ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                format!("Archive contains symlink without target: {path}"),
+                            )
+                        })?;
+                        let target = target.to_str().ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "Archive contains non UTF-8 path",
+                            )
+                        })?;
+                        ArchiveEntry::Symlink(path, target.to_string())
+                    }
+                    // hard links and special files have no representation
+                    _ => continue,
+                };
+
+                if sender.blocking_send(archive_entry).is_err() {
+                    break;
+                }
+                if let Some(data_writer) = data_writer {
+                    std::io::copy(&mut entry, &mut SyncIo::new(data_writer))?;
+                }
+            }
+
+            Ok(())
+        });
+
+        tokio::join!(
+            async {
+                self.download_blocks(&source_node, &mut writer, &key, &progress_bar)
+                    .await;
+
+                // closing the stream marks the end of the archive
+                drop(writer);
+            },
+            self.unarchive_entries(dir_node, dir_node_id, receiver, &key, &progress),
+        );
+        extractor
+            .await
+            .expect("Unarchiving task panicked")
+            .expect("Failed to unarchive file");
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!("Finished unarchiving {source}"));
+    }
+}
+
+impl NodeFS {
+    fn build_archive<W: Write>(
+        builder: &mut tar::Builder<W>,
+        entries: mpsc::Receiver<ArchiveEntry>,
+    ) -> std::io::Result<()> {
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            match entry {
+                ArchiveEntry::Directory(path) => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_data(&mut header, path, std::io::empty())?;
+                }
+                ArchiveEntry::File(path, size, data) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_mode(0o644);
+                    header.set_size(size);
+                    builder.append_data(&mut header, path, SyncIo::new(data))?;
+                }
+                ArchiveEntry::Symlink(path, target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_mode(0o777);
+                    header.set_size(0);
+                    builder.append_link(&mut header, path, target)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn archive_entries(
+        &self,
+        root_node: Node,
+        root_node_id: BlockIndex,
+        root_name: &str,
+        entries: mpsc::Sender<ArchiveEntry>,
+        key: &str,
+        progress: &MultiProgress,
+    ) {
+        // walk the directory tree depth first, parents are always archived before their contents
+        let mut stack = vec![(root_name.to_string(), root_node, root_node_id, 0)];
+        let mut visited = Default::default();
+        while let Some((path, dir_node, dir_node_id, depth)) = stack.pop() {
+            if let Err(err) = self.check_traversal(&mut visited, dir_node_id, depth) {
+                panic!("Stopped at {path}, it's {err}");
+            }
+            if entries.send(ArchiveEntry::Directory(path.clone())).is_err() {
+                return;
+            }
+
+            for entry in dir_node.entries() {
+                let entry_path = format!("{path}{}", entry.get_name());
+                let entry_node = match self.try_get_node(entry.block_id()).await {
+                    Ok(entry_node) => entry_node,
+                    Err(err) => {
+                        progress
+                            .println(format!(
+                                "  Skipping corrupted node {} ({entry_path}): {err}",
+                                entry.block_id()
+                            ))
+                            .unwrap();
+                        continue;
+                    }
+                };
+
+                match entry_node.kind {
+                    Directory => stack.push((entry_path, entry_node, entry.block_id(), depth + 1)),
+                    Symlink => {
+                        let entry = ArchiveEntry::Symlink(entry_path, entry_node.target().into());
+                        if entries.send(entry).is_err() {
+                            return;
+                        }
+                    }
+                    File => {
+                        // show progress information
+                        let spinner = progress.add(util::spinner());
+                        spinner.set_message(format!("Adding {entry_path}"));
+
+                        let (reader, mut writer) = tokio::io::duplex(node::BLOCK_SIZE);
+                        let entry = ArchiveEntry::File(entry_path, entry_node.size(), reader);
+                        if entries.send(entry).is_err() {
+                            return;
+                        }
+                        self.download_blocks(&entry_node, &mut writer, key, &ProgressBar::hidden())
+                            .await;
+
+                        // cleanup
+                        progress.remove(&spinner);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn unarchive_entries(
+        &self,
+        dir_node: Node,
+        dir_node_id: BlockIndex,
+        mut entries: tokio::sync::mpsc::Receiver<ArchiveEntry>,
+        key: &str,
+        progress: &MultiProgress,
+    ) {
+        // directories touched by the archive, keyed by path relative to the destination
+        let mut dirs = HashMap::from([(String::new(), (dir_node, dir_node_id))]);
+
+        while let Some(entry) = entries.recv().await {
+            match entry {
+                ArchiveEntry::Directory(path) => {
+                    self.unarchive_directory(&mut dirs, &path).await;
+                }
+                ArchiveEntry::File(path, size, data) => {
+                    let (parent_path, name) =
+                        path.split_at(path.rfind('/').map_or(0, |pos| pos + 1));
+                    let parent_node_id = self.unarchive_directory(&mut dirs, parent_path).await;
+
+                    let (parent_node, _) = &dirs[parent_path];
+                    failure::ensure(
+                        !parent_node.is_full(),
+                        Hint::DirectoryFull,
+                        format!("The directory {parent_path} is full"),
+                    );
+                    failure::ensure(
+                        !parent_node.contains_entry(name),
+                        Hint::NameExists,
+                        format!("The file {path} already exists"),
+                    );
+
+                    // charged right away, the touched directories are saved at the end
+                    let quotas = self.quota_directories(parent_node_id).await;
+                    NodeFS::check_quota(&quotas, size);
+                    self.charge_quota(&quotas, size as i64).await;
+
+                    // show progress information
+                    let spinner = progress.add(util::spinner());
+                    spinner.set_message(format!("Extracting {path}"));
+
+                    let (mut file_node, file_node_id) = self.create_file_node(parent_node_id).await;
+                    self.upload_blocks(data, &mut file_node, key, &ProgressBar::hidden())
+                        .await;
+                    assert!(
+                        file_node.size() == size,
+                        "Archive entry {path} is truncated"
+                    );
+                    self.edit_file_node(file_node_id, file_node).await;
+
+                    let (parent_node, _) = dirs.get_mut(parent_path).unwrap();
+                    parent_node.push_directory_entry(name, file_node_id);
+
+                    // cleanup
+                    progress.remove(&spinner);
+                }
+                ArchiveEntry::Symlink(path, target) => {
+                    let (parent_path, name) =
+                        path.split_at(path.rfind('/').map_or(0, |pos| pos + 1));
+                    let parent_node_id = self.unarchive_directory(&mut dirs, parent_path).await;
+
+                    let (parent_node, _) = &dirs[parent_path];
+                    failure::ensure(
+                        !parent_node.is_full(),
+                        Hint::DirectoryFull,
+                        format!("The directory {parent_path} is full"),
+                    );
+                    failure::ensure(
+                        !parent_node.contains_entry(name),
+                        Hint::NameExists,
+                        format!("The file {path} already exists"),
+                    );
+
+                    let (_, symlink_node_id) =
+                        self.create_symlink_node(parent_node_id, &target).await;
+
+                    let (parent_node, _) = dirs.get_mut(parent_path).unwrap();
+                    parent_node.push_directory_entry(name, symlink_node_id);
+                }
+            }
+        }
+
+        // save all touched directories
+        for (_, (mut node, node_id)) in dirs {
+            // keep the usage that was charged while extracting
+            if node.quota > 0 {
+                node.used = self.get_directory_node(node_id).await.used;
+            }
+
+            self.edit_directory_node(node_id, node).await;
+        }
+    }
+
+    // returns the directory at `path` relative to the destination, creating missing directories
+    async fn unarchive_directory(
+        &self,
+        dirs: &mut HashMap<String, (Node, BlockIndex)>,
+        path: &str,
+    ) -> BlockIndex {
+        let mut parent_path = String::new();
+        for segment in path.split_inclusive('/') {
+            let curr_path = format!("{parent_path}{segment}");
+            if !dirs.contains_key(&curr_path) {
+                let (parent_node, parent_node_id) = dirs.get_mut(&parent_path).unwrap();
+                let parent_node_id = *parent_node_id;
+
+                let dir = if parent_node.contains_entry(segment) {
+                    let dir_node_id = parent_node.get_directory_entry(segment).block_id();
+                    (self.get_directory_node(dir_node_id).await, dir_node_id)
+                } else {
+                    failure::ensure(
+                        !parent_node.is_full(),
+                        Hint::DirectoryFull,
+                        format!("The directory {parent_path} is full"),
+                    );
+
+                    let (dir_node, dir_node_id) = self.create_directory_node(parent_node_id).await;
+                    parent_node.push_directory_entry(segment, dir_node_id);
+                    (dir_node, dir_node_id)
+                };
+
+                dirs.insert(curr_path.clone(), dir);
+            }
+
+            parent_path = curr_path;
+        }
+
+        dirs[path].1
+    }
+
+    fn archive_path(path: &Path) -> std::io::Result<String> {
+        let mut segments = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(segment) => segments.push(segment.to_str().ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Archive contains non UTF-8 path",
+                    )
+                })?),
+                Component::CurDir => {}
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Archive contains unsafe path: {}", path.display()),
+                    ));
+                }
+            }
+        }
+
+        Ok(segments.join("/"))
+    }
+}
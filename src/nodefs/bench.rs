@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    aead::{Aead, KeyInit},
+};
+use futures::{StreamExt, stream};
+use indicatif::{HumanBytes, HumanCount, HumanDuration, MultiProgress};
+
+use crate::{node, nonce_counter::NonceCounter, util};
+
+use super::NodeFS;
+
+impl NodeFS {
+    pub async fn bench(&self, size: u64, jobs: usize, key: String) {
+        assert!(size > 0, "Benchmark size must not be 0");
+        assert!(jobs > 0, "At least one job is required");
+
+        let progress = MultiProgress::new();
+        let block_count = size.div_ceil(node::BLOCK_SIZE as u64);
+        let chunk_size =
+            |idx: u64| (size - idx * node::BLOCK_SIZE as u64).min(node::BLOCK_SIZE as u64);
+
+        // synthetic data is still encrypted to include the cypher in the measurements
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+        let mut nonce = NonceCounter::new();
+
+        // upload synthetic blocks
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Uploading {} with {jobs} jobs", HumanBytes(size)));
+        let progress_bar = progress.add(util::progress_bar(size));
+
+        let start = Instant::now();
+        let uploads: Vec<_> = stream::iter(0..block_count)
+            .map(|idx| {
+                let nonce = nonce.get_nonce();
+                let (cypher, progress_bar) = (&cypher, &progress_bar);
+                async move {
+                    let chunk = cypher
+                        .encrypt(&nonce, vec![0; chunk_size(idx) as usize].as_slice())
+                        .expect("Failed to encrypt data");
+
+                    let request = Instant::now();
+                    let block_id = self.create_data_block(chunk).await;
+                    let latency = request.elapsed();
+
+                    progress_bar.inc(chunk_size(idx));
+                    (block_id, nonce, latency)
+                }
+            })
+            .buffer_unordered(jobs)
+            .collect()
+            .await;
+        let upload_time = start.elapsed();
+        progress.remove(&progress_bar);
+
+        // download them again
+        spinner.set_message(format!("Downloading {} with {jobs} jobs", HumanBytes(size)));
+        let progress_bar = progress.add(util::progress_bar(size));
+
+        let start = Instant::now();
+        let downloads: Vec<_> = stream::iter(&uploads)
+            .map(|(block_id, nonce, _)| {
+                let (cypher, progress_bar) = (&cypher, &progress_bar);
+                async move {
+                    let request = Instant::now();
+                    let block = self.get_data_block(*block_id).await;
+                    let latency = request.elapsed();
+
+                    let block = cypher
+                        .decrypt(nonce, block.as_slice())
+                        .expect("Failed to decrypt data");
+
+                    progress_bar.inc(block.len() as u64);
+                    latency
+                }
+            })
+            .buffer_unordered(jobs)
+            .collect()
+            .await;
+        let download_time = start.elapsed();
+        progress.remove(&progress_bar);
+
+        // clean up the synthetic blocks
+        spinner.set_message(format!("Deleting {} blocks", HumanCount(block_count)));
+
+        let start = Instant::now();
+        let deletes: Vec<_> = stream::iter(&uploads)
+            .map(|(block_id, _, _)| async move {
+                let request = Instant::now();
+                self.delete_data_block(*block_id).await;
+                request.elapsed()
+            })
+            .buffer_unordered(jobs)
+            .collect()
+            .await;
+        let delete_time = start.elapsed();
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        let upload_latencies = uploads.iter().map(|(_, _, latency)| *latency).collect();
+        println!(
+            "  {} in {} blocks, {jobs} jobs",
+            HumanBytes(size),
+            HumanCount(block_count)
+        );
+        NodeFS::print_bench("Upload", size, upload_time, upload_latencies, block_count);
+        // fetching the message and downloading its attachment are separate requests
+        NodeFS::print_bench("Download", size, download_time, downloads, 2 * block_count);
+        NodeFS::print_bench("Delete", 0, delete_time, deletes, block_count);
+    }
+}
+
+impl NodeFS {
+    fn print_bench(
+        phase: &str,
+        bytes: u64,
+        elapsed: Duration,
+        mut latencies: Vec<Duration>,
+        api_calls: u64,
+    ) {
+        latencies.sort();
+        let percentile = |p: f64| {
+            let idx = ((latencies.len() as f64 * p).ceil() as usize).max(1) - 1;
+            format!("{:.2?}", latencies[idx])
+        };
+
+        let rate = if bytes > 0 {
+            format!(
+                ", {}/s",
+                HumanBytes((bytes as f64 / elapsed.as_secs_f64()) as u64)
+            )
+        } else {
+            String::new()
+        };
+
+        println!(
+            "  {phase:<10}{}{rate}, {} API calls",
+            HumanDuration(elapsed),
+            HumanCount(api_calls)
+        );
+        println!(
+            "  {:<10}latency p50 {}  p90 {}  p99 {}  max {}",
+            "",
+            percentile(0.5),
+            percentile(0.9),
+            percentile(0.99),
+            percentile(1.0)
+        );
+    }
+}
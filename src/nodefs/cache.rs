@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use serenity::all::Message;
+use tokio::fs;
+
+use super::NodeFS;
+
+// node data is cached on disk per channel, a cached node is only used while its message's
+// edit timestamp matches, so edits from other machines are noticed without downloading
+//
+// 'DFS_CACHE_DIR' moves the cache, an empty value turns it off
+impl NodeFS {
+    pub(super) fn node_cache_dir(data_channel_id: u64) -> Option<PathBuf> {
+        let dir = match std::env::var("DFS_CACHE_DIR") {
+            Ok(dir) if dir.is_empty() => return None,
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => std::env::var("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| std::env::var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .ok()?
+                .join("dfs"),
+        };
+
+        Some(dir.join(data_channel_id.to_string()))
+    }
+
+    pub(super) async fn cached_node(&self, message: &Message) -> Option<Vec<u8>> {
+        let path = self.cache_dir.as_ref()?.join(message.id.to_string());
+        let data = fs::read(path).await.ok()?;
+
+        // '<edit timestamp>\n' followed by the node data
+        let split = data.iter().position(|byte| *byte == b'\n')?;
+        if data[..split] != *NodeFS::cache_stamp(message).as_bytes() {
+            return None;
+        }
+
+        Some(data[split + 1..].to_vec())
+    }
+
+    // the cache is best effort, failing to write it doesn't fail the read
+    pub(super) async fn cache_node(&self, message: &Message, bytes: &[u8]) {
+        let Some(dir) = &self.cache_dir else {
+            return;
+        };
+        if fs::create_dir_all(dir).await.is_err() {
+            return;
+        }
+
+        let mut data = NodeFS::cache_stamp(message).into_bytes();
+        data.push(b'\n');
+        data.extend_from_slice(bytes);
+
+        // written next to it and renamed, so a reader never sees a partial entry, the process ID
+        // keeps concurrent runs from writing the same file
+        let path = dir.join(message.id.to_string());
+        let tmp = dir.join(format!("{}.{}.tmp", message.id, std::process::id()));
+        if fs::write(&tmp, data).await.is_err() || fs::rename(&tmp, path).await.is_err() {
+            let _ = fs::remove_file(tmp).await;
+        }
+    }
+
+    fn cache_stamp(message: &Message) -> String {
+        message
+            .edited_timestamp
+            .unwrap_or(message.timestamp)
+            .to_string()
+    }
+}
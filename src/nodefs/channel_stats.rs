@@ -0,0 +1,113 @@
+use std::{collections::HashMap, time::Duration};
+
+use indicatif::{HumanBytes, HumanCount, HumanDuration, MultiProgress};
+use serenity::all::{Message, MessageId};
+
+use crate::{
+    directory_entry::BlockIndex,
+    node::{Node, Size},
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // reads the whole channel history like 'gc orphans', but only reports what it found
+    pub async fn channel_stats(&self, key: String) {
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message("Scanning channel history");
+
+        let mut scanner = util::ChannelScanner::new(self.data_channel);
+        let mut nodes: HashMap<BlockIndex, Message> = HashMap::new();
+        let mut messages: Vec<(MessageId, Size)> = Vec::new();
+        let mut node_bytes = 0;
+        let mut data_bytes = 0;
+        let mut foreign = 0;
+        while let Some(page) = scanner
+            .next_page(&self.client)
+            .await
+            .expect("Failed to get messages")
+        {
+            for message in page {
+                if self.check_author(&message).is_err() {
+                    foreign += 1;
+                    continue;
+                }
+
+                let bytes: Size = message
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.size as Size)
+                    .sum();
+                messages.push((message.id, bytes));
+                if message
+                    .attachments
+                    .iter()
+                    .any(|attachment| attachment.filename.starts_with("node"))
+                {
+                    node_bytes += bytes;
+                    nodes.insert(message.id.get(), message);
+                } else {
+                    data_bytes += bytes;
+                }
+            }
+            spinner.set_message(format!(
+                "Scanning channel history ({} messages)",
+                HumanCount(messages.len() as u64)
+            ));
+        }
+
+        let (reachable, corrupted) = self
+            .reachable_messages(&nodes, &key, &progress, &spinner)
+            .await;
+        let unreachable: Vec<Size> = messages
+            .iter()
+            .filter(|(message_id, _)| !reachable.contains(&message_id.get()))
+            .map(|(_, bytes)| *bytes)
+            .collect();
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        let now = Node::now();
+        let age = |message_id: &MessageId| {
+            HumanDuration(Duration::from_secs(
+                now.saturating_sub(message_id.created_at().unix_timestamp() as u64),
+            ))
+        };
+        println!(
+            "  Messages          {} ({} by other authors)",
+            HumanCount(messages.len() as u64),
+            HumanCount(foreign)
+        );
+        println!(
+            "  Attachment bytes  {}",
+            HumanBytes(node_bytes + data_bytes)
+        );
+        println!(
+            "  Node messages     {} ({})",
+            HumanCount(nodes.len() as u64),
+            HumanBytes(node_bytes)
+        );
+        println!(
+            "  Data messages     {} ({})",
+            HumanCount((messages.len() - nodes.len()) as u64),
+            HumanBytes(data_bytes)
+        );
+        if let (Some((oldest, _)), Some((newest, _))) = (messages.first(), messages.last()) {
+            println!("  Oldest message    {} ago", age(oldest));
+            println!("  Newest message    {} ago", age(newest));
+        }
+        println!(
+            "  Unreachable       {} ({}), 'gc orphans' can delete them",
+            HumanCount(unreachable.len() as u64),
+            HumanBytes(unreachable.iter().sum())
+        );
+        if corrupted > 0 {
+            println!("  {corrupted} nodes couldn't be read, their data is counted as unreachable");
+        }
+    }
+}
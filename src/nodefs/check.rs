@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use indicatif::{HumanBytes, ProgressBar};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt},
+};
+
+use crate::{
+    directory_entry::DirectoryEntry,
+    fs_path::FsPath,
+    node::{self, Node, Size},
+    node_kind::NodeKind::{Directory, File, Symlink},
+    util,
+};
+
+use super::{METADATA_DIR_NAME, NodeFS};
+
+impl NodeFS {
+    pub async fn check(
+        &self,
+        local: String,
+        remote: FsPath,
+        checksum_only: bool,
+        skip_empty_dirs: bool,
+        key: String,
+    ) {
+        assert!(remote.is_dir(), "Directories are required");
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Scanning {local}"));
+
+        // collect file sizes of both trees, keyed by path relative to the compared directories
+        let (local_files, local_dirs) = NodeFS::local_file_sizes(PathBuf::from(&local)).await;
+
+        spinner.set_message(format!("Scanning {remote}"));
+        let mut remote_files = BTreeMap::new();
+        let mut remote_dirs = BTreeMap::new();
+        let mut corrupted = 0;
+        let (remote_node, remote_node_id) = self.traverse_path(&remote).await;
+        assert!(remote_node.kind == Directory, "Remote must be a directory");
+        let mut stack = vec![(String::new(), remote_node, remote_node_id, 0)];
+        let mut visited = Default::default();
+        while let Some((path, dir_node, dir_node_id, depth)) = stack.pop() {
+            if let Err(err) = self.check_traversal(&mut visited, dir_node_id, depth) {
+                panic!("Stopped at {remote}{path}, it's {err}");
+            }
+            if !path.is_empty() {
+                remote_dirs.insert(path.clone(), dir_node.entries().is_empty());
+            }
+            for entry in dir_node.entries() {
+                // internal metadata has no local counterpart
+                if remote.is_root() && path.is_empty() && entry.get_name() == METADATA_DIR_NAME {
+                    continue;
+                }
+
+                let entry_path = format!("{path}{}", entry.get_name());
+                spinner.set_message(format!("Scanning {remote}{entry_path}"));
+                match self.try_get_node(entry.block_id()).await {
+                    Ok(entry_node) if entry_node.kind == File => {
+                        remote_files.insert(entry_path, entry_node);
+                    }
+                    Ok(entry_node) if entry_node.kind == Symlink => {}
+                    Ok(entry_node) => {
+                        stack.push((entry_path, entry_node, entry.block_id(), depth + 1))
+                    }
+                    Err(err) => {
+                        spinner.println(format!(
+                            "  Skipping corrupted node {} ({remote}{entry_path}): {err}",
+                            entry.block_id()
+                        ));
+                        corrupted += 1;
+                    }
+                }
+            }
+        }
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        // no content hashes are stored, so comparing content means downloading the remote files
+        let mut missing = 0;
+        let mut differing = 0;
+        let mut same_size = Vec::new();
+        for (path, (local_size, local_path)) in &local_files {
+            match remote_files.get(path) {
+                None => {
+                    println!("  missing on remote   {path}");
+                    missing += 1;
+                }
+                Some(remote_node) if remote_node.size() != *local_size => {
+                    println!(
+                        "  size differs        {path} (local {}, remote {})",
+                        HumanBytes(*local_size),
+                        HumanBytes(remote_node.size())
+                    );
+                    differing += 1;
+                }
+                Some(remote_node) => same_size.push((path, local_path, remote_node)),
+            }
+        }
+
+        if checksum_only {
+            let progress_bar = util::progress_bar(
+                same_size
+                    .iter()
+                    .map(|(_, _, remote_node)| remote_node.size())
+                    .sum(),
+            );
+            for (path, local_path, remote_node) in same_size {
+                if !self
+                    .content_matches(
+                        util::local_join(&local, local_path),
+                        remote_node,
+                        &key,
+                        &progress_bar,
+                    )
+                    .await
+                {
+                    progress_bar.println(format!("  content differs     {path}"));
+                    differing += 1;
+                }
+            }
+            progress_bar.finish_and_clear();
+        }
+
+        let mut extra = 0;
+        for path in remote_files.keys() {
+            if !local_files.contains_key(path) {
+                println!("  missing locally     {path}");
+                extra += 1;
+            }
+        }
+
+        // directories with files are covered by their files, only empty ones are compared
+        let mut dirs_missing = 0;
+        let mut dirs_extra = 0;
+        if !skip_empty_dirs {
+            for (path, _) in local_dirs.iter().filter(|(_, empty)| **empty) {
+                if !remote_dirs.contains_key(path) {
+                    println!("  missing on remote   {path}");
+                    dirs_missing += 1;
+                }
+            }
+            for (path, _) in remote_dirs.iter().filter(|(_, empty)| **empty) {
+                if !local_dirs.contains_key(path) {
+                    println!("  missing locally     {path}");
+                    dirs_extra += 1;
+                }
+            }
+        }
+
+        let matching = local_files.len() - missing - differing;
+        println!(
+            "  {matching} matching, {differing} differing, {missing} missing on remote, {extra} missing locally"
+        );
+        if dirs_missing + dirs_extra > 0 {
+            println!(
+                "  {dirs_missing} empty directories missing on remote, {dirs_extra} missing locally"
+            );
+        }
+        if corrupted > 0 {
+            println!("  {corrupted} corrupted remote nodes could not be checked");
+        }
+    }
+}
+
+impl NodeFS {
+    // also returns every directory below the root and whether it's empty, names are normalized
+    // like remote entries so decomposed local names still match, files keep their real relative
+    // path to be opened with
+    pub(super) async fn local_file_sizes(
+        root: PathBuf,
+    ) -> (BTreeMap<String, (Size, String)>, BTreeMap<String, bool>) {
+        let mut files = BTreeMap::new();
+        let mut dirs = BTreeMap::new();
+        let mut stack = vec![(String::new(), String::new(), root)];
+        while let Some((path, raw_path, dir)) = stack.pop() {
+            let mut empty = true;
+            let mut entries = fs::read_dir(&dir)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to read directory {}: {err}", dir.display()));
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .expect("Failed to read directory entry")
+            {
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .expect("Local file names must be valid UTF-8");
+                let normalized = DirectoryEntry::normalize_name(&name);
+                let metadata = fs::metadata(entry.path())
+                    .await
+                    .expect("Failed to fetch file metadata");
+
+                empty = false;
+                if metadata.is_dir() {
+                    stack.push((
+                        format!("{path}{normalized}/"),
+                        format!("{raw_path}{name}/"),
+                        entry.path(),
+                    ));
+                } else if metadata.is_file() {
+                    files.insert(
+                        format!("{path}{normalized}"),
+                        (metadata.len(), format!("{raw_path}{name}")),
+                    );
+                }
+            }
+
+            if !path.is_empty() {
+                dirs.insert(path, empty);
+            }
+        }
+
+        (files, dirs)
+    }
+
+    // the remote file is streamed next to the local one, so memory use stays at a few blocks
+    async fn content_matches(
+        &self,
+        local_path: PathBuf,
+        remote_node: &Node,
+        key: &str,
+        progress_bar: &ProgressBar,
+    ) -> bool {
+        let (reader, writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        let (_, matches) = tokio::join!(
+            async {
+                let mut writer = writer;
+                self.download_blocks(remote_node, &mut writer, key, progress_bar)
+                    .await;
+            },
+            NodeFS::compare_streams(reader, local_path),
+        );
+
+        matches
+    }
+
+    async fn compare_streams<R: AsyncRead + Unpin>(mut remote: R, local_path: PathBuf) -> bool {
+        let mut local = fs::File::open(&local_path).await.ok();
+        let mut remote_buf = vec![0; node::BLOCK_SIZE];
+        let mut local_buf = vec![0; node::BLOCK_SIZE];
+        loop {
+            let len = remote
+                .read(&mut remote_buf)
+                .await
+                .expect("Failed to read downloaded data");
+            if len == 0 {
+                break;
+            }
+
+            // the download is drained even after a difference, it can't stop halfway
+            let Some(file) = &mut local else {
+                continue;
+            };
+            let same = file.read_exact(&mut local_buf[..len]).await.is_ok()
+                && local_buf[..len] == remote_buf[..len];
+            if !same {
+                local = None;
+            }
+        }
+
+        // the local file could have grown since it was scanned
+        match &mut local {
+            Some(file) => file.read(&mut local_buf).await.is_ok_and(|len| len == 0),
+            None => false,
+        }
+    }
+}
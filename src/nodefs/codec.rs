@@ -0,0 +1,22 @@
+use crate::node_codec;
+
+use super::NodeFS;
+
+impl NodeFS {
+    // only new and changed nodes get the new encoding, reading detects it per node
+    pub async fn set_codec(&mut self, name: String) {
+        let codec =
+            node_codec::by_name(&name).unwrap_or_else(|| panic!("Unknown node codec '{name}'"));
+        if codec.name() == self.codec.name() {
+            println!("  Nodes are already written with {name}");
+            return;
+        }
+
+        self.codec = codec;
+        self.save_superblock().await;
+
+        println!(
+            "  Nodes are written with {name} from now on, existing nodes change once they are written again"
+        );
+    }
+}
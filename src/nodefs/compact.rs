@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use indicatif::{HumanCount, MultiProgress};
+use serenity::all::{CreateAttachment, CreateMessage, MessageId};
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{self, Node},
+    node_kind::NodeKind::{Directory, File},
+    util,
+};
+
+use super::{METADATA_DIR_NAME, NodeFS};
+
+impl NodeFS {
+    // edits keep replacing a node message's attachments, nodes that are still edited long after
+    // they were created are moved to a fresh message and the old one is deleted
+    //
+    // nodes are moved deepest first, so a directory is copied with the new IDs of its entries,
+    // hard linked files are left alone since only one of their entries is known
+    //
+    // retention policies are keyed by directory node, they follow their directory to its new ID
+    pub async fn compact(
+        &mut self,
+        path: FsPath,
+        edited_after: Duration,
+        dry_run: bool,
+        key: String,
+    ) {
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Finding edited nodes in {path}"));
+
+        let (_, node_id) = self.traverse_path(&path).await;
+        let parent_id = if path.is_root() {
+            None
+        } else {
+            Some(self.traverse_path(&path.parent()).await.1)
+        };
+
+        // (depth, path, node, parent directory), the root has no parent directory
+        let mut candidates: Vec<(usize, FsPath, BlockIndex, Option<BlockIndex>)> = Vec::new();
+        let mut stack = vec![(path.clone(), node_id, parent_id, 0)];
+        let mut visited = Default::default();
+        let mut walked = 0;
+        while let Some((path, node_id, parent_id, depth)) = stack.pop() {
+            let message =
+                util::get_message(&self.client, self.data_channel, MessageId::new(node_id))
+                    .await
+                    .expect("Failed to get node");
+            let node = match self.node_bytes_from_message(&message).await {
+                Ok(bytes) => self.parse_node(bytes).map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            let node = match node {
+                Ok(node) => node,
+                Err(err) => {
+                    self.record_failure(
+                        &progress,
+                        path.to_string(),
+                        format!("Corrupted node {node_id}: {err}"),
+                    );
+                    continue;
+                }
+            };
+            walked += 1;
+            spinner.set_message(format!(
+                "Finding edited nodes in {path} ({} of {} nodes)",
+                HumanCount(candidates.len() as u64),
+                HumanCount(walked)
+            ));
+
+            // uploads edit their nodes right away, only later edits pile up
+            let created = message.id.created_at().unix_timestamp();
+            let churned = message.edited_timestamp.is_some_and(|edited| {
+                edited.unix_timestamp().saturating_sub(created) >= edited_after.as_secs() as i64
+            });
+            // metadata files are replaced rather than edited, and the retention policies are
+            // rewritten while directories move, so they are left alone
+            let metadata =
+                node.kind == File && path.parent() == FsPath::root().join(METADATA_DIR_NAME);
+            if churned && !(node.kind == File && node.links > 1) && !metadata {
+                candidates.push((depth, path.clone(), node_id, parent_id));
+            }
+
+            if node.kind == Directory {
+                if let Err(err) = self.check_traversal(&mut visited, node_id, depth) {
+                    panic!("Stopped at {path}, it's {err}");
+                }
+                stack.extend(node.entries().iter().map(|entry| {
+                    (
+                        path.join(entry.get_name()),
+                        entry.block_id(),
+                        Some(node_id),
+                        depth + 1,
+                    )
+                }));
+            }
+        }
+
+        if dry_run {
+            spinner.finish_and_clear();
+            for (_, path, node_id, _) in &candidates {
+                println!("  {path} (node {node_id})");
+            }
+            println!(
+                "  {} of {} nodes would be moved to fresh messages",
+                HumanCount(candidates.len() as u64),
+                HumanCount(walked)
+            );
+            return;
+        }
+
+        candidates.sort_by_key(|(depth, ..)| std::cmp::Reverse(*depth));
+        for (idx, (_, path, node_id, parent_id)) in candidates.iter().enumerate() {
+            spinner.set_message(format!(
+                "Compacting {path} ({} of {})",
+                idx + 1,
+                candidates.len()
+            ));
+            self.move_node(*node_id, *parent_id, &key).await;
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Moved {} of {} nodes in {path} to fresh messages",
+            HumanCount(candidates.len() as u64),
+            HumanCount(walked)
+        ));
+    }
+
+    // the copy is linked before the old message is deleted, an interruption only leaves an orphan
+    // that 'gc orphans' finds
+    async fn move_node(&mut self, node_id: BlockIndex, parent_id: Option<BlockIndex>, key: &str) {
+        // read again, entries may have moved since the walk
+        let node = self
+            .try_get_node(node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Node {node_id} is corrupted: {err}"));
+        let new_node_id = self.create_node_message(&node).await;
+
+        // entries point back at their directory, hard linked ones may belong to another one
+        if node.kind == Directory {
+            for entry in node.entries() {
+                let mut entry_node = self
+                    .try_get_node(entry.block_id())
+                    .await
+                    .unwrap_or_else(|err| panic!("Node {} is corrupted: {err}", entry.block_id()));
+                if entry_node.parent_block_id == node_id {
+                    entry_node.parent_block_id = new_node_id;
+                    self.edit_node(entry.block_id(), entry_node).await;
+                }
+            }
+        }
+
+        match parent_id {
+            Some(parent_id) => {
+                let mut parent = self.get_directory_node(parent_id).await;
+                parent.relink_directory_entries(node_id, new_node_id);
+                self.edit_directory_node(parent_id, parent).await;
+            }
+            None => {
+                self.root_node_id = new_node_id;
+                self.save_superblock().await;
+            }
+        }
+
+        if node.kind == Directory {
+            self.relink_retention_policy(node_id, new_node_id, key)
+                .await;
+        }
+
+        self.delete_block(node_id).await;
+    }
+
+    // sends the node in a single message, so it starts out without edits
+    async fn create_node_message(&self, node: &Node) -> BlockIndex {
+        let bytes = self.codec.encode(node);
+        let message = if self.format_version < 4 || bytes.len() <= node::NODE_SEGMENT_SIZE {
+            CreateMessage::new().add_file(CreateAttachment::bytes(bytes, "node"))
+        } else {
+            CreateMessage::new().add_files(bytes.chunks(node::NODE_SEGMENT_SIZE).enumerate().map(
+                |(idx, segment)| {
+                    CreateAttachment::bytes(
+                        segment.to_vec(),
+                        format!("node.{idx}.{:016x}", NodeFS::segment_hash(segment)),
+                    )
+                },
+            ))
+        };
+
+        util::send_message(&self.client, self.data_channel, message.content(""))
+            .await
+            .expect("Failed to create node")
+            .get()
+    }
+}
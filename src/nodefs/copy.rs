@@ -0,0 +1,146 @@
+use indicatif::{MultiProgress, ProgressBar};
+use serenity::{Client, all::GatewayIntents};
+
+use crate::{
+    failure::{self, Hint},
+    fs_path::RemotePath,
+    node, util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // copies a file between this filesystem and the remotes configured in the environment, the
+    // blocks are decrypted from the source and encrypted with the destination's key while they
+    // stream, so nothing is written to disk
+    pub async fn cp(&self, source: RemotePath, destination: RemotePath, key: String) {
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Copying {source} to {destination}"));
+
+        let source_remote = match &source.remote {
+            Some(name) => Some(NodeFS::open_remote(name, self.json, "download").await),
+            None => None,
+        };
+        let destination_remote = match &destination.remote {
+            Some(name) => Some(NodeFS::open_remote(name, self.json, "cp").await),
+            None => None,
+        };
+        let (src, src_key) = source_remote
+            .as_ref()
+            .map_or((self, key.as_str()), |(remote, key)| (remote, key.as_str()));
+        let (dst, dst_key) = destination_remote
+            .as_ref()
+            .map_or((self, key.as_str()), |(remote, key)| (remote, key.as_str()));
+
+        let (source_node, _) = src.get_download_source(&source.path, false).await;
+
+        // directories take the name of the source
+        let destination_path = if destination.path.is_dir() {
+            destination.path.join(source.path.name())
+        } else {
+            destination.path.clone()
+        };
+        let (file_path, file_name) = NodeFS::split_path(&destination_path, false, false);
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = dst.traverse_path(&file_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(file_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
+        let quotas = dst.quota_directories(dir_node_id).await;
+        NodeFS::check_quota(&quotas, source_node.size());
+
+        // show progress bar, the upload side counts the bytes
+        let progress_bar = progress.add(util::progress_bar(source_node.size()));
+
+        // create file node
+        let (mut file_node, file_node_id) = dst.create_file_node(dir_node_id).await;
+        let staging_node_id = dst.staging_directory().await;
+        dst.stage_node(staging_node_id, file_node_id).await;
+
+        // upload the source blocks while they're being downloaded
+        let (reader, mut writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        tokio::join!(
+            async {
+                src.download_blocks(&source_node, &mut writer, src_key, &ProgressBar::hidden())
+                    .await;
+
+                // closing the stream marks the end of the file
+                drop(writer);
+            },
+            dst.upload_blocks(reader, &mut file_node, dst_key, &progress_bar),
+        );
+
+        // update nodes
+        let size = file_node.size();
+        let blocks = file_node.blocks().len();
+        dst.edit_file_node(file_node_id, file_node).await;
+        dst.unstage_nodes(staging_node_id, &[file_node_id]).await;
+        dir_node.push_directory_entry(file_name, file_node_id);
+        dst.edit_directory_node(dir_node_id, dir_node).await;
+        dst.charge_quota(&quotas, size as i64).await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!("Finished copying {source} to {destination}"));
+        self.print_summary(
+            &progress,
+            util::TransferSummary {
+                operation: "Copied",
+                bytes: size,
+                blocks,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+    }
+
+    // 'DFS_REMOTE_<NAME>' holds the channel ID, 'DFS_REMOTE_<NAME>_AES_KEY' its key and the
+    // optional 'DFS_REMOTE_<NAME>_BOT_TOKEN' a bot for it, which defaults to 'BOT_TOKEN', and
+    // 'DFS_REMOTE_<NAME>_FS' the name of a filesystem sharing the channel
+    pub(super) async fn open_remote(name: &str, json: bool, operation: &str) -> (NodeFS, String) {
+        let prefix = format!("DFS_REMOTE_{}", name.to_uppercase().replace('-', "_"));
+        let channel: u64 = std::env::var(&prefix)
+            .unwrap_or_else(|_| {
+                failure::fail(
+                    Hint::MissingChannel,
+                    format!("Remote '{name}' isn't configured, set '{prefix}' to its channel ID"),
+                )
+            })
+            .parse()
+            .unwrap_or_else(|_| panic!("Expected a valid u64 discord channel ID in '{prefix}'"));
+        let key = std::env::var(format!("{prefix}_AES_KEY")).unwrap_or_else(|_| {
+            panic!("Requires the AES key of remote '{name}' in '{prefix}_AES_KEY'")
+        });
+        failure::ensure(
+            key.len() >= 32,
+            Hint::WrongKey,
+            format!("{prefix}_AES_KEY has {} bytes, it needs 32", key.len()),
+        );
+        let token = std::env::var(format!("{prefix}_BOT_TOKEN"))
+            .or_else(|_| std::env::var("BOT_TOKEN"))
+            .expect("Requires Discord bot token in environment variable 'BOT_TOKEN'");
+
+        let namespace = std::env::var(format!("{prefix}_FS"))
+            .ok()
+            .map(|name| util::parse_fs_name(&name).unwrap_or_else(|err| panic!("{err}")));
+
+        let client = Client::builder(token, GatewayIntents::empty())
+            .await
+            .expect("Failed to create client");
+        let mut remote = NodeFS::new(channel, namespace, client, json);
+        remote.preflight(operation).await;
+        remote.setup().await;
+
+        (remote, key)
+    }
+}
@@ -0,0 +1,55 @@
+use indicatif::HumanBytes;
+use tokio::fs;
+
+use crate::directory_entry::BlockIndex;
+
+use super::NodeFS;
+
+// low level access to node messages for manual repairs, nothing here checks the tree
+impl NodeFS {
+    pub async fn debug_dump_node(&self, node_id: BlockIndex, raw: Option<String>) {
+        let bytes = self
+            .read_node_bytes(node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to get node {node_id}: {err}"));
+
+        // the raw bytes are written first, they are what's needed if the node doesn't parse
+        if let Some(raw) = raw {
+            fs::write(&raw, &bytes)
+                .await
+                .expect("Failed to write node bytes");
+            eprintln!(
+                "  Wrote {} of node data to {raw}",
+                HumanBytes(bytes.len() as u64)
+            );
+        }
+
+        match self.parse_node(bytes) {
+            Ok(node) => println!("{node:#?}"),
+            Err(err) => panic!("Node {node_id} is corrupted: {err}"),
+        }
+    }
+
+    pub async fn debug_put_node(&self, node_id: BlockIndex, source: String, force: bool) {
+        let bytes = fs::read(&source).await.expect("Failed to read node bytes");
+
+        // bytes that don't parse would only replace one corruption with another
+        let node = self
+            .parse_node(bytes.clone())
+            .unwrap_or_else(|err| panic!("{source} is not a valid node: {err}"));
+        assert!(
+            force,
+            "Writing node bytes bypasses every consistency check, use '--force' to overwrite node {node_id}"
+        );
+
+        // the current node is checked too, so only an existing node message can be overwritten
+        self.read_node_bytes(node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to get node {node_id}: {err}"));
+        self.write_node_bytes(node_id, bytes)
+            .await
+            .expect("Failed to write node bytes");
+
+        println!("  Overwrote node {node_id} with a {:?} node", node.kind);
+    }
+}
@@ -0,0 +1,336 @@
+use std::{fmt::Display, time::Duration};
+
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    aead::{Aead, KeyInit},
+};
+use indicatif::{HumanBytes, HumanCount, HumanDuration};
+use serenity::all::Permissions;
+use tokio::fs;
+
+use crate::{
+    node::{Node, Timestamp},
+    node_codec,
+    nonce_counter::NonceCounter,
+    util,
+};
+
+use super::{NodeFS, permissions::CHECKLIST};
+
+// Discord rejects requests whose timestamps are too far off, and message ages are judged by it
+const MAX_CLOCK_SKEW: u64 = 30;
+
+impl NodeFS {
+    // runs before setup and only reads, every check is reported instead of stopping at the first
+    // problem
+    //
+    // returns false if any check failed
+    pub async fn doctor(&self, key: String) -> bool {
+        let mut healthy = true;
+        let mut report = |ok: bool, check: &str, detail: &dyn Display| {
+            healthy &= ok;
+            println!(
+                "  [{}] {check:<16}{detail}",
+                if ok { "pass" } else { "fail" }
+            );
+        };
+
+        // bot token
+        match self.client.http.get_current_user().await {
+            Ok(user) => report(true, "Bot token", &format!("logged in as {}", user.name)),
+            Err(err) => report(false, "Bot token", &util::explain_error(&err)),
+        }
+
+        // data channel and permissions
+        let channel = util::get_guild_channel(&self.client, self.data_channel).await;
+        match &channel {
+            Ok(channel) => report(true, "Data channel", &format!("#{}", channel.name)),
+            Err(err) => report(false, "Data channel", &util::explain_error(err)),
+        }
+        match self.channel_permissions().await {
+            Some(granted) => {
+                let missing: Vec<&str> = CHECKLIST
+                    .iter()
+                    .filter(|(permission, _, _, optional)| {
+                        !optional && !granted.contains(*permission)
+                    })
+                    .map(|(_, name, _, _)| *name)
+                    .collect();
+                match missing.is_empty() {
+                    true if !granted.contains(Permissions::MANAGE_MESSAGES) => report(
+                        true,
+                        "Permissions",
+                        &"all required, without Manage Messages the superblock has no backup",
+                    ),
+                    true => report(true, "Permissions", &"all granted"),
+                    false => report(
+                        false,
+                        "Permissions",
+                        &format!("missing {}", missing.join(", ")),
+                    ),
+                }
+            }
+            None => report(false, "Permissions", &"couldn't be determined"),
+        }
+
+        // superblock, both copies should be intact and agree
+        let topic = channel
+            .ok()
+            .and_then(|channel| channel.topic)
+            .filter(|topic| !topic.trim().is_empty());
+        let backup = self.try_find_superblock_backup().await;
+        let root_node_id = if let Some(name) = &self.namespace {
+            // named filesystems only have their pinned copy
+            match self.try_named_superblocks().await {
+                Ok(superblocks) => {
+                    match superblocks.into_iter().find(|(_, other, _)| other == name) {
+                        Some((_, _, sealed)) => match NodeFS::unseal_superblock(&sealed) {
+                            Ok(superblock) => {
+                                report(
+                                    true,
+                                    "Superblock",
+                                    &format!("filesystem '{name}' is intact"),
+                                );
+                                superblock
+                                    .split_whitespace()
+                                    .next()
+                                    .and_then(|root| root.parse::<u64>().ok())
+                            }
+                            Err(err) => {
+                                report(false, "Superblock", &format!("the pinned copy {err}"));
+                                None
+                            }
+                        },
+                        None => {
+                            report(
+                                true,
+                                "Superblock",
+                                &format!(
+                                    "no filesystem '{name}' yet, the first operation creates it"
+                                ),
+                            );
+                            None
+                        }
+                    }
+                }
+                Err(err) => {
+                    report(false, "Superblock", &util::explain_error(&err));
+                    None
+                }
+            }
+        } else {
+            match (&topic, &backup) {
+                (None, Ok(None)) => {
+                    report(
+                        true,
+                        "Superblock",
+                        &"none yet, the first operation creates it",
+                    );
+                    None
+                }
+                (_, Err(err)) => {
+                    report(false, "Superblock", &util::explain_error(err));
+                    None
+                }
+                (topic, Ok(backup)) => {
+                    let primary = topic.as_deref().map(NodeFS::unseal_superblock);
+                    let secondary = backup
+                        .as_ref()
+                        .map(|(_, backup)| NodeFS::unseal_superblock(backup));
+                    match (&primary, &secondary) {
+                        (Some(Ok(primary)), Some(Ok(secondary))) if primary == secondary => {
+                            report(true, "Superblock", &"topic and backup agree")
+                        }
+                        (Some(Ok(_)), Some(Ok(_))) => report(
+                            false,
+                            "Superblock",
+                            &"topic and backup differ, the next write replaces the backup",
+                        ),
+                        (Some(Ok(_)), None) => {
+                            report(true, "Superblock", &"intact, but there is no pinned backup")
+                        }
+                        (Some(Err(err)), _) => {
+                            report(false, "Superblock", &format!("the topic {err}"))
+                        }
+                        (_, Some(Err(err))) => {
+                            report(false, "Superblock", &format!("the backup {err}"))
+                        }
+                        (None, _) => report(
+                            false,
+                            "Superblock",
+                            &"the channel topic is empty, the next start restores it from the backup",
+                        ),
+                    }
+                    primary
+                        .or(secondary)
+                        .and_then(Result::ok)
+                        .and_then(|superblock| superblock.split_whitespace().next())
+                        .and_then(|root| root.parse::<u64>().ok())
+                }
+            }
+        };
+        if let Some(root_node_id) = root_node_id {
+            match self.read_node_bytes(root_node_id).await {
+                Ok(bytes) => match node_codec::decode(bytes) {
+                    Ok(node) => report(
+                        true,
+                        "Root node",
+                        &format!(
+                            "v{} with {} entries",
+                            node.version,
+                            HumanCount(node.entries().len() as u64)
+                        ),
+                    ),
+                    Err(err) => report(false, "Root node", &err),
+                },
+                Err(err) => report(false, "Root node", &util::explain_error(&err)),
+            }
+        }
+
+        // clock
+        match NodeFS::clock_skew().await {
+            Some(skew) if skew.unsigned_abs() <= MAX_CLOCK_SKEW => {
+                report(true, "Clock", &format!("{skew}s off Discord's clock"))
+            }
+            Some(skew) => report(
+                false,
+                "Clock",
+                &format!(
+                    "{} {} Discord's clock, sync the system time",
+                    HumanDuration(Duration::from_secs(skew.unsigned_abs())),
+                    if skew > 0 { "ahead of" } else { "behind" }
+                ),
+            ),
+            None => report(false, "Clock", &"couldn't get Discord's time"),
+        }
+
+        // node cache
+        match &self.cache_dir {
+            None => report(true, "Node cache", &"turned off"),
+            Some(dir) => match NodeFS::probe_cache(dir).await {
+                Ok((nodes, bytes)) => report(
+                    true,
+                    "Node cache",
+                    &format!(
+                        "{} nodes ({}) in {}",
+                        HumanCount(nodes),
+                        HumanBytes(bytes),
+                        dir.display()
+                    ),
+                ),
+                Err(err) => report(
+                    false,
+                    "Node cache",
+                    &format!("{} isn't writable: {err}", dir.display()),
+                ),
+            },
+        }
+
+        // encryption key
+        let cypher = (key.len() >= 32)
+            .then(|| Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).ok())
+            .flatten();
+        match cypher {
+            None => report(
+                false,
+                "Encryption key",
+                &format!("AES_KEY has {} bytes, it needs 32", key.len()),
+            ),
+            Some(cypher) => {
+                let nonce = NonceCounter::new().get_nonce();
+                let data = b"dfs doctor self-test";
+                let roundtrip = cypher
+                    .encrypt(&nonce, data.as_slice())
+                    .and_then(|encrypted| cypher.decrypt(&nonce, encrypted.as_slice()));
+                match roundtrip {
+                    Ok(decrypted) if decrypted == data => {
+                        report(true, "Encryption key", &"self-test passed")
+                    }
+                    _ => report(false, "Encryption key", &"self-test failed"),
+                }
+            }
+        }
+
+        healthy
+    }
+
+    // seconds the local clock is ahead of Discord's, read from the Date header of an API response
+    async fn clock_skew() -> Option<i64> {
+        let response = reqwest::Client::new()
+            .head("https://discord.com/api/v10/gateway")
+            .send()
+            .await
+            .ok()?;
+        let now = Node::now();
+        let date = response.headers().get("date")?.to_str().ok()?;
+        let remote = NodeFS::parse_http_date(date)?;
+        Some(now as i64 - remote as i64)
+    }
+
+    // 'Thu, 16 Oct 2026 12:00:00 GMT'
+    fn parse_http_date(date: &str) -> Option<Timestamp> {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+
+        let [_, day, month, year, time, "GMT"] =
+            <[&str; 6]>::try_from(date.split_whitespace().collect::<Vec<&str>>()).ok()?
+        else {
+            return None;
+        };
+        let day: i64 = day.parse().ok()?;
+        let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+        let year: i64 = year.parse().ok()?;
+        let mut time = time.split(':').map(|part| part.parse::<i64>().ok());
+        let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+
+        // days since the epoch of a date in the proleptic gregorian calendar
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        Timestamp::try_from(days * 86400 + hours * 3600 + minutes * 60 + seconds).ok()
+    }
+
+    // writes and removes a probe file, then measures what is cached
+    async fn probe_cache(dir: &std::path::Path) -> std::io::Result<(u64, u64)> {
+        fs::create_dir_all(dir).await?;
+        let probe = dir.join("probe");
+        fs::write(&probe, b"").await?;
+        fs::remove_file(&probe).await?;
+
+        let mut nodes = 0;
+        let mut bytes = 0;
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            nodes += 1;
+            bytes += entry.metadata().await?.len();
+        }
+        Ok((nodes, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_http_dates() {
+        assert_eq!(
+            NodeFS::parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(0)
+        );
+        assert_eq!(
+            NodeFS::parse_http_date("Fri, 16 Oct 2026 12:34:56 GMT"),
+            Some(1_792_154_096)
+        );
+        assert_eq!(
+            NodeFS::parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"),
+            Some(951_782_400)
+        );
+        assert_eq!(NodeFS::parse_http_date("yesterday"), None);
+    }
+}
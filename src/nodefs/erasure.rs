@@ -0,0 +1,333 @@
+use std::{collections::HashSet, fmt, str::FromStr, time::Duration};
+
+use futures::future;
+use indicatif::{HumanCount, ProgressBar};
+use serenity::all::{ChannelId, CreateAttachment, CreateMessage, Message, MessageId};
+
+use crate::{
+    directory_entry::BlockIndex,
+    failure::{self, Hint},
+    node::Node,
+    reed_solomon, util,
+};
+
+use super::NodeFS;
+
+// name of the data channel attachment that lists the shards of a block
+pub(super) const STRIPE_NAME: &str = "stripe";
+
+// where a shard of a stripe is as (channel, message, crc32)
+type Shard = (ChannelId, MessageId, u32);
+
+// the superblock holds the channel IDs, the topic has room for about this many
+const MAX_STRIPE_CHANNELS: usize = 32;
+
+// stored in the superblock as 'ec:<data>+<parity>:<channel>,<channel>,...', one shard of every
+// block goes to each channel
+pub(super) struct Erasure {
+    pub(super) data: usize,
+    pub(super) parity: usize,
+    pub(super) channels: Vec<ChannelId>,
+}
+
+impl FromStr for Erasure {
+    type Err = String;
+
+    fn from_str(layout: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid erasure coding '{layout}' in the superblock");
+        let (shards, channels) = layout.split_once(':').ok_or_else(invalid)?;
+        let (data, parity) = shards.split_once('+').ok_or_else(invalid)?;
+        let erasure = Erasure {
+            data: data.parse().map_err(|_| invalid())?,
+            parity: parity.parse().map_err(|_| invalid())?,
+            channels: channels
+                .split(',')
+                .map(|channel| NodeFS::parse_id(channel).map(ChannelId::new))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+        };
+        if erasure.data == 0 || erasure.channels.len() != erasure.data + erasure.parity {
+            return Err(invalid());
+        }
+
+        Ok(erasure)
+    }
+}
+
+impl fmt::Display for Erasure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels: Vec<String> = self.channels.iter().map(ToString::to_string).collect();
+        write!(f, "{}+{}:{}", self.data, self.parity, channels.join(","))
+    }
+}
+
+impl NodeFS {
+    // new blocks are split into `data` shards and `parity` shards are added, one in each channel,
+    // so a block survives the loss of up to `parity` of the channels. the nodes stay in the data
+    // channel together with a small stripe message per block that points at its shards, so the
+    // data channel itself isn't protected
+    //
+    // blocks keep the layout they were written with, changing it only affects new blocks
+    pub async fn set_erasure(&mut self, data: usize, parity: usize, channels: Vec<u64>) {
+        assert!(
+            data > 0 && parity > 0,
+            "Stripes need at least one data and one parity shard"
+        );
+        assert!(
+            channels.len() == data + parity,
+            "{data} data and {parity} parity shards need {} channels, got {}",
+            data + parity,
+            channels.len()
+        );
+        assert!(
+            channels.len() <= MAX_STRIPE_CHANNELS,
+            "Stripes can use at most {MAX_STRIPE_CHANNELS} channels"
+        );
+        assert!(
+            channels.iter().collect::<HashSet<_>>().len() == channels.len(),
+            "Every shard needs its own channel, a channel is listed twice"
+        );
+
+        let channels: Vec<ChannelId> = channels.into_iter().map(ChannelId::new).collect();
+        for channel in &channels {
+            if let Err(err) = util::get_guild_channel(&self.client, *channel).await {
+                failure::fail(
+                    Hint::MissingChannel,
+                    format!(
+                        "Failed to open shard channel {channel}: {}",
+                        util::explain_error(&err)
+                    ),
+                );
+            }
+        }
+
+        self.erasure = Some(Erasure {
+            data,
+            parity,
+            channels,
+        });
+        self.save_superblock().await;
+
+        println!(
+            "  New blocks are striped over {} channels, they survive the loss of {parity} of them",
+            data + parity
+        );
+    }
+
+    // the shards are uploaded at the same time, the stripe message is the block ID nodes refer to.
+    // nothing refers to the shards until the stripe message is sent, so they are deleted again
+    // when any part of the stripe fails
+    pub(super) async fn create_stripe(&self, erasure: &Erasure, block: Vec<u8>) -> BlockIndex {
+        let shards = reed_solomon::encode(&block, erasure.data, erasure.parity);
+        let crcs: Vec<u32> = shards.iter().map(|shard| util::crc32(shard)).collect();
+        let sent = future::join_all(shards.into_iter().zip(&erasure.channels).enumerate().map(
+            |(idx, (shard, channel))| async move {
+                let attachment = CreateAttachment::bytes(shard, format!("shard.{idx}"));
+                util::send_message(
+                    &self.client,
+                    *channel,
+                    CreateMessage::new().content("").add_file(attachment),
+                )
+                .await
+                .map_err(|err| {
+                    format!(
+                        "Failed to create shard {idx} in channel {channel}: {}",
+                        util::explain_error(&err)
+                    )
+                })
+            },
+        ))
+        .await;
+        let created: Vec<(ChannelId, MessageId)> = erasure
+            .channels
+            .iter()
+            .zip(&sent)
+            .filter_map(|(channel, result)| Some((*channel, *result.as_ref().ok()?)))
+            .collect();
+        if let Some(Err(err)) = sent.iter().find(|result| result.is_err()) {
+            self.delete_created_shards(&created).await;
+            panic!("{err}");
+        }
+
+        // '<block size> <data>+<parity> <channel>:<message>:<crc32> ...'
+        let mut index = format!("{} {}+{}", block.len(), erasure.data, erasure.parity);
+        for ((channel, message_id), crc) in created.iter().zip(crcs) {
+            index.push_str(&format!(" {channel}:{message_id}:{crc:08x}"));
+        }
+        match util::send_message(
+            &self.client,
+            self.data_channel,
+            CreateMessage::new()
+                .content("")
+                .add_file(CreateAttachment::bytes(index.into_bytes(), STRIPE_NAME)),
+        )
+        .await
+        {
+            Ok(message_id) => message_id.get(),
+            Err(err) => {
+                self.delete_created_shards(&created).await;
+                panic!("Failed to create data block: {}", util::explain_error(&err));
+            }
+        }
+    }
+
+    // shards that couldn't be deleted are left to 'gc orphans'
+    async fn delete_created_shards(&self, created: &[(ChannelId, MessageId)]) {
+        for (channel, message_id) in created {
+            let _ = util::delete_message(&self.client, *channel, *message_id).await;
+        }
+    }
+
+    // shards in the shard channels that no stripe message in the data channel lists, e.g. from an
+    // upload that was killed before it sent the stripe message. unreadable stripes are counted,
+    // their shards would look orphaned as well
+    pub(super) async fn orphaned_shards(
+        &self,
+        stripes: &[Message],
+        min_age: Duration,
+        spinner: &ProgressBar,
+    ) -> (Vec<(ChannelId, MessageId)>, usize) {
+        let Some(erasure) = &self.erasure else {
+            return (Vec::new(), 0);
+        };
+
+        let mut listed = HashSet::new();
+        let mut unreadable = 0;
+        for (idx, stripe) in stripes.iter().enumerate() {
+            spinner.set_message(format!(
+                "Reading stripe indexes ({} of {})",
+                idx + 1,
+                stripes.len()
+            ));
+            let shards = match stripe.attachments.first() {
+                Some(attachment) => util::download_attachment(attachment)
+                    .await
+                    .ok()
+                    .and_then(|index| NodeFS::parse_stripe(&index)),
+                None => None,
+            };
+            match shards {
+                Some((_, _, shards)) => listed.extend(
+                    shards
+                        .into_iter()
+                        .map(|(channel, message_id, _)| (channel, message_id)),
+                ),
+                None => unreadable += 1,
+            }
+        }
+
+        // recent shards may belong to a stripe that is still being written
+        let now = Node::now();
+        let mut orphans = Vec::new();
+        for channel in &erasure.channels {
+            let mut scanner = util::ChannelScanner::new(*channel);
+            while let Some(page) = scanner
+                .next_page(&self.client)
+                .await
+                .expect("Failed to get messages")
+            {
+                orphans.extend(
+                    page.iter()
+                        .filter(|message| self.check_author(message).is_ok())
+                        .filter(|message| !listed.contains(&(*channel, message.id)))
+                        .filter(|message| {
+                            now.saturating_sub(message.id.created_at().unix_timestamp() as u64)
+                                >= min_age.as_secs()
+                        })
+                        .map(|message| (*channel, message.id)),
+                );
+                spinner.set_message(format!(
+                    "Scanning shard channel {channel} ({} orphaned shards)",
+                    HumanCount(orphans.len() as u64)
+                ));
+            }
+        }
+
+        (orphans, unreadable)
+    }
+
+    // the data shards are read first, the parity shards only when some of them are lost or
+    // don't match their CRC, the error completes 'data block ...'
+    pub(super) async fn read_stripe(&self, index: &[u8]) -> Result<Vec<u8>, String> {
+        let (len, data, shards) = NodeFS::parse_stripe(index)
+            .ok_or_else(|| String::from("has a corrupted stripe index"))?;
+
+        let mut fetched =
+            future::join_all(shards[..data].iter().map(|shard| self.get_shard(*shard))).await;
+        if fetched.iter().any(Option::is_none) {
+            fetched.extend(
+                future::join_all(shards[data..].iter().map(|shard| self.get_shard(*shard))).await,
+            );
+        }
+
+        reed_solomon::decode(&fetched, data, len).map_err(|err| format!("can't be rebuilt, {err}"))
+    }
+
+    // the shards of a stripe are deleted with it, shards in lost channels are left alone
+    pub(super) async fn delete_shards(&self, block_id: BlockIndex) {
+        if self.erasure.is_none() {
+            return;
+        }
+        let Ok(message) =
+            util::get_message(&self.client, self.data_channel, MessageId::new(block_id)).await
+        else {
+            return;
+        };
+        let Some(attachment) = message
+            .attachments
+            .first()
+            .filter(|attachment| attachment.filename == STRIPE_NAME)
+        else {
+            return;
+        };
+        let Some((_, _, shards)) = util::download_attachment(attachment)
+            .await
+            .ok()
+            .and_then(|index| NodeFS::parse_stripe(&index))
+        else {
+            return;
+        };
+
+        for (channel, message_id, _) in shards {
+            let _ = util::delete_message(&self.client, channel, message_id).await;
+        }
+    }
+
+    // a shard that can't be read is as good as lost
+    async fn get_shard(&self, (channel, message_id, crc): Shard) -> Option<Vec<u8>> {
+        let message = util::get_message(&self.client, channel, message_id)
+            .await
+            .ok()?;
+        self.check_author(&message).ok()?;
+        let shard = util::download_attachment(message.attachments.first()?)
+            .await
+            .ok()?;
+
+        (util::crc32(&shard) == crc).then_some(shard)
+    }
+
+    // (block size, data shards, every shard)
+    fn parse_stripe(index: &[u8]) -> Option<(usize, usize, Vec<Shard>)> {
+        let mut fields = std::str::from_utf8(index).ok()?.split_whitespace();
+        let len = fields.next()?.parse().ok()?;
+        let (data, parity) = fields.next()?.split_once('+')?;
+        let (data, parity): (usize, usize) = (data.parse().ok()?, parity.parse().ok()?);
+        let shards = fields
+            .map(|shard| {
+                let mut parts = shard.split(':');
+                Some((
+                    ChannelId::new(NodeFS::parse_id(parts.next()?)?),
+                    MessageId::new(NodeFS::parse_id(parts.next()?)?),
+                    u32::from_str_radix(parts.next()?, 16).ok()?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        (data > 0 && shards.len() == data + parity).then_some((len, data, shards))
+    }
+
+    // IDs are never 0, serenity panics on them
+    fn parse_id(id: &str) -> Option<u64> {
+        id.parse().ok().filter(|id| *id != 0)
+    }
+}
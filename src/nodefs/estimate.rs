@@ -0,0 +1,123 @@
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use indicatif::{HumanBytes, HumanCount, HumanDuration};
+use tokio::fs;
+
+use crate::{
+    fs_path::FsPath,
+    node::{self, Size},
+    node_kind::NodeKind::{Directory, File, Symlink},
+    util,
+};
+
+use super::NodeFS;
+
+// every block is encrypted on its own, which adds the authentication tag
+const BLOCK_OVERHEAD: Size = 16;
+
+// creating, staging, filling and unstaging a file node and adding its entry
+const UPLOAD_CALLS_PER_FILE: u64 = 5;
+
+impl NodeFS {
+    // nothing is changed, only local metadata and remote nodes are read
+    pub async fn estimate_upload(&self, sources: Vec<String>, chunk_size: Option<Size>) {
+        let chunk_size = self.check_chunk_size(chunk_size);
+
+        // directories count like they were uploaded with --tar, as one file
+        let mut files = 0;
+        let mut bytes = 0;
+        let mut blocks = 0;
+        for source in &sources {
+            let metadata = fs::metadata(source)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to open {source}: {err}"));
+            let size = if metadata.is_dir() {
+                let (sizes, _) = NodeFS::local_file_sizes(PathBuf::from(source)).await;
+                sizes.values().map(|(size, _)| size).sum()
+            } else {
+                metadata.len()
+            };
+
+            files += 1;
+            bytes += size;
+            blocks += size.div_ceil(chunk_size);
+        }
+
+        let calls = blocks + files * UPLOAD_CALLS_PER_FILE;
+        println!(
+            "  Files             {} ({})",
+            HumanCount(files),
+            HumanBytes(bytes)
+        );
+        println!(
+            "  Blocks            {} of {}",
+            HumanCount(blocks),
+            HumanBytes(chunk_size)
+        );
+        println!("  API calls         about {}", HumanCount(calls));
+        println!(
+            "  Storage used      {}",
+            HumanBytes(bytes + blocks * BLOCK_OVERHEAD)
+        );
+        match self.average_throughput("uploaded") {
+            Some(throughput) => println!(
+                "  Duration          about {} at {}/s",
+                HumanDuration(Duration::from_secs_f64(bytes as f64 / throughput)),
+                HumanBytes(throughput as u64)
+            ),
+            None => println!("  Duration          unknown, set DFS_STATS=on to measure throughput"),
+        }
+    }
+
+    pub async fn estimate_rm(&self, path: FsPath) {
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Measuring {path}"));
+
+        let (node, node_id) = self.traverse_path(&path).await;
+        let mut files = 0;
+        let mut bytes = 0;
+        let mut calls = 1;
+        let mut visited = HashSet::new();
+        let mut stack = vec![(node, node_id, 0)];
+        while let Some((node, node_id, depth)) = stack.pop() {
+            match node.kind {
+                // hard linked files only lose a link
+                File if node.links > 1 => calls += 1,
+                File => {
+                    files += 1;
+                    bytes += node.size();
+                    calls += 1 + node
+                        .blocks()
+                        .iter()
+                        .filter(|block_id| **block_id != node::HOLE)
+                        .count() as u64;
+                }
+                Symlink => calls += 1,
+                Directory => {
+                    if let Err(err) = self.check_traversal(&mut visited, node_id, depth) {
+                        panic!("Failed to measure {path}: {err}");
+                    }
+                    calls += 1;
+                    for entry in node.entries() {
+                        spinner.set_message(format!("Measuring {path}{}", entry.get_name()));
+                        if let Ok(entry_node) = self.try_get_node(entry.block_id()).await {
+                            stack.push((entry_node, entry.block_id(), depth + 1));
+                        }
+                    }
+                }
+            }
+        }
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        println!(
+            "  Files             {} ({})",
+            HumanCount(files),
+            HumanBytes(bytes)
+        );
+        println!("  API calls         about {}", HumanCount(calls));
+        println!("  Storage freed     {}", HumanBytes(bytes));
+    }
+}
@@ -0,0 +1,38 @@
+use indicatif::{HumanCount, MultiProgress};
+
+use super::NodeFS;
+
+// bulk operations skip entries that fail on their own and continue with the rest, they are
+// listed again at the end so nothing gets lost in the progress output
+impl NodeFS {
+    pub(super) fn record_failure(&self, progress: &MultiProgress, entry: String, reason: String) {
+        progress
+            .println(format!("  Failed {entry}: {reason}"))
+            .unwrap();
+        self.failures.lock().unwrap().push((entry, reason));
+    }
+
+    // returns whether the operation only completed partially
+    pub fn report_failures(&self) -> bool {
+        let failures = self.failures.lock().unwrap();
+        if failures.is_empty() {
+            return false;
+        }
+
+        let width = failures
+            .iter()
+            .map(|(entry, _)| entry.chars().count())
+            .max()
+            .unwrap_or(0);
+        eprintln!();
+        eprintln!(
+            "  Completed with {} failed entries:",
+            HumanCount(failures.len() as u64)
+        );
+        for (entry, reason) in failures.iter() {
+            eprintln!("  {entry:<width$}  {reason}");
+        }
+
+        true
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashSet};
+
+use indicatif::HumanCount;
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{LinkCount, Node},
+    node_kind::NodeKind::File,
+    on_conflict::OnConflict,
+    util,
+};
+
+use super::{NodeFS, plan::Action};
+
+impl NodeFS {
+    // walks every directory, only directory nodes are fetched so it stays cheap on large trees,
+    // a report is meant to be complete so it reads the other nodes too
+    pub async fn fsck(&self, repair: bool, report_only: Option<String>) {
+        // show progress information
+        let spinner = util::spinner();
+
+        let mut visited = HashSet::new();
+        let mut stack = vec![(FsPath::root(), self.root_node_id)];
+        let mut checked = 0;
+        let mut duplicates = 0;
+        let mut unreadable = 0;
+        let mut actions = Vec::new();
+        let mut findings = Vec::new();
+        let mut linked: BTreeMap<BlockIndex, (FsPath, LinkCount)> = BTreeMap::new();
+        while let Some((dir_path, dir_node_id)) = stack.pop() {
+            // a directory reachable twice means broken entries, it's only checked once
+            if !visited.insert(dir_node_id) {
+                let detail = format!(
+                    "{dir_path} refers to node {dir_node_id}, which is already part of the tree"
+                );
+                spinner.println(format!("  {detail}"));
+                findings.push((String::from("shared-directory"), detail));
+                continue;
+            }
+
+            spinner.set_message(format!("Checking {dir_path}"));
+            let mut dir_node = match self.try_get_fsck_node(dir_node_id).await {
+                Ok(dir_node) => dir_node,
+                Err((finding, err)) => {
+                    unreadable += 1;
+                    let detail = format!("{dir_path} (node {dir_node_id}) {err}");
+                    spinner.println(format!("  {detail}"));
+                    findings.push((format!("{finding}-directory"), detail));
+                    continue;
+                }
+            };
+            checked += 1;
+
+            let positions = dir_node.duplicate_entries();
+            duplicates += positions.len();
+            for pos in &positions {
+                let name = dir_node.entries()[*pos].get_name().clone();
+                if !repair && report_only.is_none() {
+                    spinner.println(format!("  {dir_path}{name} is a duplicate entry"));
+                    continue;
+                }
+
+                // planned renames are made on the local copy too, so they don't collide
+                let new_name = (1..)
+                    .map(|n| OnConflict::numbered_name(&name, n))
+                    .find(|new_name| !dir_node.contains_entry(new_name))
+                    .unwrap();
+                dir_node.rename_directory_entry_at(*pos, &new_name);
+                if repair {
+                    spinner.println(format!(
+                        "  Renamed duplicate entry {dir_path}{name} to {new_name}"
+                    ));
+                } else {
+                    spinner.println(format!(
+                        "  {dir_path}{name} is a duplicate entry, the report renames it to {new_name}"
+                    ));
+                    actions.push(Action::RenameEntry {
+                        dir_node_id,
+                        position: *pos,
+                        name,
+                        new_name,
+                    });
+                }
+            }
+
+            stack.extend(
+                dir_node
+                    .entries()
+                    .iter()
+                    .filter(|entry| entry.get_name().ends_with('/'))
+                    .map(|entry| (dir_path.join(entry.get_name()), entry.block_id())),
+            );
+            if report_only.is_some() {
+                for entry in dir_node.entries() {
+                    if !entry.get_name().ends_with('/') {
+                        linked
+                            .entry(entry.block_id())
+                            .or_insert_with(|| (dir_path.join(entry.get_name()), 0))
+                            .1 += 1;
+                    }
+                }
+            }
+
+            if repair && !positions.is_empty() {
+                self.edit_directory_node(dir_node_id, dir_node).await;
+            }
+        }
+
+        // hard links are the only way to share a node, so every entry of a file is one link
+        let mut dangling = 0;
+        let mut drifted = 0;
+        for (idx, (node_id, (path, entries))) in linked.iter().enumerate() {
+            spinner.set_message(format!("Checking links ({} of {})", idx + 1, linked.len()));
+            match self.try_get_fsck_node(*node_id).await {
+                Ok(node) if node.kind == File && node.links != *entries => {
+                    drifted += 1;
+                    let detail = format!(
+                        "{path} (node {node_id}) counts {} links, {entries} entries refer to it",
+                        node.links
+                    );
+                    spinner.println(format!("  {detail}"));
+                    findings.push((String::from("link-count"), detail));
+                }
+                Ok(_) => {}
+                Err((finding, err)) => {
+                    dangling += 1;
+                    let detail = format!("{path} (node {node_id}) {err}");
+                    spinner.println(format!("  {detail}"));
+                    findings.push((format!("{finding}-entry"), detail));
+                }
+            }
+        }
+
+        // interrupted operations leave their nodes staged
+        if let Some(staging_node_id) = self.find_staging_directory().await {
+            let staged = self
+                .get_directory_node(staging_node_id)
+                .await
+                .entries()
+                .len();
+            if staged > 0 {
+                let detail = format!(
+                    "{staged} nodes are staged by running or interrupted operations, 'gc staging' deletes the leftovers"
+                );
+                spinner.println(format!("  {detail}"));
+                findings.push((String::from("staged-nodes"), detail));
+            }
+        }
+
+        // legacy superblocks are only rewritten here and by 'migrate'
+        if self.superblock_outdated && !repair {
+            let detail = String::from(
+                "The superblock has no CRC or its copy in the topic is lost, 'fsck --repair' saves it again",
+            );
+            spinner.println(format!("  {detail}"));
+            findings.push((String::from("outdated-superblock"), detail));
+        }
+        if self.owner.is_none() {
+            let detail =
+                String::from("The superblock names no owner, messages by any author are trusted");
+            spinner.println(format!("  {detail}"));
+            findings.push((String::from("no-owner"), detail));
+        }
+        if repair && self.repair_superblock().await {
+            spinner.println("  Saved the superblock again with a CRC and a backup");
+        }
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        println!(
+            "  Checked {} directories, found {} duplicate entries and {} unreadable directories",
+            HumanCount(checked),
+            HumanCount(duplicates as u64),
+            HumanCount(unreadable)
+        );
+        if report_only.is_some() {
+            println!(
+                "  Checked {} other nodes, found {} dangling or unreadable entries and {} wrong link counts",
+                HumanCount(linked.len() as u64),
+                HumanCount(dangling),
+                HumanCount(drifted)
+            );
+        }
+        if let Some(report) = report_only {
+            self.write_report(
+                &report,
+                "fsck",
+                &[("checked", checked)],
+                &actions,
+                &findings,
+            );
+        } else if duplicates > 0 && !repair {
+            println!("  Run 'fsck --repair' to rename the duplicates to 'name (1)'");
+        }
+    }
+
+    // (finding, reason) for nodes that can't be checked, the messages of dangling entries are gone
+    async fn try_get_fsck_node(&self, node_id: BlockIndex) -> Result<Node, (&'static str, String)> {
+        match self.read_node_bytes(node_id).await {
+            Ok(bytes) => self
+                .parse_node(bytes)
+                .map_err(|err| ("unreadable", format!("is unreadable: {err}"))),
+            Err(err) if util::is_unknown_message(&err) => {
+                Err(("dangling", String::from("doesn't exist anymore")))
+            }
+            Err(serenity::Error::Other(reason)) => {
+                Err(("unreadable", format!("is unreadable: {reason}")))
+            }
+            Err(err) => panic!("Failed to get node: {err}"),
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use indicatif::{HumanCount, MultiProgress, ProgressBar};
+use serenity::all::{Message, MessageId};
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{self, Node},
+    node_codec,
+    node_kind::NodeKind::{Directory, File, Symlink},
+    util,
+};
+
+use super::{NodeFS, erasure::STRIPE_NAME, plan::Action};
+
+impl NodeFS {
+    // unlinks right away, the data is deleted later by 'gc run-queue'
+    pub(super) async fn rm_deferred(&self, path: FsPath, recursive: bool, key: String) {
+        let (_, node_id) = self.traverse_path(&path).await;
+
+        // the entry is removed first, a queued node must never be reachable anymore
+        self.__rm(path.clone(), true, recursive, 1, &MultiProgress::new())
+            .await;
+
+        let mut queue = self.load_deletion_queue(&key).await;
+        queue.push((node_id, path.to_string()));
+        self.save_deletion_queue(&queue, &key).await;
+
+        println!(
+            "  Queued {path} for deletion ({} entries in the queue)",
+            queue.len()
+        );
+    }
+
+    pub async fn gc_run_queue(&self, key: String) {
+        let progress = MultiProgress::new();
+        let mut queue = self.load_deletion_queue(&key).await;
+        if queue.is_empty() {
+            println!("  The deletion queue is empty");
+            return;
+        }
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        let total = queue.len();
+
+        // deletes are sent one after another so the rate limits are respected, the
+        // queue is saved after every entry so an interrupted run continues where it stopped
+        while let Some((node_id, path)) = queue.first().cloned() {
+            spinner.set_message(format!(
+                "Deleting {path} ({} of {total})",
+                total - queue.len() + 1
+            ));
+
+            match self.try_get_queued_node(node_id).await {
+                Ok(node) => match node.kind {
+                    Directory => {
+                        self.delete_directory(node, node_id, &path, 1, &progress)
+                            .await
+                    }
+                    File => self.unlink_file(node, node_id, &path, &progress).await,
+                    Symlink => self.delete_block(node_id).await,
+                },
+                Err(err) => progress
+                    .println(format!(
+                        "  Skipping corrupted node {node_id} ({path}), its data is left behind: {err}"
+                    ))
+                    .unwrap(),
+            }
+
+            queue.remove(0);
+            self.save_deletion_queue(&queue, &key).await;
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!("Deleted {total} queued entries"));
+    }
+}
+
+impl NodeFS {
+    // messages nothing in the filesystem refers to, found by scanning the channel history
+    // instead of fetching every node on its own
+    //
+    // with erasure coding the shard channels are scanned as well, shards no stripe message lists
+    // are orphans too
+    pub async fn gc_orphans(
+        &self,
+        delete: bool,
+        min_age: Duration,
+        report_only: Option<String>,
+        key: String,
+    ) {
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message("Scanning channel history");
+
+        // only node messages are kept, everything else just needs its ID
+        let mut scanner = util::ChannelScanner::new(self.data_channel);
+        let mut nodes: HashMap<BlockIndex, Message> = HashMap::new();
+        let mut stripes = Vec::new();
+        let mut messages = Vec::new();
+        let mut foreign = 0;
+        while let Some(page) = scanner
+            .next_page(&self.client)
+            .await
+            .expect("Failed to get messages")
+        {
+            for message in page {
+                if self.check_author(&message).is_err() {
+                    foreign += 1;
+                    continue;
+                }
+
+                messages.push(message.id);
+                if message
+                    .attachments
+                    .iter()
+                    .any(|attachment| attachment.filename.starts_with("node"))
+                {
+                    nodes.insert(message.id.get(), message);
+                } else if message
+                    .attachments
+                    .first()
+                    .is_some_and(|attachment| attachment.filename == STRIPE_NAME)
+                {
+                    stripes.push(message);
+                }
+            }
+            spinner.set_message(format!(
+                "Scanning channel history ({} messages)",
+                HumanCount(messages.len() as u64)
+            ));
+        }
+
+        let (reachable, corrupted) = self
+            .reachable_messages(&nodes, &key, &progress, &spinner)
+            .await;
+        let (shards, unreadable_stripes) = self.orphaned_shards(&stripes, min_age, &spinner).await;
+
+        // recent messages may belong to an upload that is still running
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the unix epoch")
+            .as_secs();
+        let orphans: Vec<MessageId> = messages
+            .into_iter()
+            .filter(|message_id| !reachable.contains(&message_id.get()))
+            .filter(|message_id| {
+                now.saturating_sub(message_id.created_at().unix_timestamp() as u64)
+                    >= min_age.as_secs()
+            })
+            .collect();
+
+        spinner.finish_with_message(format!(
+            "Found {} orphaned messages ({} reachable, {foreign} by other authors left alone)",
+            HumanCount(orphans.len() as u64),
+            HumanCount(reachable.len() as u64)
+        ));
+        if self.erasure.is_some() {
+            progress
+                .println(format!(
+                    "  Found {} orphaned shards",
+                    HumanCount(shards.len() as u64)
+                ))
+                .unwrap();
+        }
+        if let Some(report) = report_only {
+            let mut actions: Vec<Action> = orphans
+                .iter()
+                .map(|message_id| Action::DeleteMessage {
+                    message_id: message_id.get(),
+                })
+                .collect();
+            actions.extend(
+                shards
+                    .iter()
+                    .map(|(channel, message_id)| Action::DeleteShard {
+                        channel_id: channel.get(),
+                        message_id: message_id.get(),
+                    }),
+            );
+            let mut findings = Vec::new();
+            if corrupted > 0 {
+                findings.push((
+                    String::from("unreadable-nodes"),
+                    format!("{corrupted} nodes couldn't be read, their data may look orphaned"),
+                ));
+            }
+            if unreadable_stripes > 0 {
+                findings.push((
+                    String::from("unreadable-stripes"),
+                    format!(
+                        "{unreadable_stripes} stripe indexes couldn't be read, their shards may look orphaned"
+                    ),
+                ));
+            }
+            self.write_report(
+                &report,
+                "gc-orphans",
+                &[("corrupted", (corrupted + unreadable_stripes) as u64)],
+                &actions,
+                &findings,
+            );
+            return;
+        }
+        if !delete || (orphans.is_empty() && shards.is_empty()) {
+            return;
+        }
+
+        // the blocks of an unreadable node would look orphaned as well
+        assert!(
+            corrupted == 0,
+            "Not deleting anything, {corrupted} nodes couldn't be read and their data may look orphaned"
+        );
+        assert!(
+            unreadable_stripes == 0,
+            "Not deleting anything, {unreadable_stripes} stripe indexes couldn't be read and their shards may look orphaned"
+        );
+
+        let spinner = progress.add(util::spinner());
+        for (idx, message_id) in orphans.iter().enumerate() {
+            spinner.set_message(format!(
+                "Deleting orphaned messages ({} of {})",
+                idx + 1,
+                orphans.len()
+            ));
+            self.delete_data_block(message_id.get()).await;
+        }
+        for (idx, (channel, message_id)) in shards.iter().enumerate() {
+            spinner.set_message(format!(
+                "Deleting orphaned shards ({} of {})",
+                idx + 1,
+                shards.len()
+            ));
+            util::delete_message(&self.client, *channel, *message_id)
+                .await
+                .expect("Failed to delete shard");
+        }
+        spinner.finish_with_message(format!(
+            "Deleted {} orphaned messages and {} orphaned shards",
+            HumanCount(orphans.len() as u64),
+            HumanCount(shards.len() as u64)
+        ));
+    }
+
+    // messages the filesystem refers to, nodes are read from the scanned node messages
+    pub(super) async fn reachable_messages(
+        &self,
+        nodes: &HashMap<BlockIndex, Message>,
+        key: &str,
+        progress: &MultiProgress,
+        spinner: &ProgressBar,
+    ) -> (HashSet<BlockIndex>, usize) {
+        // queued deletions are unlinked already but their data is still in use
+        let mut reachable = HashSet::new();
+        let (roots, superblocks) = self.channel_filesystems().await;
+        reachable.extend(superblocks.iter().map(|message_id| message_id.get()));
+        let mut stack = roots.clone();
+        for root_node_id in roots {
+            stack.extend(
+                self.load_deletion_queue_of(root_node_id, key)
+                    .await
+                    .into_iter()
+                    .map(|(node_id, _)| node_id),
+            );
+        }
+        let mut corrupted = 0;
+        while let Some(node_id) = stack.pop() {
+            if !reachable.insert(node_id) {
+                continue;
+            }
+            spinner.set_message(format!(
+                "Walking the filesystem ({} messages reachable)",
+                HumanCount(reachable.len() as u64)
+            ));
+
+            let node = match nodes.get(&node_id) {
+                Some(message) => self
+                    .node_bytes_from_message(message)
+                    .await
+                    .map_err(|err| err.to_string())
+                    // other filesystems in the channel may have another format version
+                    .and_then(|bytes| node_codec::decode(bytes).map_err(|err| err.to_string())),
+                None => Err(String::from("missing from the channel")),
+            };
+            let node = match node {
+                Ok(node) => node,
+                Err(err) => {
+                    progress
+                        .println(format!("  Node {node_id} is unusable: {err}"))
+                        .unwrap();
+                    corrupted += 1;
+                    continue;
+                }
+            };
+
+            match node.kind {
+                Directory => stack.extend(node.entries().iter().map(|entry| entry.block_id())),
+                File => reachable.extend(
+                    node.blocks()
+                        .iter()
+                        .filter(|block_id| **block_id != node::HOLE),
+                ),
+                Symlink => {}
+            }
+        }
+
+        (reachable, corrupted)
+    }
+
+    // queued nodes may have been queued before a migration by an older dfs, so any version up to
+    // the filesystem's is accepted
+    async fn try_get_queued_node(&self, node_id: BlockIndex) -> Result<Node, String> {
+        let bytes = match self.read_node_bytes(node_id).await {
+            Ok(bytes) => bytes,
+            // foreign messages are reported like corrupted nodes
+            Err(serenity::Error::Other(reason)) => return Err(reason.to_string()),
+            Err(err) => panic!("Failed to get node: {err}"),
+        };
+        let mut node = node_codec::decode(bytes).map_err(|err| err.to_string())?;
+        if node.version > self.format_version {
+            return Err(format!(
+                "Node has format version v{} in a v{} filesystem",
+                node.version, self.format_version
+            ));
+        }
+        node.case_insensitive = self.case_insensitive;
+
+        Ok(node)
+    }
+
+    pub(super) async fn load_deletion_queue(&self, key: &str) -> Vec<(BlockIndex, String)> {
+        self.load_deletion_queue_of(self.root_node_id, key).await
+    }
+
+    // other filesystems in the channel keep their own queue below their root
+    async fn load_deletion_queue_of(
+        &self,
+        root_node_id: BlockIndex,
+        key: &str,
+    ) -> Vec<(BlockIndex, String)> {
+        let Some(data) = self
+            .read_metadata_file_of(root_node_id, "deletion-queue", key)
+            .await
+        else {
+            return Vec::new();
+        };
+
+        // one '<node id> <path>' line per queued entry, the path is only shown in messages
+        String::from_utf8(data)
+            .expect("Deletion queue is corrupted")
+            .lines()
+            .map(|line| {
+                let (node_id, path) = line.split_once(' ').expect("Deletion queue is corrupted");
+                (
+                    node_id.parse().expect("Deletion queue is corrupted"),
+                    path.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    async fn save_deletion_queue(&self, queue: &[(BlockIndex, String)], key: &str) {
+        let data: String = queue
+            .iter()
+            .map(|(node_id, path)| format!("{node_id} {path}\n"))
+            .collect();
+
+        self.ensure_metadata_directory().await;
+        self.write_file_bytes(
+            &NodeFS::metadata_path("deletion-queue"),
+            data.as_bytes(),
+            key,
+        )
+        .await;
+    }
+}
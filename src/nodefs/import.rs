@@ -0,0 +1,130 @@
+use indicatif::{HumanBytes, HumanCount, MultiProgress};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node, util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    pub async fn import(&self, source: String, destination: FsPath, key: String) {
+        assert!(
+            !source.starts_with("s3://"),
+            "S3 sources are not supported, import a presigned HTTPS URL of the object instead"
+        );
+        assert!(
+            source.starts_with("http://") || source.starts_with("https://"),
+            "Source must be an HTTP(S) URL"
+        );
+
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Importing {source} to {destination}"));
+
+        let mut response = reqwest::get(&source)
+            .await
+            .and_then(|response| response.error_for_status())
+            .expect("Failed to request source");
+
+        // directories take the name of the last URL segment
+        let destination = if destination.is_dir() {
+            let name = response
+                .url()
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|name| !name.is_empty())
+                .expect("Source URL has no file name, destination must include one");
+            destination.join(name)
+        } else {
+            destination
+        };
+
+        let (file_path, file_name) = NodeFS::split_path(&destination, false, false);
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&file_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(file_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
+
+        // show progress bar, servers may not report the size upfront
+        let progress_bar = progress.add(if let Some(filesize) = response.content_length() {
+            failure::ensure(
+                filesize <= node::MAX_FILE_SIZE as u64,
+                Hint::FileTooBig,
+                format!(
+                    "File exceeds maximum file size of {} ({}): {} ({})",
+                    HumanBytes(node::MAX_FILE_SIZE as u64),
+                    HumanCount(node::MAX_FILE_SIZE as u64),
+                    HumanBytes(filesize),
+                    HumanCount(filesize)
+                ),
+            );
+
+            util::progress_bar(filesize)
+        } else {
+            util::stream_progress()
+        });
+
+        // create file node
+        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        let staging_node_id = self.staging_directory().await;
+        self.stage_node(staging_node_id, file_node_id).await;
+
+        // upload the response body while it's being received
+        let (reader, mut writer) = tokio::io::duplex(node::BLOCK_SIZE);
+        tokio::join!(
+            async {
+                while let Some(chunk) = response.chunk().await.expect("Failed to read from source")
+                {
+                    writer
+                        .write_all(&chunk)
+                        .await
+                        .expect("Failed to stream source");
+                }
+
+                // closing the stream marks the end of the file
+                drop(writer);
+            },
+            self.upload_blocks(reader, &mut file_node, &key, &progress_bar),
+        );
+
+        // the size is only known for sure now
+        let size = file_node.size();
+        let quotas = self.quota_directories(dir_node_id).await;
+        NodeFS::check_quota(&quotas, size);
+
+        // update nodes
+        let blocks = file_node.blocks().len();
+        self.edit_file_node(file_node_id, file_node).await;
+        self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+        dir_node.push_directory_entry(file_name, file_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, size as i64).await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!("Finished importing {source}"));
+        self.print_summary(
+            &progress,
+            util::TransferSummary {
+                operation: "Imported",
+                bytes: size,
+                blocks,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+    }
+}
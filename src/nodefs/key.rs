@@ -0,0 +1,141 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use tokio::fs;
+
+use super::NodeFS;
+
+const ENV_FILE: &str = ".env";
+
+// entries of a recovery bundle that are restored into the .env file
+const RESTORED_VARIABLES: [&str; 2] = ["AES_KEY", "DATA_CHANNEL_ID"];
+
+impl NodeFS {
+    // the bundle has everything needed to access the filesystem except the bot token, it's
+    // encrypted with the age or gpg command line tools
+    pub async fn key_export(
+        &self,
+        age_recipient: Option<String>,
+        gpg_recipient: Option<String>,
+        output: String,
+        key: String,
+    ) {
+        assert!(
+            !key.contains('\'') && !key.contains('\n'),
+            "The AES key can't be written to a .env file"
+        );
+        let bundle = format!(
+            "# DFS recovery bundle, the superblock is the channel topic with a pinned backup message\n\
+             AES_KEY='{key}'\n\
+             DATA_CHANNEL_ID={}\n\
+             # root node {} at format v{}\n",
+            self.data_channel.get(),
+            self.root_node_id,
+            self.format_version
+        );
+
+        let (mut command, tool) = match (age_recipient, gpg_recipient) {
+            (Some(recipient), _) => {
+                let mut command = Command::new("age");
+                command.args(["--armor", "--recipient", &recipient]);
+                (command, "age")
+            }
+            (_, Some(recipient)) => {
+                let mut command = Command::new("gpg");
+                command.args(["--encrypt", "--armor", "--recipient", &recipient]);
+                (command, "gpg")
+            }
+            (None, None) => panic!("A recipient is required"),
+        };
+        let encrypted = NodeFS::run_tool(&mut command, tool, bundle.as_bytes());
+
+        fs::write(&output, encrypted)
+            .await
+            .expect("Failed to write recovery bundle");
+        println!("  Exported the recovery bundle to {output}, the bot token isn't part of it");
+    }
+
+    // runs without a filesystem, the restored .env file is what's missing to set one up
+    pub async fn key_import(bundle: String, identity: Option<String>) {
+        let encrypted = fs::read(&bundle)
+            .await
+            .expect("Failed to read recovery bundle");
+
+        let decrypted = if encrypted.starts_with(b"-----BEGIN PGP MESSAGE-----") {
+            NodeFS::run_tool(
+                Command::new("gpg").args(["--decrypt", "--quiet"]),
+                "gpg",
+                &encrypted,
+            )
+        } else {
+            let identity = identity.expect("An age identity file is required (--identity)");
+            NodeFS::run_tool(
+                Command::new("age").args(["--decrypt", "--identity", &identity]),
+                "age",
+                &encrypted,
+            )
+        };
+        let decrypted = String::from_utf8(decrypted).expect("Recovery bundle is corrupted");
+
+        // existing settings are never overwritten
+        let mut env = fs::read_to_string(ENV_FILE).await.unwrap_or_default();
+        let mut restored = Vec::new();
+        for line in decrypted.lines() {
+            let Some((name, _)) = line.split_once('=') else {
+                continue;
+            };
+            if !RESTORED_VARIABLES.contains(&name) {
+                continue;
+            }
+            assert!(
+                !env.lines()
+                    .any(|line| line.starts_with(&format!("{name}="))),
+                "{ENV_FILE} already sets {name}, remove it to import the bundle"
+            );
+
+            if !env.is_empty() && !env.ends_with('\n') {
+                env.push('\n');
+            }
+            env.push_str(line);
+            env.push('\n');
+            restored.push(name);
+        }
+        assert!(
+            restored.len() == RESTORED_VARIABLES.len(),
+            "Recovery bundle is incomplete"
+        );
+
+        fs::write(ENV_FILE, env)
+            .await
+            .expect("Failed to write .env file");
+        println!(
+            "  Restored {} into {ENV_FILE}, add BOT_TOKEN to access the filesystem",
+            restored.join(", ")
+        );
+    }
+
+    fn run_tool(command: &mut Command, tool: &str, input: &[u8]) -> Vec<u8> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|err| panic!("Failed to run {tool}, is it installed? {err}"));
+
+        // the bundle is tiny, it fits into the pipe before the output is read
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input)
+            .unwrap_or_else(|err| panic!("Failed to pass data to {tool}: {err}"));
+
+        let output = child
+            .wait_with_output()
+            .unwrap_or_else(|err| panic!("Failed to run {tool}: {err}"));
+        assert!(output.status.success(), "{tool} failed: {}", output.status);
+
+        output.stdout
+    }
+}
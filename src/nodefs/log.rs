@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use indicatif::{HumanBytes, ProgressBar};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node::{self, Node, Size},
+    node_kind::NodeKind::File,
+};
+
+use super::{BlockFetch, NodeFS};
+
+impl NodeFS {
+    pub async fn log_append(&self, path: FsPath, key: String) {
+        let appended = self.append_to_file(&path, tokio::io::stdin(), &key).await;
+
+        println!("  Appended {} to {path}", HumanBytes(appended));
+    }
+
+    pub async fn log_tail(
+        &self,
+        path: FsPath,
+        lines: usize,
+        follow: bool,
+        interval: Duration,
+        key: String,
+    ) {
+        let (file_node, file_node_id) = self.resolve_path(&path).await;
+        assert!(file_node.kind == File, "{path} is not a file");
+
+        // only the blocks holding the last lines are downloaded
+        let mut stdout = tokio::io::stdout();
+        let mut first_block = file_node.blocks().len().saturating_sub(1);
+        let mut data = self.read_blocks_from(&file_node, first_block, &key).await;
+        while first_block > 0 && data.iter().filter(|byte| **byte == b'\n').count() <= lines {
+            first_block -= 1;
+            data = self.read_blocks_from(&file_node, first_block, &key).await;
+        }
+
+        // a trailing newline ends the last line, it doesn't start a new one
+        let end = data.len() - usize::from(data.last() == Some(&b'\n'));
+        let start = data[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, byte)| **byte == b'\n')
+            .nth(lines.saturating_sub(1))
+            .map_or(0, |(pos, _)| pos + 1);
+        if lines > 0 {
+            NodeFS::write_stdout(&mut stdout, &data[start..]).await;
+        }
+
+        if !follow {
+            return;
+        }
+
+        // poll for appended data, the node is fetched again every interval
+        let mut offset = file_node.size();
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let file_node = self.get_file_node(file_node_id).await;
+            if file_node.size() < offset {
+                eprintln!("  {path} was truncated, following from the start");
+                offset = 0;
+            }
+            if file_node.size() == offset {
+                continue;
+            }
+
+            let first_block = (offset / file_node.chunk_size) as usize;
+            let data = self.read_blocks_from(&file_node, first_block, &key).await;
+            let skip = (offset - first_block as Size * file_node.chunk_size) as usize;
+            NodeFS::write_stdout(&mut stdout, &data[skip..]).await;
+            offset = file_node.size();
+        }
+    }
+
+    // appends to a file or creates it, only a partial last block is uploaded again
+    pub(super) async fn append_to_file<R: AsyncRead + Unpin>(
+        &self,
+        path: &FsPath,
+        reader: R,
+        key: &str,
+    ) -> Size {
+        let (dir_path, file_name) = NodeFS::split_path(path, false, false);
+        let (mut dir_node, dir_node_id) = self.traverse_path(&dir_path).await;
+        let quotas = self.quota_directories(dir_node_id).await;
+
+        let Some(file_node_id) = dir_node
+            .find_directory_entry(file_name)
+            .map(|entry| entry.block_id())
+        else {
+            failure::ensure(
+                !dir_node.is_full(),
+                Hint::DirectoryFull,
+                "The directory is full",
+            );
+
+            let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+            self.upload_blocks(reader, &mut file_node, key, &ProgressBar::hidden())
+                .await;
+            let size = file_node.size();
+            NodeFS::check_quota(&quotas, size);
+
+            self.edit_file_node(file_node_id, file_node).await;
+            dir_node.push_directory_entry(file_name, file_node_id);
+            self.edit_directory_node(dir_node_id, dir_node).await;
+            self.charge_quota(&quotas, size as i64).await;
+
+            return size;
+        };
+
+        let mut file_node = self
+            .try_get_node(file_node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Node {file_node_id} is corrupted: {err}"));
+        assert!(file_node.kind == File, "{path} is not a file");
+
+        // the partial last block is merged with the new data
+        let old_size = file_node.size();
+        let kept = (old_size / file_node.chunk_size) as usize;
+        let partial = self.read_blocks_from(&file_node, kept, key).await;
+        let dropped = file_node.truncate_blocks(kept);
+        self.upload_blocks(
+            partial.as_slice().chain(reader),
+            &mut file_node,
+            key,
+            &ProgressBar::hidden(),
+        )
+        .await;
+        let size = file_node.size();
+        NodeFS::check_quota(&quotas, size - old_size);
+
+        // the dropped block is still referenced until the node is saved
+        self.edit_file_node(file_node_id, file_node).await;
+        for block_id in dropped
+            .into_iter()
+            .filter(|block_id| *block_id != node::HOLE)
+        {
+            self.delete_data_block(block_id).await;
+        }
+        self.charge_quota(&quotas, (size - old_size) as i64).await;
+
+        size - old_size
+    }
+
+    // everything from the given block to the end of the file
+    async fn read_blocks_from(&self, file_node: &Node, first_block: usize, key: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        if first_block < file_node.blocks().len() {
+            self.download_blocks_from(
+                file_node,
+                first_block,
+                BlockFetch::default(),
+                &mut data,
+                key,
+                &ProgressBar::hidden(),
+            )
+            .await;
+        }
+
+        data
+    }
+
+    async fn write_stdout(stdout: &mut tokio::io::Stdout, data: &[u8]) {
+        stdout
+            .write_all(data)
+            .await
+            .expect("Failed to write to stdout");
+        stdout.flush().await.expect("Failed to write to stdout");
+    }
+}
@@ -0,0 +1,116 @@
+use serenity::all::MessageId;
+
+use crate::{
+    node::{self, Timestamp, Version},
+    node_codec,
+    node_kind::NodeKind::Directory,
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    pub async fn migrate(&mut self, to: String, key: String) {
+        let target = to
+            .strip_prefix('v')
+            .unwrap_or(&to)
+            .parse::<Version>()
+            .expect("Target version must be a valid version like 'v1'");
+        assert!(
+            target <= node::FORMAT_VERSION,
+            "Target version v{target} is not supported, the newest supported is v{}",
+            node::FORMAT_VERSION
+        );
+        assert!(
+            target >= self.format_version,
+            "Cannot downgrade filesystem from v{} to v{target}",
+            self.format_version
+        );
+
+        if target == self.format_version {
+            println!("  Filesystem is already at v{target}");
+            if self.repair_superblock().await {
+                println!("  Saved the superblock again with a CRC and a backup");
+            }
+            return;
+        }
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!(
+            "Migrating from v{} to v{target}",
+            self.format_version
+        ));
+
+        // every node records its own version, an interrupted migration resumes by
+        // skipping the nodes that were already rewritten
+        //
+        // nodes queued by 'rm --defer' are unlinked but still read by 'gc run-queue', so they
+        // are migrated as well
+        let mut visited = 0;
+        let mut migrated = 0;
+        let mut stack = vec![(self.root_node_id, 0)];
+        stack.extend(
+            self.load_deletion_queue(&key)
+                .await
+                .into_iter()
+                .map(|(node_id, _)| (node_id, 0)),
+        );
+        let mut visited_dirs = Default::default();
+        while let Some((node_id, depth)) = stack.pop() {
+            let mut node = node_codec::decode(
+                self.read_node_bytes(node_id)
+                    .await
+                    .expect("Failed to get node"),
+            )
+            .unwrap_or_else(|err| panic!("Node {node_id} is corrupted: {err}"));
+            assert!(
+                node.version == self.format_version || node.version == target,
+                "Node {node_id} has format version v{}, expected v{} or v{target}",
+                node.version,
+                self.format_version
+            );
+
+            if node.kind == Directory {
+                if let Err(err) = self.check_traversal(&mut visited_dirs, node_id, depth) {
+                    panic!("Stopped at node {node_id}, it's {err}");
+                }
+                stack.extend(
+                    node.entries()
+                        .iter()
+                        .map(|entry| (entry.block_id(), depth + 1)),
+                );
+            }
+
+            if node.version < target {
+                if node.version == 0 {
+                    // v0 has no timestamps, the message creation time is the best guess
+                    let created = MessageId::new(node_id).created_at().unix_timestamp();
+                    node.created = created as Timestamp;
+                    node.modified = created as Timestamp;
+                }
+                node.version = target;
+
+                // nodes from before v2 are parsed with a single link
+                self.edit_node(node_id, node).await;
+                migrated += 1;
+            }
+
+            visited += 1;
+            spinner.set_message(format!(
+                "Migrating from v{} to v{target} ({migrated} of {visited} nodes rewritten)",
+                self.format_version
+            ));
+        }
+
+        // only mark the filesystem as migrated once every node was rewritten
+        let from = self.format_version;
+        self.format_version = target;
+        self.save_superblock().await;
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Migrated from v{from} to v{target} ({migrated} of {visited} nodes rewritten)"
+        ));
+    }
+}
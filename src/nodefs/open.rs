@@ -0,0 +1,100 @@
+use std::{path::Path, process::Command, time::Duration};
+
+use indicatif::MultiProgress;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, BufReader},
+};
+
+use crate::{command::DownloadOptions, fs_path::FsPath, on_conflict::OnConflict};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // the copy is kept under the system's temp directory, a later open of the same file replaces it
+    pub async fn open(&self, path: FsPath, edit: bool, key: String) {
+        let progress = MultiProgress::new();
+
+        let (_, name) = NodeFS::split_path(&path, false, false);
+        let (_, node_id) = self.resolve_path(&path).await;
+        let dir = std::env::temp_dir()
+            .join("dfs")
+            .join(self.data_channel.to_string())
+            .join(node_id.to_string());
+        fs::create_dir_all(&dir)
+            .await
+            .expect("Failed to create temporary directory");
+        let local = dir.join(name).to_string_lossy().to_string();
+
+        let options = DownloadOptions {
+            follow_symlinks: true,
+            on_conflict: OnConflict::Overwrite,
+            readahead: 2,
+            allow_holes: false,
+        };
+        self.__download(path.clone(), local.clone(), options, key.clone(), &progress)
+            .await;
+
+        NodeFS::open_with_default_app(Path::new(&local));
+        if !edit {
+            return;
+        }
+
+        // changes are uploaded once the file was saved, polling works the same on every OS
+        println!("  Watching {local} for changes, press Enter to stop");
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut uploaded = NodeFS::modified_stamp(&local).await;
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                _ = lines.next_line() => break,
+                _ = interval.tick() => {}
+            }
+
+            let stamp = NodeFS::modified_stamp(&local).await;
+            if stamp == uploaded {
+                continue;
+            }
+            self.__upload(
+                vec![local.clone()],
+                path.clone(),
+                OnConflict::Overwrite,
+                self.check_chunk_size(None),
+                key.clone(),
+                &progress,
+            )
+            .await;
+            uploaded = stamp;
+        }
+
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+
+    fn open_with_default_app(path: &Path) {
+        let mut command = if cfg!(target_os = "windows") {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        } else if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else {
+            Command::new("xdg-open")
+        };
+
+        let status = command
+            .arg(path)
+            .status()
+            .unwrap_or_else(|err| panic!("Failed to open {}: {err}", path.display()));
+        assert!(
+            status.success(),
+            "Failed to open {}, no default application?",
+            path.display()
+        );
+    }
+
+    // editors often replace the file instead of writing to it, so the size is compared too
+    async fn modified_stamp(path: &str) -> Option<(std::time::SystemTime, u64)> {
+        let metadata = fs::metadata(path).await.ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
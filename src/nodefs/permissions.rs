@@ -0,0 +1,106 @@
+use serenity::all::Permissions;
+
+use crate::util;
+
+use super::NodeFS;
+
+// operations that never write to the channel once the filesystem exists
+const READ_ONLY_OPERATIONS: [&str; 9] = [
+    "ls",
+    "download",
+    "check",
+    "stat",
+    "info",
+    "activity",
+    "estimate",
+    "channel-stats",
+    "salvage",
+];
+
+// (permission, what it's needed for, whether the operation can do without it)
+pub(super) const CHECKLIST: [(Permissions, &str, &str, bool); 6] = [
+    (
+        Permissions::VIEW_CHANNEL,
+        "View Channel",
+        "see the data channel",
+        false,
+    ),
+    (
+        Permissions::READ_MESSAGE_HISTORY,
+        "Read Message History",
+        "read nodes and blocks",
+        false,
+    ),
+    (
+        Permissions::SEND_MESSAGES,
+        "Send Messages",
+        "upload, edit and delete its own nodes and blocks",
+        false,
+    ),
+    (
+        Permissions::ATTACH_FILES,
+        "Attach Files",
+        "store nodes and blocks as attachments",
+        false,
+    ),
+    (
+        Permissions::MANAGE_CHANNELS,
+        "Manage Channels",
+        "keep the superblock in the channel topic",
+        false,
+    ),
+    (
+        Permissions::MANAGE_MESSAGES,
+        "Manage Messages",
+        "pin the superblock backup",
+        true,
+    ),
+];
+
+impl NodeFS {
+    // checked before anything is written, so a long upload doesn't stop halfway with a serenity
+    // error, permissions that can't be determined are left to the operation itself
+    pub async fn preflight(&self, operation: &str) {
+        let Some(granted) = self.channel_permissions().await else {
+            return;
+        };
+
+        let read_only = READ_ONLY_OPERATIONS.contains(&operation);
+        let required = |permission: Permissions| {
+            !read_only
+                || permission
+                    .intersects(Permissions::VIEW_CHANNEL | Permissions::READ_MESSAGE_HISTORY)
+        };
+        let missing = CHECKLIST.iter().any(|(permission, _, _, optional)| {
+            !optional && required(*permission) && !granted.contains(*permission)
+        });
+        if !missing {
+            return;
+        }
+
+        println!("  Permissions of the bot in channel {}:", self.data_channel);
+        for (permission, name, reason, optional) in CHECKLIST {
+            let mark = if granted.contains(permission) {
+                "[x]"
+            } else if optional || !required(permission) {
+                "[-]"
+            } else {
+                "[ ]"
+            };
+            println!("    {mark} {name:<22}{reason}");
+        }
+        panic!("The bot is missing permissions that '{operation}' needs, grant the unchecked ones");
+    }
+
+    pub(super) async fn channel_permissions(&self) -> Option<Permissions> {
+        let channel = util::get_guild_channel(&self.client, self.data_channel)
+            .await
+            .ok()?;
+        let guild = util::get_guild(&self.client, channel.guild_id).await.ok()?;
+        let user_id = util::get_current_user(&self.client).await.ok()?;
+        let member = util::get_member(&self.client, channel.guild_id, user_id)
+            .await
+            .ok()?;
+        Some(guild.user_permissions_in(&channel, &member))
+    }
+}
@@ -0,0 +1,247 @@
+use indicatif::{HumanCount, MultiProgress};
+use serenity::{
+    all::{ChannelId, MessageId},
+    json::{self, Value, json},
+};
+
+use crate::{directory_entry::BlockIndex, node::Node, util};
+
+use super::NodeFS;
+
+// 'gc orphans' and 'fsck' write what they would change with --report-only, one JSON object per
+// line: a header naming the report and channel, then actions and findings
+//
+// 'apply --from-report' runs exactly the actions of a reviewed report, findings are only for the
+// reader, renames are skipped if their directory changed since
+pub(super) enum Action {
+    DeleteMessage {
+        message_id: BlockIndex,
+    },
+    // shards live outside the data channel
+    DeleteShard {
+        channel_id: u64,
+        message_id: u64,
+    },
+    // entries are addressed by position, duplicates share their name
+    RenameEntry {
+        dir_node_id: BlockIndex,
+        position: usize,
+        name: String,
+        new_name: String,
+    },
+}
+
+impl Action {
+    fn to_json(&self) -> String {
+        match self {
+            Action::DeleteMessage { message_id } => {
+                json!({"action": "delete-message", "message_id": message_id})
+            }
+            Action::DeleteShard {
+                channel_id,
+                message_id,
+            } => json!({
+                "action": "delete-shard",
+                "channel_id": channel_id,
+                "message_id": message_id,
+            }),
+            Action::RenameEntry {
+                dir_node_id,
+                position,
+                name,
+                new_name,
+            } => json!({
+                "action": "rename-entry",
+                "dir_node_id": dir_node_id,
+                "position": position,
+                "name": name,
+                "new_name": new_name,
+            }),
+        }
+        .to_string()
+    }
+
+    fn from_json(object: &Value) -> Result<Self, String> {
+        let field = |name: &str| {
+            object
+                .get(name)
+                .ok_or_else(|| format!("Action is missing \"{name}\""))
+        };
+        let number = |name: &str| {
+            field(name)?
+                .as_u64()
+                .ok_or_else(|| format!("\"{name}\" must be a number"))
+        };
+        let string = |name: &str| {
+            field(name)?
+                .as_str()
+                .ok_or_else(|| format!("\"{name}\" must be a string"))
+        };
+
+        match string("action")? {
+            "delete-message" => Ok(Action::DeleteMessage {
+                message_id: number("message_id")?,
+            }),
+            "delete-shard" => Ok(Action::DeleteShard {
+                channel_id: number("channel_id")?,
+                message_id: number("message_id")?,
+            }),
+            "rename-entry" => Ok(Action::RenameEntry {
+                dir_node_id: number("dir_node_id")?,
+                position: number("position")? as usize,
+                name: string("name")?.to_string(),
+                new_name: string("new_name")?.to_string(),
+            }),
+            action => Err(format!("Unknown action '{action}'")),
+        }
+    }
+}
+
+impl NodeFS {
+    // the header records facts 'apply' checks before it changes anything
+    pub(super) fn write_report(
+        &self,
+        path: &str,
+        report: &str,
+        header: &[(&str, u64)],
+        actions: &[Action],
+        findings: &[(String, String)],
+    ) {
+        let mut header_object = json!({
+            "report": report,
+            "channel": self.data_channel.get(),
+            "created": Node::now(),
+        });
+        for (name, value) in header {
+            header_object[*name] = json!(value);
+        }
+        let mut lines = vec![header_object.to_string()];
+        lines.extend(actions.iter().map(Action::to_json));
+        lines.extend(
+            findings
+                .iter()
+                .map(|(finding, detail)| json!({"finding": finding, "detail": detail}).to_string()),
+        );
+        lines.push(String::new());
+
+        std::fs::write(path, lines.join("\n"))
+            .unwrap_or_else(|err| panic!("Failed to write report {path}: {err}"));
+        println!(
+            "  Wrote {} actions and {} findings to {path}, 'apply --from-report {path}' runs the actions",
+            HumanCount(actions.len() as u64),
+            HumanCount(findings.len() as u64)
+        );
+    }
+
+    pub async fn apply_report(&self, from_report: String) {
+        let progress = MultiProgress::new();
+        let data = std::fs::read_to_string(&from_report)
+            .unwrap_or_else(|err| panic!("Failed to read report {from_report}: {err}"));
+
+        // the whole report is read before the first change
+        let mut lines = data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match json::from_str::<Value>(line) {
+                Ok(object) if object.is_object() => object,
+                Ok(_) => panic!("{from_report} is not a valid report: {line} is not an object"),
+                Err(err) => panic!("{from_report} is not a valid report: {err}"),
+            });
+        let header = lines
+            .next()
+            .filter(|header| header.get("report").is_some())
+            .unwrap_or_else(|| panic!("{from_report} doesn't start with a report header"));
+        assert!(
+            header["channel"].as_u64() == Some(self.data_channel.get()),
+            "The report was made for channel {}, not this data channel",
+            header["channel"]
+        );
+        let actions: Vec<Action> = lines
+            .filter(|object| object.get("action").is_some())
+            .map(|object| {
+                Action::from_json(&object)
+                    .unwrap_or_else(|err| panic!("{from_report} is not a valid report: {err}"))
+            })
+            .collect();
+
+        // the same refusal as 'gc orphans --delete', unreadable nodes make their data look orphaned
+        if header["report"] == "gc-orphans" {
+            assert!(
+                header
+                    .get("corrupted")
+                    .is_none_or(|corrupted| corrupted.as_u64() == Some(0)),
+                "Not applying, nodes couldn't be read when the report was made and their data may look orphaned"
+            );
+        }
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        let mut applied = 0;
+        for (idx, action) in actions.iter().enumerate() {
+            spinner.set_message(format!(
+                "Applying {from_report} ({} of {})",
+                idx + 1,
+                actions.len()
+            ));
+            match self.apply_action(action).await {
+                Ok(()) => applied += 1,
+                Err(err) => self.record_failure(&progress, action.to_json(), err),
+            }
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Applied {} of {} actions from {from_report}",
+            HumanCount(applied),
+            HumanCount(actions.len() as u64)
+        ));
+    }
+
+    async fn apply_action(&self, action: &Action) -> Result<(), String> {
+        match action {
+            // stripes take their shards along
+            Action::DeleteMessage { message_id } => self
+                .try_delete_data_block(*message_id)
+                .await
+                .map_err(|err| util::explain_error(&err)),
+            Action::DeleteShard {
+                channel_id,
+                message_id,
+            } => util::delete_message(
+                &self.client,
+                ChannelId::new(*channel_id),
+                MessageId::new(*message_id),
+            )
+            .await
+            .map_err(|err| util::explain_error(&err)),
+            Action::RenameEntry {
+                dir_node_id,
+                position,
+                name,
+                new_name,
+            } => {
+                let mut dir_node = self
+                    .try_get_node(*dir_node_id)
+                    .await
+                    .map_err(|err| format!("Directory node is unreadable: {err}"))?;
+                if dir_node
+                    .entries()
+                    .get(*position)
+                    .map(|entry| entry.get_name())
+                    != Some(name)
+                {
+                    return Err(format!(
+                        "The directory changed, entry {position} isn't named {name} anymore"
+                    ));
+                }
+                if dir_node.contains_entry(new_name) {
+                    return Err(format!("The directory already contains {new_name}"));
+                }
+
+                dir_node.rename_directory_entry_at(*position, new_name);
+                self.edit_directory_node(*dir_node_id, dir_node).await;
+                Ok(())
+            }
+        }
+    }
+}
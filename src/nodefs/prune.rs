@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use indicatif::{HumanCount, MultiProgress};
+
+use crate::{fs_path::FsPath, node_kind::NodeKind::Directory, util};
+
+use super::{METADATA_DIR_NAME, NodeFS};
+
+impl NodeFS {
+    // the given directory itself is kept, with 'repeat' directories that only contain empty
+    // directories are removed too, which is what running it until nothing changes would do
+    pub async fn prune_empty_dirs(&self, path: FsPath, repeat: bool, dry_run: bool) {
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Finding empty directories in {path}"));
+
+        let (node, node_id) = self.traverse_path(&path).await;
+        assert!(node.kind == Directory, "Can only prune directories");
+
+        // parents come before their subdirectories, each remembers its parent's position
+        let mut dirs: Vec<(FsPath, usize, Option<usize>)> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(path.clone(), node, node_id, None, 0)];
+        while let Some((dir_path, dir_node, dir_node_id, parent, depth)) = stack.pop() {
+            if let Err(err) = self.check_traversal(&mut visited, dir_node_id, depth) {
+                panic!("Stopped at {dir_path}, it's {err}");
+            }
+            let pos = dirs.len();
+            dirs.push((dir_path.clone(), dir_node.entries().len(), parent));
+
+            // the metadata directory is managed by dfs itself
+            for entry in dir_node.entries().iter().filter(|entry| {
+                entry.get_name().ends_with('/')
+                    && !(dir_path.is_root() && entry.get_name() == METADATA_DIR_NAME)
+            }) {
+                let entry_path = dir_path.join(entry.get_name());
+                match self.try_get_node(entry.block_id()).await {
+                    Ok(entry_node) => stack.push((
+                        entry_path,
+                        entry_node,
+                        entry.block_id(),
+                        Some(pos),
+                        depth + 1,
+                    )),
+                    Err(err) => self.record_failure(
+                        &progress,
+                        entry_path.to_string(),
+                        format!("Corrupted node {}: {err}", entry.block_id()),
+                    ),
+                }
+            }
+        }
+
+        // subdirectories are decided first, so emptiness can propagate up to their parents
+        let mut remaining: Vec<usize> = dirs.iter().map(|(_, entries, _)| *entries).collect();
+        let mut pruned = vec![false; dirs.len()];
+        for pos in (1..dirs.len()).rev() {
+            let empty = if repeat {
+                remaining[pos] == 0
+            } else {
+                dirs[pos].1 == 0
+            };
+            if empty {
+                pruned[pos] = true;
+                remaining[dirs[pos].2.unwrap()] -= 1;
+            }
+        }
+
+        // cleanup
+        progress.remove(&spinner);
+
+        // only the topmost pruned directories are deleted, their empty subdirectories go with them
+        let mut count = 0;
+        for (pos, (dir_path, _, parent)) in dirs.iter().enumerate() {
+            if !pruned[pos] {
+                continue;
+            }
+            count += 1;
+            if dry_run {
+                println!("  Would delete {dir_path}");
+            } else if !parent.is_some_and(|parent| pruned[parent]) {
+                self.__rm(dir_path.clone(), false, true, 1, &progress).await;
+            }
+        }
+
+        if dry_run {
+            println!("  {} empty directories would be deleted", HumanCount(count));
+        } else {
+            println!("  Deleted {} empty directories", HumanCount(count));
+        }
+    }
+}
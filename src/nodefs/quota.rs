@@ -0,0 +1,169 @@
+use indicatif::{HumanBytes, HumanCount};
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{Node, Size},
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    pub async fn quota_set(&self, path: FsPath, quota: Size) {
+        assert!(path.is_dir(), "Directories are required");
+        assert!(
+            self.format_version >= 3,
+            "Quotas require format v3, run 'migrate --to v3' first"
+        );
+        assert!(
+            quota > 0,
+            "Quota must be larger than 0, use 'quota rm' to remove it"
+        );
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Measuring {path}"));
+
+        // usage is only maintained while a quota is set
+        let (mut dir_node, dir_node_id) = self.traverse_path(&path).await;
+        let used = if dir_node.quota > 0 {
+            dir_node.used
+        } else {
+            self.disk_usage(self.get_directory_node(dir_node_id).await)
+                .await
+        };
+
+        dir_node.quota = quota;
+        dir_node.used = used;
+        self.edit_directory_node(dir_node_id, dir_node).await;
+
+        // cleanup
+        spinner.finish_with_message(format!(
+            "Set quota of {path} to {} ({})",
+            HumanBytes(quota),
+            HumanCount(quota)
+        ));
+        if used > quota {
+            println!(
+                "  {path} already uses {} ({}), uploads will fail until it shrinks",
+                HumanBytes(used),
+                HumanCount(used)
+            );
+        }
+    }
+
+    pub async fn quota_rm(&self, path: FsPath) {
+        assert!(path.is_dir(), "Directories are required");
+
+        let (mut dir_node, dir_node_id) = self.traverse_path(&path).await;
+        assert!(dir_node.quota > 0, "{path} has no quota");
+
+        dir_node.quota = 0;
+        dir_node.used = 0;
+        self.edit_directory_node(dir_node_id, dir_node).await;
+
+        println!("  Removed quota of {path}");
+    }
+
+    pub async fn quota_show(&self, path: FsPath) {
+        assert!(path.is_dir(), "Directories are required");
+
+        let (dir_node, dir_node_id) = self.traverse_path(&path).await;
+        let quotas = self.quota_directories(dir_node_id).await;
+        if quotas.is_empty() {
+            println!("  No quota applies to {path}");
+            return;
+        }
+
+        // the closest quota is listed first
+        for (quota_path, quota_node, _) in quotas {
+            println!(
+                "  {quota_path}  {} of {} used ({:.1}%)",
+                HumanBytes(quota_node.used),
+                HumanBytes(quota_node.quota),
+                quota_node.used as f64 / quota_node.quota as f64 * 100.0
+            );
+        }
+
+        if dir_node.quota == 0 {
+            println!("  ({path} has no quota of its own)");
+        }
+    }
+}
+
+impl NodeFS {
+    // directories with a quota on the way from `dir_node_id` up to the root, including itself
+    pub(super) async fn quota_directories(
+        &self,
+        dir_node_id: BlockIndex,
+    ) -> Vec<(String, Node, BlockIndex)> {
+        if self.format_version < 3 {
+            return Vec::new();
+        }
+
+        let mut chain = Vec::new();
+        let mut node_id = dir_node_id;
+        let mut node = self.get_directory_node(node_id).await;
+        let mut names = Vec::new();
+        while node_id != self.root_node_id {
+            let parent_node_id = node.parent_block_id;
+            let parent_node = self.get_directory_node(parent_node_id).await;
+            names.push(
+                parent_node
+                    .entries()
+                    .iter()
+                    .find(|entry| entry.block_id() == node_id)
+                    .map_or_else(|| format!("<{node_id}>/"), |entry| entry.get_name().clone()),
+            );
+            chain.push((names.len() - 1, node, node_id));
+
+            node_id = parent_node_id;
+            node = parent_node;
+        }
+        chain.push((names.len(), node, node_id));
+
+        // names were collected bottom up, a directory's path is its name and the ones after it
+        chain
+            .into_iter()
+            .filter(|(_, node, _)| node.quota > 0)
+            .map(|(name_pos, node, node_id)| {
+                let path: String = names[name_pos..].iter().rev().map(String::as_str).collect();
+                (format!("/{path}"), node, node_id)
+            })
+            .collect()
+    }
+
+    pub(super) fn check_quota(quotas: &[(String, Node, BlockIndex)], size: Size) {
+        if let Some(err) = NodeFS::quota_exceeded(quotas, size) {
+            panic!("{err}");
+        }
+    }
+
+    // for callers that clean up before they fail
+    pub(super) fn quota_exceeded(
+        quotas: &[(String, Node, BlockIndex)],
+        size: Size,
+    ) -> Option<String> {
+        quotas
+            .iter()
+            .find(|(_, node, _)| node.used.saturating_add(size) > node.quota)
+            .map(|(path, node, _)| {
+                format!(
+                    "Quota of {path} exceeded: {} of {} used, {} more requested",
+                    HumanBytes(node.used),
+                    HumanBytes(node.quota),
+                    HumanBytes(size)
+                )
+            })
+    }
+
+    // nodes are fetched again as the caller may have edited them in the meantime
+    pub(super) async fn charge_quota(&self, quotas: &[(String, Node, BlockIndex)], delta: i64) {
+        for (_, _, node_id) in quotas {
+            let mut node = self.get_directory_node(*node_id).await;
+            node.used = node.used.saturating_add_signed(delta);
+            self.edit_directory_node(*node_id, node).await;
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use serenity::all::{CreateMessage, EditMessage, MessageId};
+
+use crate::{node, util};
+
+use super::NodeFS;
+
+// the note is a pinned message, so it can be found without knowing its ID
+const RECOVERY_PREFIX: &str = "dfs recovery ";
+
+impl NodeFS {
+    // writes the note again, e.g. after upgrading dfs or moving the root node
+    pub async fn recovery_note(&self) {
+        if self.save_recovery_note().await {
+            println!(
+                "  Pinned the recovery note, it describes format v{}",
+                node::FORMAT_VERSION
+            );
+        }
+    }
+
+    // a plaintext description of the format pinned in the channel, so the data can still be read
+    // once this version of dfs is gone, it's written when a filesystem is created
+    //
+    // returns false when it couldn't be pinned, the filesystem works without it
+    pub(super) async fn save_recovery_note(&self) -> bool {
+        let filesystem = match &self.namespace {
+            Some(name) => format!("filesystem '{name}'"),
+            None => String::from("the default filesystem"),
+        };
+        let note = NodeFS::format_recovery_note(&filesystem, self.root_node_id);
+        let existing = match self.try_find_recovery_note().await {
+            Ok(existing) => existing,
+            Err(err) if util::is_missing_permission(&err) => {
+                println!(
+                    "  Not saving the recovery note, pinned messages can't be read: {}",
+                    util::explain_error(&err)
+                );
+                return false;
+            }
+            Err(err) => panic!("Failed to get pinned messages: {err}"),
+        };
+        match existing {
+            Some(message_id) => util::edit_message(
+                &self.client,
+                self.data_channel,
+                message_id,
+                EditMessage::new().content(note),
+            )
+            .await
+            .expect("Failed to save the recovery note"),
+            None => {
+                let message_id = util::send_message(
+                    &self.client,
+                    self.data_channel,
+                    CreateMessage::new().content(note),
+                )
+                .await
+                .expect("Failed to save the recovery note");
+                match util::pin_message(&self.client, self.data_channel, message_id).await {
+                    Ok(_) => {}
+                    Err(err) if util::is_missing_permission(&err) => {
+                        println!(
+                            "  The recovery note couldn't be pinned, it needs the Pin Messages permission: {}",
+                            util::explain_error(&err)
+                        );
+                        return false;
+                    }
+                    Err(err) => panic!("Failed to pin the recovery note: {err}"),
+                }
+            }
+        }
+
+        true
+    }
+
+    pub(super) async fn try_find_recovery_note(&self) -> serenity::Result<Option<MessageId>> {
+        Ok(util::get_pins(&self.client, self.data_channel)
+            .await?
+            .into_iter()
+            .find(|message| message.content.starts_with(RECOVERY_PREFIX))
+            .map(|message| message.id))
+    }
+
+    // everything needed to read the channel by hand, it has to fit in a single message
+    fn format_recovery_note(filesystem: &str, root_node_id: u64) -> String {
+        format!(
+            "{RECOVERY_PREFIX}(written by dfs {}, format v{})
+This channel holds a DiscordFS filesystem, this is how to read it without dfs. Integers are little endian u64.
+
+**Superblock** The channel topic, or the pinned 'dfs superblock' message if the topic is lost, filesystems named with --fs have a pinned 'dfs filesystem <name>' message instead. It's '<root node message ID> v<format> [flags] crc:<crc32 of the text before>', 'codec:zstd' compresses nodes with zstd and 'ec:K+M:<channels>' stripes blocks. The root node of {filesystem} was {root_node_id} when this was written.
+
+**Nodes** A message with a 'node' attachment, or 'node.<n>.<hash>' attachments joined in order of n. The header is kind (0 directory, 1 file, 2 symlink, the format version in the upper 32 bits), size, parent node, created, modified, links, quota (chunk size for v6 files) and used, v0 stops after the parent, v1 after modified and v2 after links. Directories continue with entries of name length, UTF-8 name (directories end in '/') and node ID, files with one message ID per block (0 is a block of zeros), symlinks with the target.
+
+**Blocks** The 'data' attachment of a message, the file is split into chunks (8 MiB unless the node has a chunk size) encrypted with AES-256-GCM-SIV. The key is the first 32 bytes of AES_KEY, the nonce of the nth block of a file is 4 zero bytes and n.
+
+**Stripes** A 'stripe' attachment '<size> K+M <channel>:<message>:<crc32> ...'. The first K shards are the encrypted block split evenly and padded with zeros, shard i >= K is Reed-Solomon parity over GF(2^8) (polynomial 0x11d) with the coefficient 1/(i xor j) for data shard j.
+
+Files in /.dfs/ are metadata of dfs itself.",
+            env!("CARGO_PKG_VERSION"),
+            node::FORMAT_VERSION
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // discord rejects longer message contents
+    const MAX_NOTE_LEN: usize = 2000;
+
+    #[test]
+    fn recovery_note_fits_in_a_message() {
+        let note =
+            NodeFS::format_recovery_note(&format!("filesystem '{}'", "a".repeat(32)), u64::MAX);
+        assert!(note.len() <= MAX_NOTE_LEN, "{} characters", note.len());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+
+use crate::{
+    directory_entry::{BlockIndex, DirectoryEntry},
+    fs_path::FsPath,
+    node::Node,
+    util,
+};
+
+use super::NodeFS;
+
+// directory node with the entry positions to rename and their new names
+type PlannedDirectory = (Node, BlockIndex, Vec<(usize, String)>);
+
+impl NodeFS {
+    pub async fn rename_pattern(
+        &self,
+        path: FsPath,
+        substitution: util::Substitution,
+        recursive: bool,
+        dry_run: bool,
+    ) {
+        assert!(path.is_dir(), "Directories are required");
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Planning renames in {path}"));
+
+        // plan every rename first, so nothing changes when a name is invalid or conflicts
+        let (root_node, root_node_id) = self.resolve_path(&path).await;
+        let mut planned: Vec<PlannedDirectory> = Vec::new();
+        let mut stack = vec![(path.clone(), root_node, root_node_id, 0)];
+        let mut visited = Default::default();
+        let mut conflicts = Vec::new();
+        while let Some((dir_path, dir_node, dir_node_id, depth)) = stack.pop() {
+            if let Err(err) = self.check_traversal(&mut visited, dir_node_id, depth) {
+                panic!("Stopped at {dir_path}, it's {err}");
+            }
+            let mut renames = Vec::new();
+            for (pos, entry) in dir_node.entries().iter().enumerate() {
+                let name = entry.get_name();
+                let is_dir = name.ends_with('/');
+
+                // directories keep their trailing '/', only the name itself is substituted
+                let stem = name.trim_end_matches('/');
+                let new = substitution.apply(stem);
+                if new != stem {
+                    let new = if is_dir { format!("{new}/") } else { new };
+                    // the substituted name is not escaped yet
+                    let new_path = FsPath::parse(&format!("/{}", new.replace('\\', "\\\\")))
+                        .unwrap_or_else(|err| {
+                            panic!("Invalid new name for {dir_path}{name}: {err}")
+                        });
+                    assert!(
+                        new_path.segments().len() == 1 && new_path.is_dir() == is_dir,
+                        "New name for {dir_path}{name} must not include a path: {new}"
+                    );
+                    if let Err(err) = DirectoryEntry::validate_name(new_path.name()) {
+                        panic!("Invalid new name for {dir_path}{name}: {err}");
+                    }
+
+                    renames.push((pos, new_path.name().to_string()));
+                }
+
+                if recursive && is_dir {
+                    let node = self.get_directory_node(entry.block_id()).await;
+                    stack.push((dir_path.join(name), node, entry.block_id(), depth + 1));
+                }
+            }
+
+            if renames.is_empty() {
+                continue;
+            }
+
+            // the names after renaming have to be unique within the directory
+            let mut names = HashSet::new();
+            for (pos, entry) in dir_node.entries().iter().enumerate() {
+                let name = renames
+                    .iter()
+                    .find(|(rename_pos, _)| *rename_pos == pos)
+                    .map_or(entry.get_name(), |(_, new)| new);
+                let key = if self.case_insensitive {
+                    name.to_lowercase()
+                } else {
+                    name.clone()
+                };
+
+                if !names.insert(key) {
+                    conflicts.push(format!("{dir_path}{name}"));
+                }
+            }
+
+            for (pos, new) in &renames {
+                println!(
+                    "  {dir_path}{} -> {new}",
+                    dir_node.entries()[*pos].get_name()
+                );
+            }
+
+            planned.push((dir_node, dir_node_id, renames));
+        }
+
+        assert!(
+            conflicts.is_empty(),
+            "Renaming would create duplicate names: {}",
+            conflicts.join(", ")
+        );
+
+        let count: usize = planned.iter().map(|(_, _, renames)| renames.len()).sum();
+        if dry_run {
+            spinner.finish_with_message(format!("Would rename {count} entries in {path}"));
+            return;
+        }
+
+        // a single edit per directory node
+        for (mut dir_node, dir_node_id, renames) in planned {
+            spinner.set_message(format!("Renaming {} entries", renames.len()));
+            for (pos, new) in renames {
+                dir_node.rename_directory_entry_at(pos, new);
+            }
+            self.edit_directory_node(dir_node_id, dir_node).await;
+        }
+
+        // cleanup
+        spinner.finish_with_message(format!("Renamed {count} entries in {path}"));
+    }
+}
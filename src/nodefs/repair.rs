@@ -0,0 +1,120 @@
+use indicatif::HumanCount;
+use serenity::all::MessageId;
+
+use crate::{
+    directory_entry::BlockIndex,
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node::{self, Node},
+    node_kind::NodeKind::{Directory, File},
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // links a surviving node whose directory entry got lost, e.g. when the directory edit of an
+    // upload failed, the node and its data are checked before anything is changed
+    pub async fn repair_entry(&self, path: FsPath, node_id: BlockIndex) {
+        let (dir_path, name) = NodeFS::split_path(&path, true, false);
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Checking node {node_id}"));
+
+        let mut node = self
+            .try_get_node(node_id)
+            .await
+            .unwrap_or_else(|err| panic!("Node {node_id} can't be linked: {err}"));
+        assert!(
+            path.is_dir() == (node.kind == Directory),
+            "Node {node_id} is a {:?} node, {path} needs a {}",
+            node.kind,
+            if path.is_dir() {
+                "directory (remove the trailing '/')"
+            } else {
+                "file or symlink (add a trailing '/' for directories)"
+            }
+        );
+        if node.kind == File {
+            self.check_data_blocks(&node, &spinner).await;
+        }
+
+        let (mut dir_node, dir_node_id) = self.traverse_path(&dir_path).await;
+        failure::ensure(
+            !dir_node.contains_entry(name),
+            Hint::NameExists,
+            format!("{path} already exists, remove it or choose another name"),
+        );
+        assert!(
+            !dir_node
+                .entries()
+                .iter()
+                .any(|entry| entry.block_id() == node_id),
+            "Node {node_id} is already linked in {dir_path}"
+        );
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        if node.kind == Directory {
+            let is_ancestor =
+                NodeFS::is_ancestor(node_id, dir_node_id, self.root_node_id, |id| async move {
+                    self.get_directory_node(id).await.parent_block_id
+                })
+                .await;
+            assert!(
+                !is_ancestor,
+                "Node {node_id} can't be linked inside of itself"
+            );
+        }
+
+        let quotas = self.quota_directories(dir_node_id).await;
+        let size = match node.kind {
+            File => node.size(),
+            Directory => self.disk_usage(node.clone()).await,
+            _ => 0,
+        };
+        NodeFS::check_quota(&quotas, size);
+
+        // a node left over from an interrupted upload is still staged
+        spinner.set_message(format!("Linking node {node_id} as {path}"));
+        if let Some(staging_node_id) = self.find_staging_directory().await {
+            self.unstage_nodes(staging_node_id, &[node_id]).await;
+        }
+
+        // '..' follows the parent of directories
+        if node.kind == Directory && node.parent_block_id != dir_node_id {
+            node.parent_block_id = dir_node_id;
+            self.edit_directory_node(node_id, node).await;
+        }
+        dir_node.push_directory_entry(name, node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, size as i64).await;
+
+        // cleanup
+        spinner.finish_with_message(format!("Linked node {node_id} as {path}"));
+    }
+
+    // every data block has to exist, holes have no message
+    async fn check_data_blocks(&self, file_node: &Node, spinner: &indicatif::ProgressBar) {
+        let total = file_node.blocks().len();
+        for (idx, block_id) in file_node.blocks().iter().enumerate() {
+            spinner.set_message(format!(
+                "Checking data block {} / {}",
+                HumanCount(idx as u64 + 1),
+                HumanCount(total as u64)
+            ));
+            if *block_id == node::HOLE {
+                continue;
+            }
+
+            assert!(
+                util::message_exists(&self.client, self.data_channel, MessageId::new(*block_id))
+                    .await,
+                "Data block {block_id} of the node is missing, the file can't be linked"
+            );
+        }
+    }
+}
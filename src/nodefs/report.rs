@@ -0,0 +1,149 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use indicatif::{HumanBytes, HumanDuration};
+
+use crate::{
+    node::{Node, Size, Timestamp},
+    node_kind::NodeKind::{Directory, File, Symlink},
+    util,
+};
+
+use super::{METADATA_DIR_NAME, NodeFS};
+
+impl NodeFS {
+    pub async fn report_usage(&self, since: Duration, key: String) {
+        let usage_file = NodeFS::metadata_path("usage");
+
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(String::from("Measuring usage"));
+
+        // measure every top level directory, files in the root are accounted to '/'
+        let now = Node::now();
+        let mut usage = vec![(String::from("/"), 0)];
+        for entry in self.get_root_directory_node().await.entries() {
+            if entry.get_name() == METADATA_DIR_NAME {
+                continue;
+            }
+
+            spinner.set_message(format!("Measuring /{}", entry.get_name()));
+            match self.try_get_node(entry.block_id()).await {
+                Ok(node) if node.kind == File => usage[0].1 += node.size(),
+                Ok(node) if node.kind == Symlink => {}
+                Ok(node) => usage.push((
+                    format!("/{}", entry.get_name()),
+                    self.disk_usage(node).await,
+                )),
+                Err(err) => spinner.println(format!(
+                    "  Skipping corrupted node {} (/{}): {err}",
+                    entry.block_id(),
+                    entry.get_name()
+                )),
+            }
+        }
+
+        // record the snapshot, one '<timestamp> <bytes> <path>' line per directory
+        spinner.set_message(String::from("Saving usage snapshot"));
+        self.ensure_metadata_directory().await;
+        let mut history = self
+            .read_file_bytes(&usage_file, &key)
+            .await
+            .map(|history| String::from_utf8(history).expect("Usage history is corrupted"))
+            .unwrap_or_default();
+        for (path, size) in &usage {
+            history.push_str(&format!("{now} {size} {path}\n"));
+        }
+        self.write_file_bytes(&usage_file, history.as_bytes(), &key)
+            .await;
+
+        // compare against the oldest snapshot in the time frame
+        let snapshots: Vec<(Timestamp, Size, &str)> = history
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ' ');
+                Some((
+                    parts.next()?.parse().ok()?,
+                    parts.next()?.parse().ok()?,
+                    parts.next()?,
+                ))
+            })
+            .collect();
+        let cutoff = now.saturating_sub(since.as_secs());
+        let baseline_time = snapshots
+            .iter()
+            .map(|(timestamp, _, _)| *timestamp)
+            .filter(|timestamp| *timestamp >= cutoff)
+            .min()
+            .unwrap_or(now);
+        let baseline: HashMap<&str, Size> = snapshots
+            .iter()
+            .filter(|(timestamp, _, _)| *timestamp == baseline_time)
+            .map(|(_, size, path)| (*path, *size))
+            .collect();
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        println!(
+            "  Usage compared to {} ago",
+            HumanDuration(Duration::from_secs(now - baseline_time))
+        );
+        let width = usage.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+        for (path, size) in &usage {
+            let growth = match baseline.get(path.as_str()) {
+                Some(old_size) if size >= old_size => format!("+{}", HumanBytes(size - old_size)),
+                Some(old_size) => format!("-{}", HumanBytes(old_size - size)),
+                None => String::from("new"),
+            };
+            println!(
+                "  {path:<width$}  {:>12}  {growth}",
+                HumanBytes(*size).to_string()
+            );
+        }
+        for (path, old_size) in &baseline {
+            if !usage.iter().any(|(curr_path, _)| curr_path == path) {
+                println!(
+                    "  {path:<width$}  {:>12}  -{}",
+                    "removed",
+                    HumanBytes(*old_size)
+                );
+            }
+        }
+    }
+}
+
+impl NodeFS {
+    pub(super) async fn disk_usage(&self, node: Node) -> Size {
+        let mut size = 0;
+        let mut visited = HashSet::new();
+        let mut stack = vec![(node, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            match node.kind {
+                File => size += node.size(),
+                // only the target is stored
+                Symlink => {}
+                Directory => {
+                    for entry in node.entries() {
+                        // directories below a cycle would be counted forever
+                        if entry.get_name().ends_with('/')
+                            && let Err(err) =
+                                self.check_traversal(&mut visited, entry.block_id(), depth + 1)
+                        {
+                            panic!("Failed to measure {}: {err}", entry.get_name());
+                        }
+
+                        // corrupted nodes can't be measured
+                        if let Ok(entry_node) = self.try_get_node(entry.block_id()).await {
+                            stack.push((entry_node, depth + 1));
+                        }
+                    }
+                }
+            }
+        }
+
+        size
+    }
+}
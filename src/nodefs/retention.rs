@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use indicatif::{HumanDuration, MultiProgress};
+use serenity::all::MessageId;
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{Node, Timestamp},
+    node_kind::NodeKind::File,
+    util,
+};
+
+use super::NodeFS;
+
+struct RetentionPolicy {
+    // policies follow their directory through moves and renames
+    dir_node_id: BlockIndex,
+    keep_days: Option<u64>,
+    keep_last: Option<usize>,
+}
+
+impl NodeFS {
+    pub async fn retention_set(
+        &self,
+        path: FsPath,
+        keep_days: Option<u64>,
+        keep_last: Option<usize>,
+        key: String,
+    ) {
+        assert!(path.is_dir(), "Directories are required");
+        assert!(
+            keep_days.is_some() || keep_last.is_some(),
+            "Policy requires '--keep-days' or '--keep-last'"
+        );
+        assert!(
+            self.format_version >= 1,
+            "Retention requires modification times from format v1, run 'migrate --to v1' first"
+        );
+
+        let (_, dir_node_id) = self.traverse_path(&path).await;
+
+        let mut policies = self.load_retention_policies(&key).await;
+        policies.retain(|policy| policy.dir_node_id != dir_node_id);
+        policies.push(RetentionPolicy {
+            dir_node_id,
+            keep_days,
+            keep_last,
+        });
+        self.save_retention_policies(&policies, &key).await;
+
+        println!(
+            "  Set retention policy of {path}: {}",
+            NodeFS::describe_retention_policy(policies.last().unwrap())
+        );
+    }
+
+    pub async fn retention_rm(&self, path: FsPath, key: String) {
+        assert!(path.is_dir(), "Directories are required");
+
+        let (_, dir_node_id) = self.traverse_path(&path).await;
+
+        let mut policies = self.load_retention_policies(&key).await;
+        let count = policies.len();
+        policies.retain(|policy| policy.dir_node_id != dir_node_id);
+        assert!(policies.len() < count, "{path} has no retention policy");
+        self.save_retention_policies(&policies, &key).await;
+
+        println!("  Removed retention policy of {path}");
+    }
+
+    pub async fn retention_show(&self, key: String) {
+        let policies = self.load_retention_policies(&key).await;
+        if policies.is_empty() {
+            println!("  No retention policies");
+            return;
+        }
+
+        for policy in &policies {
+            let path = self.directory_path(policy.dir_node_id).await;
+            println!(
+                "  {}  {}",
+                path.map_or(String::from("<deleted directory>"), |path| path.to_string()),
+                NodeFS::describe_retention_policy(policy)
+            );
+        }
+    }
+
+    pub async fn retention_apply(&self, dry_run: bool, key: String) {
+        let progress = MultiProgress::new();
+        let now = Node::now();
+
+        let mut deleted = 0;
+        for policy in self.load_retention_policies(&key).await {
+            let Some(dir_path) = self.directory_path(policy.dir_node_id).await else {
+                println!(
+                    "  Skipping policy of deleted directory node {}, remove it with 'retention rm'",
+                    policy.dir_node_id
+                );
+                continue;
+            };
+
+            // show progress information
+            let spinner = progress.add(util::spinner());
+            spinner.set_message(format!("Checking {dir_path}"));
+
+            // only files directly inside the directory are pruned, newest first
+            let dir_node = self.get_directory_node(policy.dir_node_id).await;
+            let mut files: Vec<(String, Timestamp)> = Vec::new();
+            for entry in dir_node.entries() {
+                match self.try_get_node(entry.block_id()).await {
+                    Ok(node) if node.kind == File => {
+                        files.push((entry.get_name().clone(), node.modified))
+                    }
+                    Ok(_) => {}
+                    Err(err) => self.record_failure(
+                        &progress,
+                        format!("{dir_path}{}", entry.get_name()),
+                        format!("Corrupted node {}: {err}", entry.block_id()),
+                    ),
+                }
+            }
+            files.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+            // cleanup
+            progress.remove(&spinner);
+
+            for (pos, (name, modified)) in files.into_iter().enumerate() {
+                let in_last = policy.keep_last.is_some_and(|keep_last| pos < keep_last);
+                // files without a modification time (from v0) are never too old
+                let young = policy.keep_days.is_some_and(|keep_days| {
+                    modified == 0 || now.saturating_sub(modified) < keep_days * 24 * 60 * 60
+                });
+                if in_last || young {
+                    continue;
+                }
+
+                let path = dir_path.join(&name);
+                let age = HumanDuration(Duration::from_secs(now.saturating_sub(modified)));
+                if dry_run {
+                    println!("  Would delete {path} (modified {age} ago)");
+                } else {
+                    self.__rm(path, false, false, 1, &progress).await;
+                }
+                deleted += 1;
+            }
+        }
+
+        if dry_run {
+            println!("  {deleted} files would be deleted");
+        } else {
+            println!("  Deleted {deleted} files");
+        }
+    }
+}
+
+impl NodeFS {
+    async fn load_retention_policies(&self, key: &str) -> Vec<RetentionPolicy> {
+        let Some(data) = self
+            .read_file_bytes(&NodeFS::metadata_path("retention"), key)
+            .await
+        else {
+            return Vec::new();
+        };
+
+        // one '<directory node> <keep days or -> <keep last or ->' line per policy
+        String::from_utf8(data)
+            .expect("Retention policies are corrupted")
+            .lines()
+            .map(|line| {
+                let parts: Vec<&str> = line.split(' ').collect();
+                assert!(parts.len() == 3, "Retention policies are corrupted");
+
+                RetentionPolicy {
+                    dir_node_id: parts[0].parse().expect("Retention policies are corrupted"),
+                    keep_days: (parts[1] != "-")
+                        .then(|| parts[1].parse().expect("Retention policies are corrupted")),
+                    keep_last: (parts[2] != "-")
+                        .then(|| parts[2].parse().expect("Retention policies are corrupted")),
+                }
+            })
+            .collect()
+    }
+
+    async fn save_retention_policies(&self, policies: &[RetentionPolicy], key: &str) {
+        let data: String = policies
+            .iter()
+            .map(|policy| {
+                format!(
+                    "{} {} {}\n",
+                    policy.dir_node_id,
+                    policy
+                        .keep_days
+                        .map_or(String::from("-"), |keep_days| keep_days.to_string()),
+                    policy
+                        .keep_last
+                        .map_or(String::from("-"), |keep_last| keep_last.to_string())
+                )
+            })
+            .collect();
+
+        self.ensure_metadata_directory().await;
+        self.write_file_bytes(&NodeFS::metadata_path("retention"), data.as_bytes(), key)
+            .await;
+    }
+
+    // a directory that moved to another node keeps its policy
+    pub(super) async fn relink_retention_policy(
+        &self,
+        old_node_id: BlockIndex,
+        new_node_id: BlockIndex,
+        key: &str,
+    ) {
+        let mut policies = self.load_retention_policies(key).await;
+        let mut relinked = false;
+        for policy in policies
+            .iter_mut()
+            .filter(|policy| policy.dir_node_id == old_node_id)
+        {
+            policy.dir_node_id = new_node_id;
+            relinked = true;
+        }
+        if relinked {
+            self.save_retention_policies(&policies, key).await;
+        }
+    }
+
+    fn describe_retention_policy(policy: &RetentionPolicy) -> String {
+        match (policy.keep_days, policy.keep_last) {
+            (Some(keep_days), Some(keep_last)) => {
+                format!("keep files of the last {keep_days} days and the newest {keep_last}")
+            }
+            (Some(keep_days), None) => format!("keep files of the last {keep_days} days"),
+            (None, Some(keep_last)) => format!("keep the newest {keep_last} files"),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    // walks up the parents to find the path of a directory, `None` if it was deleted
+    async fn directory_path(&self, dir_node_id: BlockIndex) -> Option<FsPath> {
+        let mut names = Vec::new();
+        let mut node_id = dir_node_id;
+        while node_id != self.root_node_id {
+            if !util::message_exists(&self.client, self.data_channel, MessageId::new(node_id)).await
+            {
+                return None;
+            }
+
+            let node = self.try_get_node(node_id).await.ok()?;
+            let parent_node = self.try_get_node(node.parent_block_id).await.ok()?;
+            let entry = parent_node
+                .entries()
+                .iter()
+                .find(|entry| entry.block_id() == node_id)?;
+
+            names.push(entry.get_name().clone());
+            node_id = node.parent_block_id;
+        }
+
+        Some(
+            names
+                .iter()
+                .rev()
+                .fold(FsPath::root(), |path, name| path.join(name)),
+        )
+    }
+}
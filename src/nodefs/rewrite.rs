@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+
+use indicatif::{HumanBytes, HumanCount, MultiProgress, ProgressBar};
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{self, Node, Size},
+    node_kind::NodeKind::{Directory, File},
+    util,
+};
+
+use super::NodeFS;
+
+impl NodeFS {
+    // uploads files again in blocks of the given size, e.g. after they were adapted to a lower
+    // upload limit; the file node keeps its ID so hard links and entries stay valid, its block
+    // list is swapped with a single edit and the old blocks are deleted afterwards
+    pub async fn rewrite(&self, path: FsPath, chunk_size: Option<Size>, key: String) {
+        let chunk_size = self.check_chunk_size(chunk_size);
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Finding files in {path}"));
+
+        // files that don't use the chunk size yet, directories are walked with a stack, hard
+        // linked files are only rewritten once
+        let (node, node_id) = self.traverse_path(&path).await;
+        let mut files: Vec<(FsPath, Node, BlockIndex)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![(path.clone(), node, node_id, 0)];
+        let mut visited = Default::default();
+        while let Some((path, node, node_id, depth)) = stack.pop() {
+            match node.kind {
+                File if node.chunk_size != chunk_size
+                    && !node.blocks().is_empty()
+                    && seen.insert(node_id) =>
+                {
+                    files.push((path, node, node_id))
+                }
+                Directory => {
+                    if let Err(err) = self.check_traversal(&mut visited, node_id, depth) {
+                        panic!("Stopped at {path}, it's {err}");
+                    }
+                    for entry in node.entries() {
+                        let entry_path = path.join(entry.get_name());
+                        match self.try_get_node(entry.block_id()).await {
+                            Ok(entry_node) => {
+                                stack.push((entry_path, entry_node, entry.block_id(), depth + 1))
+                            }
+                            Err(err) => self.record_failure(
+                                &progress,
+                                entry_path.to_string(),
+                                format!("Corrupted node {}: {err}", entry.block_id()),
+                            ),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // show progress bar shared by all files
+        let progress_bar = progress.add(util::progress_bar(
+            files.iter().map(|(_, node, _)| node.size()).sum(),
+        ));
+
+        let total = files.len();
+        let mut bytes = 0;
+        let mut blocks = 0;
+        for (path, walked_node, file_node_id) in files {
+            spinner.set_message(format!(
+                "Rewriting {path} in blocks of {}",
+                HumanBytes(chunk_size)
+            ));
+
+            // read again, the file may have changed since the walk
+            let file_node = self.get_file_node(file_node_id).await;
+            if file_node.chunk_size == chunk_size || file_node.blocks().is_empty() {
+                progress_bar.inc(walked_node.size());
+                continue;
+            }
+
+            // the content doesn't change, neither does the modification time
+            let mut new_node = file_node.clone();
+            new_node.truncate_blocks(0);
+            new_node.chunk_size = chunk_size;
+
+            let (reader, writer) = tokio::io::duplex(node::BLOCK_SIZE);
+            tokio::join!(
+                async {
+                    let mut writer = writer;
+                    self.download_blocks(&file_node, &mut writer, &key, &ProgressBar::hidden())
+                        .await;
+                },
+                self.upload_blocks(reader, &mut new_node, &key, &progress_bar),
+            );
+            assert!(
+                new_node.size() == file_node.size(),
+                "Rewriting {path} produced {} instead of {}",
+                HumanBytes(new_node.size()),
+                HumanBytes(file_node.size())
+            );
+            new_node.modified = file_node.modified;
+
+            bytes += new_node.size();
+            blocks += new_node.blocks().len();
+            self.edit_file_node(file_node_id, new_node).await;
+
+            // the old blocks are unreferenced now, holes have no message
+            for block_id in file_node
+                .blocks()
+                .iter()
+                .filter(|block_id| **block_id != node::HOLE)
+            {
+                self.delete_data_block(*block_id).await;
+            }
+        }
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!(
+            "Rewrote {} files in {path} in blocks of {}",
+            HumanCount(total as u64),
+            HumanBytes(chunk_size)
+        ));
+        if total > 0 {
+            self.print_summary(
+                &progress,
+                util::TransferSummary {
+                    operation: "Uploaded",
+                    bytes,
+                    blocks,
+                    elapsed: progress_bar.elapsed(),
+                },
+            );
+        }
+    }
+}
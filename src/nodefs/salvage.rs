@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    aead::{Aead, KeyInit},
+};
+use indicatif::{HumanBytes, HumanCount, MultiProgress};
+use serenity::all::Message;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    directory_entry::BlockIndex,
+    node::{self, Node},
+    node_codec,
+    node_kind::NodeKind::{Directory, File},
+    nonce_counter::NonceCounter,
+    util,
+};
+
+use super::NodeFS;
+
+// loose blocks that don't continue a fragment are tried with this many nonces, a block's nonce is
+// its position in the file it belonged to
+const NONCE_SEARCH_LIMIT: u64 = 256;
+
+impl NodeFS {
+    // recovers data straight from the channel history without walking the directory tree, file
+    // nodes that still parse are rebuilt as a whole, remaining blocks are grouped into fragments
+    //
+    // it runs without the superblock, so nodes of any format version are read and messages of
+    // any author are tried, blocks that don't decrypt with the key are skipped anyway
+    pub async fn salvage(&self, out: String, key: String) {
+        let progress = MultiProgress::new();
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message("Scanning channel history");
+
+        let mut scanner = util::ChannelScanner::new(self.data_channel);
+        let mut nodes: Vec<Message> = Vec::new();
+        let mut data: Vec<Message> = Vec::new();
+        while let Some(page) = scanner
+            .next_page(&self.client)
+            .await
+            .expect("Failed to get messages")
+        {
+            for message in page {
+                if self.check_author(&message).is_err() || message.attachments.is_empty() {
+                    continue;
+                }
+                if message
+                    .attachments
+                    .iter()
+                    .any(|attachment| attachment.filename.starts_with("node"))
+                {
+                    nodes.push(message);
+                } else {
+                    data.push(message);
+                }
+            }
+            spinner.set_message(format!(
+                "Scanning channel history ({} messages)",
+                HumanCount((nodes.len() + data.len()) as u64)
+            ));
+        }
+
+        // directory nodes that still parse give their entries back their names
+        let mut files: Vec<(BlockIndex, Node)> = Vec::new();
+        let mut names: HashMap<BlockIndex, String> = HashMap::new();
+        for message in &nodes {
+            spinner.set_message(format!("Reading node {}", message.id));
+            let node = match self.node_bytes_from_message(message).await {
+                Ok(bytes) => node_codec::decode(bytes).map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            match node {
+                Ok(node) if node.kind == File => files.push((message.id.get(), node)),
+                Ok(node) if node.kind == Directory => {
+                    names.extend(
+                        node.entries()
+                            .iter()
+                            .map(|entry| (entry.block_id(), entry.get_name().clone())),
+                    );
+                }
+                Ok(_) => {}
+                Err(err) => self.record_failure(
+                    &progress,
+                    format!("node {}", message.id),
+                    format!("Unreadable node: {err}"),
+                ),
+            }
+        }
+
+        let out = PathBuf::from(out);
+        fs::create_dir_all(out.join("files"))
+            .await
+            .expect("Failed to create output directory");
+        fs::create_dir_all(out.join("fragments"))
+            .await
+            .expect("Failed to create output directory");
+
+        // whole files, blocks that are gone are written as zeros so the rest stays in place
+        let messages: HashMap<BlockIndex, &Message> = data
+            .iter()
+            .map(|message| (message.id.get(), message))
+            .collect();
+        let mut used = HashSet::new();
+        let mut bytes = 0;
+        for (node_id, file_node) in &files {
+            let name = names.get(node_id).map_or(String::new(), |name| {
+                format!("-{}", name.replace(['/', '\\'], "_"))
+            });
+            let path = out.join("files").join(format!("{node_id}{name}"));
+            spinner.set_message(format!("Rebuilding {}", path.display()));
+
+            let mut file = NodeFS::create_salvage_file(&path).await;
+            let mut nonce = NonceCounter::new();
+            for (idx, block_id) in file_node.blocks().iter().enumerate() {
+                let nonce = nonce.get_nonce();
+                let block = match messages.get(block_id) {
+                    _ if *block_id == node::HOLE => None,
+                    Some(message) => {
+                        used.insert(*block_id);
+                        match self.download_block(&message.attachments[0]).await {
+                            Ok(block) => cypher.decrypt(&nonce, block.as_slice()).ok(),
+                            Err(_) => None,
+                        }
+                    }
+                    None => None,
+                };
+                if block.is_none() && *block_id != node::HOLE {
+                    self.record_failure(
+                        &progress,
+                        path.display().to_string(),
+                        format!("Block {idx} ({block_id}) is lost, it's filled with zeros"),
+                    );
+                }
+
+                let block = block.unwrap_or_else(|| vec![0; file_node.block_size(idx) as usize]);
+                bytes += block.len() as u64;
+                file.write_all(&block)
+                    .await
+                    .expect("Failed to write salvaged data");
+            }
+            file.flush().await.expect("Failed to write salvaged data");
+        }
+
+        // the remaining blocks were uploaded one after another, so consecutive messages that
+        // decrypt with consecutive nonces most likely belong to the same file
+        let mut fragment: Option<(fs::File, u64)> = None;
+        let mut fragments = 0;
+        for message in data
+            .iter()
+            .filter(|message| !used.contains(&message.id.get()))
+        {
+            spinner.set_message(format!("Decrypting block {}", message.id));
+            let Ok(block) = self.download_block(&message.attachments[0]).await else {
+                self.record_failure(
+                    &progress,
+                    format!("block {}", message.id),
+                    String::from("Failed to download"),
+                );
+                continue;
+            };
+
+            let next = fragment.as_ref().map(|(_, next)| *next);
+            let decrypt = |idx: u64| {
+                cypher
+                    .decrypt(
+                        &NonceCounter::starting_at(idx).get_nonce(),
+                        block.as_slice(),
+                    )
+                    .ok()
+                    .map(|data| (idx, data))
+            };
+            let Some((idx, data)) = next
+                .and_then(decrypt)
+                .or_else(|| (0..NONCE_SEARCH_LIMIT).find_map(decrypt))
+            else {
+                self.record_failure(
+                    &progress,
+                    format!("block {}", message.id),
+                    String::from("Can't be decrypted with this key"),
+                );
+                if let Some((mut file, _)) = fragment.take() {
+                    file.flush().await.expect("Failed to write salvaged data");
+                }
+                continue;
+            };
+
+            if next != Some(idx) {
+                if let Some((file, _)) = &mut fragment {
+                    file.flush().await.expect("Failed to write salvaged data");
+                }
+                fragments += 1;
+                let path = out
+                    .join("fragments")
+                    .join(format!("{}-from-block-{idx}", message.id));
+                fragment = Some((NodeFS::create_salvage_file(&path).await, idx));
+            }
+            let (file, next) = fragment.as_mut().unwrap();
+            file.write_all(&data)
+                .await
+                .expect("Failed to write salvaged data");
+            *next = idx + 1;
+            bytes += data.len() as u64;
+        }
+
+        if let Some((file, _)) = &mut fragment {
+            file.flush().await.expect("Failed to write salvaged data");
+        }
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        println!(
+            "  Salvaged {} in {} files and {} fragments to {}",
+            HumanBytes(bytes),
+            HumanCount(files.len() as u64),
+            HumanCount(fragments),
+            out.display()
+        );
+    }
+
+    async fn create_salvage_file(path: &Path) -> fs::File {
+        fs::File::create(path)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to create {}: {err}", path.display()))
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    aead::{Aead, KeyInit},
+};
+use indicatif::{HumanBytes, HumanCount, MultiProgress};
+use serenity::all::MessageId;
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{self, Size},
+    node_kind::NodeKind::{Directory, File},
+    nonce_counter::NonceCounter,
+    util,
+};
+
+use super::{BlockFetch, NodeFS};
+
+impl NodeFS {
+    // reads about `amount` bytes of files every run and checks that every block still decrypts,
+    // the next run continues after the last scrubbed file so repeated runs cover everything
+    //
+    // lost blocks are uploaded again from the same file on a mirror remote if one is given, the
+    // mirror's copy has to use the same block size
+    pub async fn scrub(&self, amount: Size, mirror: Option<String>, key: String) {
+        let progress = MultiProgress::new();
+        let mirror = match &mirror {
+            Some(name) => Some(NodeFS::open_remote(name, self.json, "download").await),
+            None => None,
+        };
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message("Finding files");
+
+        // hard linked files are only scrubbed once
+        let (root_node, root_node_id) = self.traverse_path(&FsPath::root()).await;
+        let mut files: Vec<(String, FsPath, BlockIndex, Size)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![(FsPath::root(), root_node, root_node_id, 0)];
+        let mut visited = Default::default();
+        while let Some((path, node, node_id, depth)) = stack.pop() {
+            match node.kind {
+                File if seen.insert(node_id) => {
+                    files.push((path.to_string(), path, node_id, node.size()))
+                }
+                Directory => {
+                    if let Err(err) = self.check_traversal(&mut visited, node_id, depth) {
+                        panic!("Stopped at {path}, it's {err}");
+                    }
+                    for entry in node.entries() {
+                        let entry_path = path.join(entry.get_name());
+                        match self.try_get_node(entry.block_id()).await {
+                            Ok(entry_node) => {
+                                stack.push((entry_path, entry_node, entry.block_id(), depth + 1))
+                            }
+                            Err(err) => self.record_failure(
+                                &progress,
+                                entry_path.to_string(),
+                                format!("Corrupted node {}: {err}", entry.block_id()),
+                            ),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        files.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+        // continue after the last scrubbed file, wrapping around at the end
+        let cursor = self
+            .read_file_bytes(&NodeFS::metadata_path("scrub"), &key)
+            .await
+            .map(|data| String::from_utf8(data).expect("Scrub position is corrupted"));
+        let start = cursor.map_or(0, |cursor| {
+            files.partition_point(|(path, ..)| *path <= cursor) % files.len().max(1)
+        });
+        let mut selected = Vec::new();
+        let mut bytes = 0;
+        for idx in 0..files.len() {
+            if bytes >= amount && !selected.is_empty() {
+                break;
+            }
+            let file = &files[(start + idx) % files.len()];
+            bytes += file.3;
+            selected.push(file);
+        }
+
+        // show progress bar shared by all files
+        let progress_bar = progress.add(util::progress_bar(bytes));
+
+        let mut lost_blocks = 0;
+        let mut repaired = 0;
+        for (_, path, node_id, _) in &selected {
+            spinner.set_message(format!("Scrubbing {path}"));
+            let file_node = self.get_file_node(*node_id).await;
+            let lost = self
+                .download_blocks_from(
+                    &file_node,
+                    0,
+                    BlockFetch {
+                        allow_holes: true,
+                        ..Default::default()
+                    },
+                    &mut tokio::io::sink(),
+                    &key,
+                    &progress_bar,
+                )
+                .await;
+            if lost.is_empty() {
+                continue;
+            }
+
+            lost_blocks += lost.len();
+            match &mirror {
+                Some((mirror, mirror_key)) => {
+                    repaired += self
+                        .repair_blocks(
+                            &progress,
+                            (path, *node_id),
+                            &lost,
+                            (mirror, mirror_key),
+                            &key,
+                        )
+                        .await
+                }
+                None => {
+                    for (idx, offset, size) in lost {
+                        self.record_failure(
+                            &progress,
+                            path.to_string(),
+                            format!(
+                                "Block {idx} is lost or corrupted (bytes {offset}..{})",
+                                offset + size
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        // the next run starts after the last file, or from the beginning once everything was read
+        if let Some((last, ..)) = selected.last() {
+            self.ensure_metadata_directory().await;
+            self.write_file_bytes(&NodeFS::metadata_path("scrub"), last.as_bytes(), &key)
+                .await;
+        }
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!(
+            "Scrubbed {} of {} files ({}), {} blocks lost{}",
+            HumanCount(selected.len() as u64),
+            HumanCount(files.len() as u64),
+            HumanBytes(bytes),
+            HumanCount(lost_blocks as u64),
+            if mirror.is_some() {
+                format!(", {} repaired from the mirror", HumanCount(repaired))
+            } else {
+                String::new()
+            }
+        ));
+    }
+
+    // uploads the lost blocks again from the mirror's copy of the file, returns how many were
+    // repaired
+    async fn repair_blocks(
+        &self,
+        progress: &MultiProgress,
+        (path, node_id): (&FsPath, BlockIndex),
+        lost: &[(usize, Size, Size)],
+        (mirror, mirror_key): (&NodeFS, &str),
+        key: &str,
+    ) -> u64 {
+        let mut file_node = self.get_file_node(node_id).await;
+        let mirror_node = match mirror.lookup_path(path, false).await {
+            Some((mirror_node, _))
+                if mirror_node.kind == File
+                    && mirror_node.size() == file_node.size()
+                    && mirror_node.chunk_size == file_node.chunk_size =>
+            {
+                mirror_node
+            }
+            Some(_) => {
+                self.record_failure(
+                    progress,
+                    path.to_string(),
+                    format!(
+                        "{} blocks are lost, the mirror's copy has a different size or block size",
+                        lost.len()
+                    ),
+                );
+                return 0;
+            }
+            None => {
+                self.record_failure(
+                    progress,
+                    path.to_string(),
+                    format!("{} blocks are lost, the mirror has no copy", lost.len()),
+                );
+                return 0;
+            }
+        };
+
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+        let mirror_cypher = Aes256GcmSiv::new_from_slice(&mirror_key.as_bytes()[..32])
+            .expect("Failed to create cypher");
+        let mut replaced = Vec::new();
+        for (idx, _, size) in lost {
+            // blocks are encrypted with their index as the nonce on both sides
+            let nonce = NonceCounter::starting_at(*idx as u64).get_nonce();
+            let data = match mirror_node.blocks()[*idx] {
+                node::HOLE => Ok(vec![0; *size as usize]),
+                block_id => mirror.try_get_data_block(block_id).await.and_then(|block| {
+                    mirror_cypher
+                        .decrypt(&nonce, block.as_slice())
+                        .map_err(|_| String::from("it doesn't decrypt"))
+                }),
+            };
+            match data {
+                Ok(data) if data.len() as Size == *size => {
+                    let block = cypher
+                        .encrypt(&nonce, data.as_slice())
+                        .expect("Failed to encrypt data");
+                    let block_id = self.create_data_block(block).await;
+                    replaced.push(file_node.replace_data_block(*idx, block_id));
+                }
+                Ok(_) => self.record_failure(
+                    progress,
+                    path.to_string(),
+                    format!("Block {idx} is lost, the mirror's copy has a different size"),
+                ),
+                Err(err) => self.record_failure(
+                    progress,
+                    path.to_string(),
+                    format!("Block {idx} is lost, the mirror's copy too: {err}"),
+                ),
+            }
+        }
+        if replaced.is_empty() {
+            return 0;
+        }
+
+        // corrupted blocks still have a message, lost ones may not
+        self.edit_file_node(node_id, file_node).await;
+        for block_id in &replaced {
+            if *block_id != node::HOLE {
+                self.delete_shards(*block_id).await;
+                let _ = util::delete_message(
+                    &self.client,
+                    self.data_channel,
+                    MessageId::new(*block_id),
+                )
+                .await;
+            }
+        }
+        progress
+            .println(format!(
+                "  Repaired {} blocks of {path} from the mirror",
+                replaced.len()
+            ))
+            .unwrap();
+
+        replaced.len() as u64
+    }
+}
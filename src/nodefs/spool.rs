@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+
+use aes_gcm_siv::{
+    Aes256GcmSiv,
+    aead::{Aead, KeyInit},
+};
+use indicatif::{HumanBytes, HumanCount, MultiProgress};
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::{
+    directory_entry::BlockIndex,
+    failure::{self, Hint},
+    fs_path::FsPath,
+    node::{self, Size},
+    nonce_counter::NonceCounter,
+    util,
+};
+
+use super::NodeFS;
+
+const MANIFEST_NAME: &str = "manifest";
+
+// what's in a spool directory, saved after every pushed block so pushing can resume
+//
+// 'destination <path>' followed by one 'block <size> <message id|->' line per block
+struct SpoolManifest {
+    destination: FsPath,
+    blocks: Vec<(Size, Option<BlockIndex>)>,
+}
+
+impl SpoolManifest {
+    fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let destination = lines
+            .next()
+            .and_then(|line| line.strip_prefix("destination "))
+            .expect("Spool manifest must start with the destination");
+        let destination = FsPath::parse(destination)
+            .unwrap_or_else(|err| panic!("Spool manifest has an invalid destination: {err}"));
+
+        let blocks = lines
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                assert!(
+                    fields.next() == Some("block"),
+                    "Invalid spool manifest line '{line}'"
+                );
+                let size = fields
+                    .next()
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or_else(|| {
+                        panic!("Invalid block size in spool manifest line '{line}'")
+                    });
+                let block_id = match fields.next() {
+                    Some("-") => None,
+                    Some(block_id) => Some(block_id.parse().unwrap_or_else(|_| {
+                        panic!("Invalid message ID in spool manifest line '{line}'")
+                    })),
+                    None => panic!("Missing message ID in spool manifest line '{line}'"),
+                };
+
+                (size, block_id)
+            })
+            .collect();
+
+        SpoolManifest {
+            destination,
+            blocks,
+        }
+    }
+
+    // written to a temporary file first so an interruption never leaves half a manifest
+    async fn save(&self, spool: &Path) {
+        let mut text = format!("destination {}\n", self.destination);
+        for (size, block_id) in &self.blocks {
+            match block_id {
+                Some(block_id) => text.push_str(&format!("block {size} {block_id}\n")),
+                None => text.push_str(&format!("block {size} -\n")),
+            }
+        }
+
+        let tmp = spool.join(format!("{MANIFEST_NAME}.tmp"));
+        fs::write(&tmp, text)
+            .await
+            .expect("Failed to write spool manifest");
+        fs::rename(tmp, spool.join(MANIFEST_NAME))
+            .await
+            .expect("Failed to write spool manifest");
+    }
+
+    fn block_path(spool: &Path, idx: usize) -> PathBuf {
+        spool.join(format!("block_{idx}"))
+    }
+}
+
+impl NodeFS {
+    pub async fn upload_spooled(
+        &self,
+        source: String,
+        destination: FsPath,
+        spool: String,
+        key: String,
+    ) {
+        NodeFS::split_path(&destination, false, false);
+        let spool_dir = Path::new(&spool);
+        if fs::try_exists(spool_dir)
+            .await
+            .expect("Failed to check spool directory")
+        {
+            let mut entries = fs::read_dir(spool_dir)
+                .await
+                .expect("Failed to read spool directory");
+            assert!(
+                entries
+                    .next_entry()
+                    .await
+                    .expect("Failed to read spool directory")
+                    .is_none(),
+                "Spool directory {spool} is not empty, use 'push {spool}' to continue an earlier upload"
+            );
+        }
+        fs::create_dir_all(spool_dir)
+            .await
+            .expect("Failed to create spool directory");
+
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Spooling {source} to {spool}"));
+
+        // Open source file
+        let mut file = fs::File::open(&source).await.expect("Failed to open file");
+        let filesize = file
+            .metadata()
+            .await
+            .expect("Failed to fetch source file size")
+            .len();
+        failure::ensure(
+            filesize <= node::MAX_FILE_SIZE as u64,
+            Hint::FileTooBig,
+            format!(
+                "File exceeds maximum file size of {} ({}): {} ({})",
+                HumanBytes(node::MAX_FILE_SIZE as u64),
+                HumanCount(node::MAX_FILE_SIZE as u64),
+                HumanBytes(filesize),
+                HumanCount(filesize)
+            ),
+        );
+
+        // show progress bar
+        let progress_bar = progress.add(util::progress_bar(filesize));
+
+        // encrypt exactly like a direct upload, the push only sends the finished blocks
+        let cypher =
+            Aes256GcmSiv::new_from_slice(&key.as_bytes()[..32]).expect("Failed to create cypher");
+        let mut nonce = NonceCounter::new();
+        let mut manifest = SpoolManifest {
+            destination,
+            blocks: Vec::new(),
+        };
+        loop {
+            let mut chunk = Vec::with_capacity(node::BLOCK_SIZE);
+            (&mut file)
+                .take(node::BLOCK_SIZE as u64)
+                .read_to_end(&mut chunk)
+                .await
+                .expect("Error reading from source");
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk_size = chunk.len() as u64;
+
+            let chunk = cypher
+                .encrypt(&nonce.get_nonce(), chunk.as_slice())
+                .expect("Failed to encrypt data");
+            fs::write(
+                SpoolManifest::block_path(spool_dir, manifest.blocks.len()),
+                chunk,
+            )
+            .await
+            .expect("Failed to write spooled block");
+            manifest.blocks.push((chunk_size, None));
+
+            progress_bar.inc(chunk_size);
+        }
+
+        // the manifest marks the spool as complete
+        manifest.save(spool_dir).await;
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!(
+            "Spooled {source} into {} blocks",
+            manifest.blocks.len()
+        ));
+
+        self.push(spool).await;
+    }
+
+    pub async fn push(&self, spool: String) {
+        let spool_dir = Path::new(&spool);
+        let mut manifest = SpoolManifest::parse(
+            &fs::read_to_string(spool_dir.join(MANIFEST_NAME))
+                .await
+                .expect("Failed to read spool manifest, the spooling may not have finished"),
+        );
+        let destination = manifest.destination.clone();
+        let (file_path, file_name) = NodeFS::split_path(&destination, false, false);
+
+        let progress = MultiProgress::new();
+
+        // show progress informaton
+        let spinner = progress.add(util::spinner());
+        spinner.set_message(format!("Pushing {spool} to {destination}"));
+
+        // show progress bar, blocks pushed before are already done
+        let progress_bar = progress.add(util::progress_bar(
+            manifest.blocks.iter().map(|(size, _)| size).sum(),
+        ));
+        progress_bar.set_position(
+            manifest
+                .blocks
+                .iter()
+                .filter(|(_, block_id)| block_id.is_some())
+                .map(|(size, _)| size)
+                .sum(),
+        );
+
+        let mut pushed = 0;
+        for idx in 0..manifest.blocks.len() {
+            let (size, block_id) = manifest.blocks[idx];
+            if block_id.is_some() {
+                continue;
+            }
+
+            let data = fs::read(SpoolManifest::block_path(spool_dir, idx))
+                .await
+                .expect("Failed to read spooled block");
+            self.check_upload_limit(data.len() as Size).await;
+            manifest.blocks[idx].1 = Some(self.create_data_block(data).await);
+            manifest.save(spool_dir).await;
+
+            pushed += 1;
+            progress_bar.inc(size);
+        }
+
+        // get target directory
+        let (mut dir_node, dir_node_id) = self.traverse_path(&file_path).await;
+        failure::ensure(
+            !dir_node.is_full(),
+            Hint::DirectoryFull,
+            "The directory is full",
+        );
+        failure::ensure(
+            !dir_node.contains_entry(file_name),
+            Hint::NameExists,
+            "The file already exists",
+        );
+        let quotas = self.quota_directories(dir_node_id).await;
+
+        // create file node from the pushed blocks
+        let (mut file_node, file_node_id) = self.create_file_node(dir_node_id).await;
+        let staging_node_id = self.staging_directory().await;
+        self.stage_node(staging_node_id, file_node_id).await;
+        for (size, block_id) in &manifest.blocks {
+            file_node.push_data_block(block_id.unwrap(), *size);
+        }
+        let size = file_node.size();
+        NodeFS::check_quota(&quotas, size);
+
+        // update nodes
+        self.edit_file_node(file_node_id, file_node).await;
+        self.unstage_nodes(staging_node_id, &[file_node_id]).await;
+        dir_node.push_directory_entry(file_name, file_node_id);
+        self.edit_directory_node(dir_node_id, dir_node).await;
+        self.charge_quota(&quotas, size as i64).await;
+
+        // the spool is only removed once the file is in place
+        fs::remove_dir_all(spool_dir)
+            .await
+            .expect("Failed to remove spool directory");
+
+        // cleanup
+        progress_bar.finish_and_clear();
+        spinner.finish_with_message(format!("Finished pushing {spool} to {destination}"));
+        self.print_summary(
+            &progress,
+            util::TransferSummary {
+                operation: "Pushed",
+                bytes: size,
+                blocks: pushed,
+                elapsed: progress_bar.elapsed(),
+            },
+        );
+    }
+}
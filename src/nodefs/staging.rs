@@ -0,0 +1,221 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use indicatif::{HumanCount, MultiProgress};
+use serenity::all::MessageId;
+
+use crate::{directory_entry::BlockIndex, fs_path::FsPath, node_kind::NodeKind::File, util};
+
+use super::{METADATA_DIR_NAME, NodeFS};
+
+const STAGING_DIR_NAME: &str = "tmp/";
+
+// new nodes are linked under '/.dfs/tmp/' while their data is uploaded and only committed
+// into the target directory at the end, an interrupted operation never leaves a half
+// written entry in the target and 'gc staging' deletes its leftovers
+//
+// the entries are named after the node ID, so concurrent operations never collide, uploads add
+// the CRC of the target name so 'upload --on-conflict resume' finds their node again
+impl NodeFS {
+    pub(super) async fn staging_directory(&self) -> BlockIndex {
+        self.ensure_metadata_directory().await;
+        let metadata_path = FsPath::root().join(METADATA_DIR_NAME);
+        let (mut metadata_node, metadata_node_id) = self.traverse_path(&metadata_path).await;
+        if let Some(entry) = metadata_node.find_directory_entry(STAGING_DIR_NAME) {
+            return entry.block_id();
+        }
+        assert!(!metadata_node.is_full(), "The metadata directory is full");
+
+        let (_, dir_node_id) = self.create_directory_node(metadata_node_id).await;
+        metadata_node.push_directory_entry(STAGING_DIR_NAME, dir_node_id);
+        self.edit_directory_node(metadata_node_id, metadata_node)
+            .await;
+
+        dir_node_id
+    }
+
+    // like staging_directory, but doesn't create it
+    pub(super) async fn find_staging_directory(&self) -> Option<BlockIndex> {
+        let staging_path = FsPath::root()
+            .join(METADATA_DIR_NAME)
+            .join(STAGING_DIR_NAME);
+        self.lookup_path(&staging_path, false)
+            .await
+            .map(|(_, node_id)| node_id)
+    }
+
+    pub(super) async fn stage_node(&self, staging_node_id: BlockIndex, node_id: BlockIndex) {
+        self.stage_entry(staging_node_id, node_id.to_string(), node_id)
+            .await;
+    }
+
+    // the node has to be created in its target directory
+    pub(super) async fn stage_upload(
+        &self,
+        staging_node_id: BlockIndex,
+        node_id: BlockIndex,
+        name: &str,
+    ) {
+        self.stage_entry(
+            staging_node_id,
+            NodeFS::staged_upload_name(node_id, name),
+            node_id,
+        )
+        .await;
+    }
+
+    // the most recent staged upload of the name into the directory
+    pub(super) async fn find_staged_upload(
+        &self,
+        staging_node_id: BlockIndex,
+        dir_node_id: BlockIndex,
+        name: &str,
+    ) -> Option<BlockIndex> {
+        let staging_node = self.get_directory_node(staging_node_id).await;
+        let mut candidates: Vec<BlockIndex> = staging_node
+            .entries()
+            .iter()
+            .filter(|entry| *entry.get_name() == NodeFS::staged_upload_name(entry.block_id(), name))
+            .map(|entry| entry.block_id())
+            .collect();
+        candidates.sort_unstable();
+        for node_id in candidates.into_iter().rev() {
+            if let Ok(node) = self.try_get_node(node_id).await
+                && node.kind == File
+                && node.parent_block_id == dir_node_id
+            {
+                return Some(node_id);
+            }
+        }
+
+        None
+    }
+
+    fn staged_upload_name(node_id: BlockIndex, name: &str) -> String {
+        format!("{node_id}.{:08x}", util::crc32(name.as_bytes()))
+    }
+
+    async fn stage_entry(
+        &self,
+        staging_node_id: BlockIndex,
+        entry_name: String,
+        node_id: BlockIndex,
+    ) {
+        let mut staging_node = self.get_directory_node(staging_node_id).await;
+        assert!(
+            !staging_node.is_full(),
+            "Too many unfinished operations in /{METADATA_DIR_NAME}{STAGING_DIR_NAME}, 'gc staging' deletes the leftovers of interrupted ones"
+        );
+
+        staging_node.push_directory_entry(entry_name, node_id);
+        self.edit_directory_node(staging_node_id, staging_node)
+            .await;
+    }
+
+    // has to happen before the nodes are linked into their directory, a node is never
+    // reachable from both places as deleting the staged entry would delete the data
+    pub(super) async fn unstage_nodes(&self, staging_node_id: BlockIndex, node_ids: &[BlockIndex]) {
+        if node_ids.is_empty() {
+            return;
+        }
+
+        let mut staging_node = self.get_directory_node(staging_node_id).await;
+        // entries removed by hand in the meantime are ignored
+        let names: Vec<String> = staging_node
+            .entries()
+            .iter()
+            .filter(|entry| node_ids.contains(&entry.block_id()))
+            .map(|entry| entry.get_name().clone())
+            .collect();
+        for name in names {
+            staging_node.delete_directory_entry(name);
+        }
+        self.edit_directory_node(staging_node_id, staging_node)
+            .await;
+    }
+
+    // deletes staged nodes and their data once their operation is gone, nodes of running
+    // operations are told apart by the age of their last edit as uploads save their node
+    // every few blocks
+    pub async fn gc_staging(&self, min_age: Duration, dry_run: bool) {
+        let Some(staging_node_id) = self.find_staging_directory().await else {
+            println!("  Nothing is staged");
+            return;
+        };
+        let progress = MultiProgress::new();
+
+        // show progress information
+        let spinner = progress.add(util::spinner());
+        spinner.set_message("Checking staged nodes");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the unix epoch")
+            .as_secs();
+        let staging_node = self.get_directory_node(staging_node_id).await;
+        let mut stale = Vec::new();
+        for entry in staging_node.entries() {
+            let node_id = entry.block_id();
+            let message =
+                match util::get_message(&self.client, self.data_channel, MessageId::new(node_id))
+                    .await
+                {
+                    Ok(message) => message,
+                    // the node is gone already, only its entry is left
+                    Err(err) if util::is_unknown_message(&err) => {
+                        stale.push((node_id, None));
+                        continue;
+                    }
+                    Err(err) => panic!("Failed to get node: {}", util::explain_error(&err)),
+                };
+            let edited = message
+                .edited_timestamp
+                .unwrap_or(message.timestamp)
+                .unix_timestamp() as u64;
+            if now.saturating_sub(edited) < min_age.as_secs() {
+                continue;
+            }
+
+            match self.node_bytes_from_message(&message).await {
+                Ok(bytes) => match self.parse_node(bytes) {
+                    Ok(node) => stale.push((node_id, Some(node))),
+                    Err(err) => self.record_failure(
+                        &progress,
+                        format!("staged node {node_id}"),
+                        format!("Corrupted node: {err}"),
+                    ),
+                },
+                Err(err) => self.record_failure(
+                    &progress,
+                    format!("staged node {node_id}"),
+                    err.to_string(),
+                ),
+            }
+        }
+        spinner.finish_with_message(format!(
+            "Found {} leftovers of interrupted operations ({} staged)",
+            HumanCount(stale.len() as u64),
+            HumanCount(staging_node.entries().len() as u64)
+        ));
+        if dry_run || stale.is_empty() {
+            return;
+        }
+
+        // the entries go first, a deleted node must never be reachable
+        let node_ids: Vec<BlockIndex> = stale.iter().map(|(node_id, _)| *node_id).collect();
+        self.unstage_nodes(staging_node_id, &node_ids).await;
+        for (node_id, node) in stale {
+            match node {
+                Some(node) if node.kind == File => {
+                    self.delete_file(node, node_id, node_id.to_string(), &progress)
+                        .await
+                }
+                Some(_) => self.delete_block(node_id).await,
+                None => {}
+            }
+        }
+        println!(
+            "  Deleted {} staged nodes",
+            HumanCount(node_ids.len() as u64)
+        );
+    }
+}
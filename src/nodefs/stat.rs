@@ -0,0 +1,198 @@
+use indicatif::{HumanBytes, HumanCount};
+use serenity::{all::MessageId, json::json};
+
+use crate::{
+    directory_entry::BlockIndex,
+    fs_path::FsPath,
+    node::{self, Node, Size},
+    node_kind::NodeKind::{Directory, File, Symlink},
+    util,
+};
+
+use super::NodeFS;
+
+// one row of a file's block map, holes have no message
+struct BlockInfo {
+    block_id: BlockIndex,
+    size: Size,
+    stored_size: Option<u32>,
+    crc32: Option<u32>,
+    link: Option<String>,
+}
+
+impl NodeFS {
+    // the last path segment isn't followed, like 'stat' without '-L'
+    pub async fn stat(&self, path: FsPath, blocks: bool, json: bool) {
+        // show progress information
+        let spinner = util::spinner();
+        spinner.set_message(format!("Fetching {path}"));
+
+        let (node, node_id) = self.traverse_path(&path).await;
+        assert!(
+            !blocks || node.kind == File,
+            "{path} is not a file, only files have blocks"
+        );
+
+        // every block is downloaded to hash the stored (encrypted) data
+        let mut block_map = Vec::new();
+        if blocks {
+            for (idx, block_id) in node.blocks().iter().enumerate() {
+                spinner.set_message(format!(
+                    "Hashing block {} / {}",
+                    HumanCount(idx as u64 + 1),
+                    HumanCount(node.blocks().len() as u64)
+                ));
+                block_map.push(self.block_info(&node, idx, *block_id).await);
+            }
+        }
+
+        // cleanup
+        spinner.finish_and_clear();
+
+        if json {
+            NodeFS::print_stat_json(&path, &node, node_id, blocks.then_some(&block_map));
+        } else {
+            NodeFS::print_stat(&path, &node, node_id, blocks.then_some(&block_map));
+        }
+    }
+
+    async fn block_info(&self, file_node: &Node, idx: usize, block_id: BlockIndex) -> BlockInfo {
+        let size = file_node.block_size(idx);
+        if block_id == node::HOLE {
+            return BlockInfo {
+                block_id,
+                size,
+                stored_size: None,
+                crc32: None,
+                link: None,
+            };
+        }
+
+        let message = util::get_message(&self.client, self.data_channel, MessageId::new(block_id))
+            .await
+            .unwrap_or_else(|err| panic!("Failed to get data block {block_id}: {err}"));
+        if let Err(err) = self.check_author(&message) {
+            panic!("Data block {block_id} {err}");
+        }
+        let attachment = message.attachments.first().unwrap_or_else(|| {
+            panic!("Data block {block_id} should contain an attachment of block data")
+        });
+        let data = util::download_attachment(attachment)
+            .await
+            .expect("Failed to get data block");
+
+        BlockInfo {
+            block_id,
+            size,
+            stored_size: Some(attachment.size),
+            crc32: Some(util::crc32(&data)),
+            link: Some(self.message_link(block_id)),
+        }
+    }
+
+    fn message_link(&self, message_id: u64) -> String {
+        let guild = self
+            .guild_id
+            .map_or(String::from("@me"), |guild_id| guild_id.to_string());
+
+        format!(
+            "https://discord.com/channels/{guild}/{}/{message_id}",
+            self.data_channel.get()
+        )
+    }
+
+    fn print_stat(
+        path: &FsPath,
+        node: &Node,
+        node_id: BlockIndex,
+        blocks: Option<&Vec<BlockInfo>>,
+    ) {
+        println!("  Path        {path}");
+        println!("  Node        {node_id}");
+        println!("  Kind        {:?}", node.kind);
+        match node.kind {
+            File => {
+                println!(
+                    "  Size        {} ({})",
+                    HumanBytes(node.size()),
+                    HumanCount(node.size())
+                );
+                println!("  Chunk size  {}", HumanBytes(node.chunk_size));
+                println!("  Blocks      {}", HumanCount(node.blocks().len() as u64));
+            }
+            Directory => println!("  Entries     {}", HumanCount(node.size())),
+            Symlink => println!("  Target      {}", node.target()),
+        }
+        println!("  Links       {}", node.links);
+        println!("  Created     {}", node.created);
+        println!("  Modified    {}", node.modified);
+
+        let Some(blocks) = blocks else {
+            return;
+        };
+        println!();
+        for (idx, block) in blocks.iter().enumerate() {
+            match (block.stored_size, block.crc32, &block.link) {
+                (Some(stored_size), Some(crc32), Some(link)) => println!(
+                    "  {idx:>6}  {:>20}  {:>10}  {:>10} stored  crc32 {crc32:08x}  {link}",
+                    block.block_id,
+                    HumanBytes(block.size).to_string(),
+                    HumanBytes(stored_size as u64).to_string()
+                ),
+                _ => println!(
+                    "  {idx:>6}  {:>20}  {:>10}  hole",
+                    "-",
+                    HumanBytes(block.size).to_string()
+                ),
+            }
+        }
+    }
+
+    fn print_stat_json(
+        path: &FsPath,
+        node: &Node,
+        node_id: BlockIndex,
+        blocks: Option<&Vec<BlockInfo>>,
+    ) {
+        let mut json = json!({
+            "path": path.to_string(),
+            "node": node_id,
+            "kind": format!("{:?}", node.kind).to_lowercase(),
+            "size": node.size(),
+            "links": node.links,
+            "created": node.created,
+            "modified": node.modified,
+        });
+        match node.kind {
+            File => json["chunk_size"] = json!(node.chunk_size),
+            Symlink => json["target"] = json!(node.target()),
+            Directory => {}
+        }
+
+        if let Some(blocks) = blocks {
+            json["blocks"] = blocks
+                .iter()
+                .map(
+                    |block| match (block.stored_size, block.crc32, &block.link) {
+                        (Some(stored_size), Some(crc32), Some(link)) => json!({
+                            "id": block.block_id,
+                            "size": block.size,
+                            "stored_size": stored_size,
+                            "crc32": format!("{crc32:08x}"),
+                            "link": link,
+                        }),
+                        _ => json!({
+                            "id": null,
+                            "size": block.size,
+                            "stored_size": null,
+                            "crc32": null,
+                            "link": null,
+                        }),
+                    },
+                )
+                .collect();
+        }
+
+        println!("{json}");
+    }
+}
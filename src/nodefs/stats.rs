@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use indicatif::{HumanBytes, HumanCount, HumanDuration};
+
+use crate::{node::Node, util};
+
+use super::NodeFS;
+
+// lifetime statistics per data channel, kept in a local file and never sent anywhere
+//
+// opt in with 'DFS_STATS=on', the file holds one '<counter> <value>' line per counter
+impl NodeFS {
+    pub(super) fn stats_file(data_channel_id: u64) -> Option<PathBuf> {
+        if !matches!(std::env::var("DFS_STATS").as_deref(), Ok("on" | "1")) {
+            return None;
+        }
+
+        let dir = std::env::var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| {
+                std::env::var("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            })
+            .ok()?
+            .join("dfs");
+
+        Some(dir.join(format!("{data_channel_id}.stats")))
+    }
+
+    // counts the run, an operation that panics is counted as an error
+    pub fn track_operation(&self, operation: &str) {
+        let Some(path) = self.stats_file.clone() else {
+            return;
+        };
+        NodeFS::add_stats(&path, &[(format!("runs.{operation}"), 1)]);
+
+        let start = Node::now();
+        let started = Instant::now();
+        let _ = self.operation.set((operation.to_string(), start, started));
+
+        let operation = operation.to_string();
+        let transferred = self.transferred.clone();
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            NodeFS::add_stats(&path, &[(format!("errors.{operation}"), 1)]);
+            NodeFS::append_activity(
+                &NodeFS::activity_file(&path),
+                start,
+                &operation,
+                "error",
+                transferred.load(Ordering::Relaxed),
+                started.elapsed(),
+            );
+            hook(info);
+        }));
+    }
+
+    pub(super) fn record_transfer(&self, summary: &util::TransferSummary) {
+        let Some(path) = &self.stats_file else {
+            return;
+        };
+        self.transferred.fetch_add(summary.bytes, Ordering::Relaxed);
+
+        let direction = summary.operation.to_lowercase();
+        NodeFS::add_stats(
+            path,
+            &[
+                (format!("bytes.{direction}"), summary.bytes),
+                (
+                    format!("millis.{direction}"),
+                    summary.elapsed.as_millis() as u64,
+                ),
+            ],
+        );
+    }
+
+    pub(super) fn print_stats(&self) {
+        let Some(path) = &self.stats_file else {
+            println!("  Statistics are turned off, set DFS_STATS=on to collect them");
+            return;
+        };
+        let stats = NodeFS::read_stats(path);
+        let stat = |name: &str| stats.get(name).copied().unwrap_or(0);
+
+        println!("  Statistics        {}", path.display());
+        if let Some(since) = stats.get("since") {
+            println!(
+                "  Collected for     {}",
+                HumanDuration(Duration::from_secs(Node::now().saturating_sub(*since)))
+            );
+        }
+        for direction in ["uploaded", "downloaded"] {
+            let bytes = stat(&format!("bytes.{direction}"));
+            let millis = stat(&format!("millis.{direction}"));
+            println!(
+                "  {:<18}{} ({}/s on average)",
+                format!("Total {direction}"),
+                HumanBytes(bytes),
+                HumanBytes((bytes as f64 / (millis as f64 / 1000.0).max(f64::EPSILON)) as u64)
+            );
+        }
+
+        println!();
+        println!("  {:<18}{:>10}{:>10}", "Operation", "Runs", "Errors");
+        for (name, runs) in stats
+            .iter()
+            .filter_map(|(name, runs)| Some((name.strip_prefix("runs.")?, runs)))
+        {
+            println!(
+                "  {name:<18}{:>10}{:>10}",
+                HumanCount(*runs).to_string(),
+                HumanCount(stat(&format!("errors.{name}"))).to_string()
+            );
+        }
+    }
+
+    // in bytes per second, None without statistics or transfers in that direction
+    pub(super) fn average_throughput(&self, direction: &str) -> Option<f64> {
+        let stats = NodeFS::read_stats(self.stats_file.as_ref()?);
+        let bytes = *stats.get(&format!("bytes.{direction}"))?;
+        let millis = *stats.get(&format!("millis.{direction}"))?;
+
+        (bytes > 0 && millis > 0).then(|| bytes as f64 / (millis as f64 / 1000.0))
+    }
+
+    fn read_stats(path: &Path) -> BTreeMap<String, u64> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(' ')?;
+                Some((name.to_string(), value.parse().ok()?))
+            })
+            .collect()
+    }
+
+    // synchronous, it also runs from the panic hook; statistics are best effort
+    fn add_stats(path: &Path, updates: &[(String, u64)]) {
+        let mut stats = NodeFS::read_stats(path);
+        stats.entry(String::from("since")).or_insert(Node::now());
+        for (name, value) in updates {
+            *stats.entry(name.clone()).or_insert(0) += value;
+        }
+
+        let data: String = stats
+            .iter()
+            .map(|(name, value)| format!("{name} {value}\n"))
+            .collect();
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = std::fs::write(path, data);
+    }
+}
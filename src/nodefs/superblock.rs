@@ -0,0 +1,408 @@
+use serenity::all::{CreateMessage, EditMessage, MessageId, UserId};
+
+use crate::{
+    directory_entry::BlockIndex,
+    failure::{self, Hint},
+    node::Version,
+    node_codec, util,
+};
+
+use super::NodeFS;
+
+// the backup copy is a pinned message, so it can be found without knowing its ID
+const BACKUP_PREFIX: &str = "dfs superblock ";
+
+// superblocks of the filesystems named with --fs, followed by the name
+const NAMED_PREFIX: &str = "dfs filesystem ";
+
+// superblocks got their CRC while v5 was the newest format, so every later one has it
+const SEALED_SINCE: Version = 6;
+
+impl NodeFS {
+    // the superblock is '<root node id> v<format version> [flags] [codec:<node codec>]
+    // [ec:<erasure coding>] owner:<bot user id> [limit:<upload limit>] crc:<crc32>', the primary
+    // copy is the channel topic and a pinned message holds the backup
+    //
+    // filesystems named with --fs share the channel with the default one, the topic only has room
+    // for one superblock so theirs is only kept in a pinned 'dfs filesystem <name> ...' message
+    //
+    // returns false when there is no superblock at all, so a new filesystem is created
+    pub(super) async fn load_superblock(&mut self) -> bool {
+        let channel = util::get_guild_channel(&self.client, self.data_channel)
+            .await
+            .unwrap_or_else(|err| {
+                failure::fail(
+                    Hint::MissingChannel,
+                    format!(
+                        "Failed to open data channel {}: {}",
+                        self.data_channel,
+                        util::explain_error(&err)
+                    ),
+                )
+            });
+        self.guild_id = Some(channel.guild_id);
+        let topic = channel.topic.filter(|topic| !topic.trim().is_empty());
+
+        let (superblock, outdated) = match &self.namespace {
+            Some(name) => match self.find_named_superblock(name).await {
+                Some((_, sealed)) => {
+                    let superblock = NodeFS::unseal_superblock(&sealed).unwrap_or_else(|err| {
+                        panic!("The superblock of filesystem '{name}' {err}")
+                    });
+                    (superblock.to_string(), false)
+                }
+                None => return false,
+            },
+            None => match self.load_channel_superblock(topic).await {
+                Some(loaded) => loaded,
+                None => return false,
+            },
+        };
+
+        // without a version it's v0
+        let mut fields = superblock.split_whitespace();
+        self.root_node_id = fields
+            .next()
+            .and_then(|block_id| block_id.parse::<u64>().ok())
+            .expect("The root message ID should be in the superblock and be a valid u64");
+        self.format_version = fields.next().map_or(0, |version| {
+            version
+                .strip_prefix('v')
+                .and_then(|version| version.parse::<Version>().ok())
+                .expect("The format version in the superblock should be 'v' and a valid u32")
+        });
+        for flag in fields {
+            match flag {
+                "case-insensitive" => self.case_insensitive = true,
+                _ if flag.starts_with("owner:") => {
+                    self.owner =
+                        Some(UserId::new(flag["owner:".len()..].parse().expect(
+                            "The owner in the superblock should be a valid user ID",
+                        )))
+                }
+                _ if flag.starts_with("codec:") => {
+                    self.codec = node_codec::by_name(&flag["codec:".len()..]).unwrap_or_else(
+                        || panic!("Unknown node codec '{flag}' in the superblock, a newer version of dfs may be needed"),
+                    )
+                }
+                _ if flag.starts_with("ec:") => {
+                    self.erasure = Some(flag["ec:".len()..].parse().unwrap_or_else(|err| panic!("{err}")))
+                }
+                _ if flag.starts_with("limit:") => {
+                    self.recorded_upload_limit = Some(
+                        flag["limit:".len()..]
+                            .parse()
+                            .expect("The upload limit in the superblock should be a valid u64"),
+                    )
+                }
+                _ => panic!("Unknown filesystem flag '{flag}' in the superblock"),
+            }
+        }
+
+        // loading never writes, read-only commands leave the channel alone
+        self.superblock_outdated = outdated;
+
+        true
+    }
+
+    // for 'fsck --repair' and 'migrate', returns whether the superblock was saved again
+    pub(super) async fn repair_superblock(&self) -> bool {
+        if self.superblock_outdated {
+            self.save_superblock().await;
+        }
+
+        self.superblock_outdated
+    }
+
+    // the topic, or the backup if the topic is lost, as (superblock, whether it needs repairing)
+    async fn load_channel_superblock(&self, topic: Option<String>) -> Option<(String, bool)> {
+        match topic.as_deref().map(NodeFS::unseal_superblock) {
+            // superblocks from before the CRC get one and a backup when they are repaired
+            Some(Ok(superblock)) => {
+                Some((superblock.to_string(), !topic.unwrap().contains(" crc:")))
+            }
+            primary => match self.find_superblock_backup().await {
+                Some((_, backup)) => {
+                    let superblock = NodeFS::unseal_superblock(&backup).unwrap_or_else(|err| {
+                        panic!("Both copies of the superblock are corrupted, the backup {err}")
+                    });
+                    match primary {
+                        Some(Err(err)) => {
+                            println!(
+                                "  The superblock in the channel topic {err}, using the backup"
+                            )
+                        }
+                        _ => println!("  The channel topic is empty, using the superblock backup"),
+                    }
+                    Some((superblock.to_string(), true))
+                }
+                None => match primary {
+                    Some(Err(err)) => panic!(
+                        "The superblock in the channel topic {err} and there is no backup to restore it from"
+                    ),
+                    _ => None,
+                },
+            },
+        }
+    }
+
+    // writes both copies, the topic first since it's the one read on startup
+    pub(super) async fn save_superblock(&self) {
+        let mut superblock = format!("{} v{}", self.root_node_id, self.format_version);
+        if self.case_insensitive {
+            superblock.push_str(" case-insensitive");
+        }
+        if self.codec.name() != node_codec::DEFAULT.name() {
+            superblock.push_str(&format!(" codec:{}", self.codec.name()));
+        }
+        if let Some(erasure) = &self.erasure {
+            superblock.push_str(&format!(" ec:{erasure}"));
+        }
+        if let Some(owner) = self.owner {
+            superblock.push_str(&format!(" owner:{owner}"));
+        }
+        if let Some(limit) = self
+            .upload_limit
+            .get()
+            .or(self.recorded_upload_limit.as_ref())
+        {
+            superblock.push_str(&format!(" limit:{limit}"));
+        }
+        let sealed = format!(
+            "{superblock} crc:{:08x}",
+            util::crc32(superblock.as_bytes())
+        );
+
+        if let Some(name) = &self.namespace {
+            self.save_named_superblock(name, &sealed).await;
+            return;
+        }
+
+        util::edit_channel_topic(&self.client, self.data_channel, sealed.clone())
+            .await
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Failed to save the superblock in the channel topic: {} (it needs the Manage Channels permission)",
+                    util::explain_error(&err)
+                )
+            });
+
+        // the backup only protects against a lost topic, the filesystem works without it
+        let backup = format!("{BACKUP_PREFIX}{sealed}");
+        let existing = match self.try_find_superblock_backup().await {
+            Ok(existing) => existing,
+            Err(err) if util::is_missing_permission(&err) => {
+                println!(
+                    "  Not saving the superblock backup, pinned messages can't be read: {}",
+                    util::explain_error(&err)
+                );
+                return;
+            }
+            Err(err) => panic!("Failed to get pinned messages: {err}"),
+        };
+        match existing {
+            Some((message_id, _)) => util::edit_message(
+                &self.client,
+                self.data_channel,
+                message_id,
+                EditMessage::new().content(backup),
+            )
+            .await
+            .expect("Failed to save the superblock backup"),
+            None => {
+                let message_id = util::send_message(
+                    &self.client,
+                    self.data_channel,
+                    CreateMessage::new().content(backup),
+                )
+                .await
+                .expect("Failed to save the superblock backup");
+                match util::pin_message(&self.client, self.data_channel, message_id).await {
+                    Ok(_) => {}
+                    Err(err) if util::is_missing_permission(&err) => println!(
+                        "  The superblock backup couldn't be pinned, it needs the Pin Messages permission: {}",
+                        util::explain_error(&err)
+                    ),
+                    Err(err) => panic!("Failed to pin the superblock backup: {err}"),
+                }
+            }
+        }
+    }
+
+    // the pinned message is the only copy, so a filesystem that can't be pinned can't be found again
+    async fn save_named_superblock(&self, name: &str, sealed: &str) {
+        let content = format!("{NAMED_PREFIX}{name} {sealed}");
+        match self.find_named_superblock(name).await {
+            Some((message_id, _)) => util::edit_message(
+                &self.client,
+                self.data_channel,
+                message_id,
+                EditMessage::new().content(content),
+            )
+            .await
+            .expect("Failed to save the superblock"),
+            None => {
+                let message_id = util::send_message(
+                    &self.client,
+                    self.data_channel,
+                    CreateMessage::new().content(content),
+                )
+                .await
+                .expect("Failed to save the superblock");
+                util::pin_message(&self.client, self.data_channel, message_id)
+                    .await
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "Failed to pin the superblock of filesystem '{name}': {} (it needs the Pin Messages permission)",
+                            util::explain_error(&err)
+                        )
+                    });
+            }
+        }
+    }
+
+    pub(super) async fn find_named_superblock(&self, name: &str) -> Option<(MessageId, String)> {
+        self.named_superblocks()
+            .await
+            .into_iter()
+            .find(|(_, other, _)| other == name)
+            .map(|(message_id, _, sealed)| (message_id, sealed))
+    }
+
+    // every filesystem named with --fs in the channel as (message, name, sealed superblock)
+    pub(super) async fn named_superblocks(&self) -> Vec<(MessageId, String, String)> {
+        self.try_named_superblocks().await.unwrap_or_else(|err| {
+            panic!(
+                "Failed to get pinned messages: {}",
+                util::explain_error(&err)
+            )
+        })
+    }
+
+    pub(super) async fn try_named_superblocks(
+        &self,
+    ) -> serenity::Result<Vec<(MessageId, String, String)>> {
+        Ok(util::get_pins(&self.client, self.data_channel)
+            .await?
+            .into_iter()
+            .filter_map(|message| {
+                let (name, sealed) = message
+                    .content
+                    .strip_prefix(NAMED_PREFIX)?
+                    .split_once(' ')?;
+                Some((message.id, name.to_string(), sealed.to_string()))
+            })
+            .collect())
+    }
+
+    pub(super) async fn find_superblock_backup(&self) -> Option<(MessageId, String)> {
+        self.try_find_superblock_backup()
+            .await
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Failed to get pinned messages: {}",
+                    util::explain_error(&err)
+                )
+            })
+    }
+
+    pub(super) async fn try_find_superblock_backup(
+        &self,
+    ) -> serenity::Result<Option<(MessageId, String)>> {
+        Ok(util::get_pins(&self.client, self.data_channel)
+            .await?
+            .into_iter()
+            .find_map(|message| {
+                let backup = message.content.strip_prefix(BACKUP_PREFIX)?.to_string();
+                Some((message.id, backup))
+            }))
+    }
+
+    // root nodes of every filesystem in the channel and the pinned messages holding superblocks
+    // and the recovery note, walks over the whole channel would take the other filesystems for orphaned data otherwise
+    pub(super) async fn channel_filesystems(&self) -> (Vec<BlockIndex>, Vec<MessageId>) {
+        let root_of = |sealed: &str| {
+            NodeFS::unseal_superblock(sealed)
+                .ok()?
+                .split_whitespace()
+                .next()?
+                .parse::<BlockIndex>()
+                .ok()
+        };
+
+        let mut roots = vec![self.root_node_id];
+        let mut messages = Vec::new();
+        let topic = util::get_guild_channel(&self.client, self.data_channel)
+            .await
+            .ok()
+            .and_then(|channel| channel.topic)
+            .filter(|topic| !topic.trim().is_empty());
+        let backup = self.find_superblock_backup().await;
+        if topic.is_some() || backup.is_some() {
+            let root = topic
+                .as_deref()
+                .and_then(root_of)
+                .or_else(|| root_of(&backup.as_ref()?.1))
+                .expect("Both copies of the default filesystem's superblock are corrupted");
+            roots.push(root);
+        }
+        messages.extend(backup.map(|(message_id, _)| message_id));
+        messages.extend(self.try_find_recovery_note().await.unwrap_or_else(|err| {
+            panic!(
+                "Failed to get pinned messages: {}",
+                util::explain_error(&err)
+            )
+        }));
+
+        for (message_id, name, sealed) in self.named_superblocks().await {
+            let root = root_of(&sealed)
+                .unwrap_or_else(|| panic!("The superblock of filesystem '{name}' is corrupted"));
+            roots.push(root);
+            messages.push(message_id);
+        }
+
+        (roots, messages)
+    }
+
+    // checks the CRC and strips it, the error completes 'the superblock ...'
+    pub(super) fn unseal_superblock(sealed: &str) -> Result<&str, String> {
+        let Some((superblock, crc)) = sealed.rsplit_once(" crc:") else {
+            // a newer superblock without one was cut off or edited by hand, without a version
+            // it's v0
+            let version = sealed.split_whitespace().nth(1).map_or(Some(0), |version| {
+                version.strip_prefix('v')?.parse::<Version>().ok()
+            });
+            return match version {
+                Some(version) if version < SEALED_SINCE => Ok(sealed),
+                _ => Err(String::from("has no CRC")),
+            };
+        };
+        let crc =
+            u32::from_str_radix(crc, 16).map_err(|_| format!("has an invalid CRC '{crc}'"))?;
+        let actual = util::crc32(superblock.as_bytes());
+        if crc != actual {
+            return Err(format!(
+                "is corrupted (CRC is {actual:08x}, expected {crc:08x})"
+            ));
+        }
+
+        Ok(superblock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn superblocks_need_a_crc_since_it_was_introduced() {
+        assert_eq!(NodeFS::unseal_superblock("123 v5"), Ok("123 v5"));
+        assert_eq!(NodeFS::unseal_superblock("123"), Ok("123"));
+        assert!(NodeFS::unseal_superblock("123 v6").is_err());
+        assert!(NodeFS::unseal_superblock("123 v6 owner:1").is_err());
+
+        let sealed = format!("123 v6 crc:{:08x}", util::crc32(b"123 v6"));
+        assert_eq!(NodeFS::unseal_superblock(&sealed), Ok("123 v6"));
+        assert!(NodeFS::unseal_superblock(&sealed.replace("v6", "v7")).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use indicatif::{HumanBytes, ProgressBar};
+use serenity::all::PremiumTier;
+
+use crate::{
+    node::{self, Node, Size},
+    util,
+};
+
+use super::NodeFS;
+
+// every encrypted block grows by the AES-GCM-SIV authentication tag
+const AUTH_TAG_SIZE: Size = 16;
+
+impl NodeFS {
+    // the attachment size limit of the server, queried once per run before the first upload
+    //
+    // the superblock records the last known limit so a change is pointed out
+    pub(super) async fn upload_limit(&self) -> Size {
+        if let Some(limit) = self.upload_limit.get() {
+            return *limit;
+        }
+
+        let guild_id = self
+            .guild_id
+            .expect("The data channel's server should be known after setup");
+        let limit = match util::get_guild(&self.client, guild_id)
+            .await
+            .expect("Failed to get the server of the data channel")
+            .premium_tier
+        {
+            PremiumTier::Tier2 => 50 << 20,
+            PremiumTier::Tier3 => 100 << 20,
+            _ => 10 << 20,
+        };
+        let _ = self.upload_limit.set(limit);
+
+        if self.recorded_upload_limit != Some(limit) {
+            if let Some(recorded) = self.recorded_upload_limit {
+                println!(
+                    "  The server's upload limit changed from {} to {}",
+                    HumanBytes(recorded),
+                    HumanBytes(limit)
+                );
+            }
+            self.save_superblock().await;
+        }
+
+        limit
+    }
+
+    // new files adapt their chunk size to a lower limit, existing blocks can't be changed
+    pub(super) async fn fit_chunk_size(&self, file_node: &mut Node, progress_bar: &ProgressBar) {
+        let limit = self.upload_limit().await;
+        if file_node.chunk_size + AUTH_TAG_SIZE <= limit {
+            return;
+        }
+
+        assert!(
+            file_node.blocks().is_empty(),
+            "The file's chunk size of {} exceeds the server's upload limit of {}, it can't be extended",
+            HumanBytes(file_node.chunk_size),
+            HumanBytes(limit)
+        );
+        assert!(
+            self.format_version >= 6,
+            "The server's upload limit of {} is below the block size, run 'migrate --to v6' to upload with smaller chunks",
+            HumanBytes(limit)
+        );
+
+        let chunk_size =
+            (limit - AUTH_TAG_SIZE) / node::MIN_CHUNK_SIZE as Size * node::MIN_CHUNK_SIZE as Size;
+        assert!(
+            chunk_size >= node::MIN_CHUNK_SIZE as Size,
+            "The server's upload limit of {} is too small",
+            HumanBytes(limit)
+        );
+
+        progress_bar.println(format!(
+            "  Uploading in chunks of {} to stay below the server's upload limit of {}",
+            HumanBytes(chunk_size),
+            HumanBytes(limit)
+        ));
+        file_node.chunk_size = chunk_size;
+    }
+
+    // spooled blocks were encrypted for the limit at the time
+    pub(super) async fn check_upload_limit(&self, block_size: Size) {
+        let limit = self.upload_limit().await;
+        assert!(
+            block_size <= limit,
+            "The block of {} exceeds the server's upload limit of {}",
+            HumanBytes(block_size),
+            HumanBytes(limit)
+        );
+    }
+}
@@ -7,6 +7,11 @@ impl NonceCounter {
         NonceCounter(0)
     }
 
+    // the nth data block of a file is encrypted with the nth nonce
+    pub fn starting_at(index: u64) -> Self {
+        NonceCounter(index)
+    }
+
     pub fn get_nonce(&mut self) -> Nonce {
         let mut data = [0; 12];
         data[..4].copy_from_slice(&0u32.to_le_bytes());
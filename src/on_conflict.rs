@@ -0,0 +1,27 @@
+use clap::ValueEnum;
+
+// what to do when the destination name is already taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    Fail,
+    Skip,
+    Overwrite,
+    // continue a partial transfer, the existing data is trusted to match the source
+    Resume,
+    Rename,
+}
+
+impl OnConflict {
+    // 'photo.jpg' becomes 'photo (1).jpg', directories keep their trailing '/'
+    pub fn numbered_name(name: &str, n: usize) -> String {
+        if let Some(dir_name) = name.strip_suffix('/') {
+            return format!("{dir_name} ({n})/");
+        }
+
+        // a leading dot is part of the name, not an extension
+        match name.rfind('.').filter(|pos| *pos > 0) {
+            Some(pos) => format!("{} ({n}){}", &name[..pos], &name[pos..]),
+            None => format!("{name} ({n})"),
+        }
+    }
+}
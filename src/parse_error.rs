@@ -0,0 +1,48 @@
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    // position in the parsed data where the error was detected
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(offset: usize, message: S) -> Self {
+        ParseError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    // shifts the offset of an error from a nested parser into the outer data
+    pub fn at(self, offset: usize) -> Self {
+        ParseError {
+            offset: self.offset + offset,
+            ..self
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at byte {})", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn read_u64(bytes: &[u8], offset: usize, field: &str) -> Result<u64, ParseError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| {
+            ParseError::new(
+                offset,
+                format!(
+                    "Truncated {field}: expected 8 bytes, got {}",
+                    bytes.len().saturating_sub(offset)
+                ),
+            )
+        })
+}
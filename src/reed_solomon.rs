@@ -0,0 +1,211 @@
+// systematic Reed-Solomon over GF(2^8), the first `data` shards are the block split into equal
+// parts, the parity shards are rows of a Cauchy matrix over them, so any `data` of the shards
+// rebuild the block
+
+// x^8 + x^4 + x^3 + x^2 + 1
+const POLYNOMIAL: u16 = 0x11d;
+
+// shard indices are field elements, so a stripe can't have more shards
+pub const MAX_SHARDS: usize = 256;
+
+// (log, exp) of every element, exp is doubled so sums of logs need no modulo
+static TABLES: ([u8; 256], [u8; 510]) = tables();
+
+const fn tables() -> ([u8; 256], [u8; 510]) {
+    let mut log = [0; 256];
+    let mut exp = [0; 510];
+    let mut value: u16 = 1;
+    let mut power = 0;
+    while power < 255 {
+        exp[power] = value as u8;
+        exp[power + 255] = value as u8;
+        log[value as usize] = power as u8;
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= POLYNOMIAL;
+        }
+        power += 1;
+    }
+
+    (log, exp)
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (log, exp) = &TABLES;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+fn inv(a: u8) -> u8 {
+    assert!(a != 0, "Zero has no inverse");
+    let (log, exp) = &TABLES;
+    exp[255 - log[a as usize] as usize]
+}
+
+// coefficients of shard `idx` over the data shards, the Cauchy rows 1 / (idx ^ j) stay
+// invertible with any choice of rows
+fn row(idx: usize, data: usize) -> Vec<u8> {
+    (0..data)
+        .map(|j| {
+            if idx < data {
+                (idx == j) as u8
+            } else {
+                inv((idx ^ j) as u8)
+            }
+        })
+        .collect()
+}
+
+// output ^= coefficient * input, a table per coefficient keeps it to a lookup per byte
+fn mul_add(output: &mut [u8], input: &[u8], coefficient: u8) {
+    let table: [u8; 256] = std::array::from_fn(|x| mul(coefficient, x as u8));
+    for (output, input) in output.iter_mut().zip(input) {
+        *output ^= table[*input as usize];
+    }
+}
+
+// splits the block into `data` shards padded with zeros and appends `parity` shards
+pub fn encode(block: &[u8], data: usize, parity: usize) -> Vec<Vec<u8>> {
+    assert!(
+        data > 0 && data + parity <= MAX_SHARDS,
+        "Invalid stripe of {data} data and {parity} parity shards"
+    );
+    let shard_size = block.len().div_ceil(data);
+    let mut shards: Vec<Vec<u8>> = (0..data)
+        .map(|idx| {
+            let mut shard = block
+                .get(idx * shard_size..)
+                .map_or(&[][..], |rest| &rest[..shard_size.min(rest.len())])
+                .to_vec();
+            shard.resize(shard_size, 0);
+            shard
+        })
+        .collect();
+
+    for idx in data..data + parity {
+        let mut shard = vec![0; shard_size];
+        for (j, coefficient) in row(idx, data).into_iter().enumerate() {
+            mul_add(&mut shard, &shards[j], coefficient);
+        }
+        shards.push(shard);
+    }
+
+    shards
+}
+
+// rebuilds the `len` bytes of a block from the shards that are left, missing ones are None
+pub fn decode(shards: &[Option<Vec<u8>>], data: usize, len: usize) -> Result<Vec<u8>, String> {
+    let available: Vec<usize> = shards
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, shard)| shard.as_ref().map(|_| idx))
+        .take(data)
+        .collect();
+    if available.len() < data {
+        return Err(format!(
+            "only {} of the {data} shards needed are left",
+            available.len()
+        ));
+    }
+    let shard_size = shards[available[0]].as_ref().unwrap().len();
+    if available
+        .iter()
+        .any(|idx| shards[*idx].as_ref().unwrap().len() != shard_size)
+    {
+        return Err(String::from("the shards have different sizes"));
+    }
+
+    // the data shards are the block itself while they're all there
+    let mut block = Vec::with_capacity(shard_size * data);
+    if available.iter().enumerate().all(|(j, idx)| j == *idx) {
+        for idx in available {
+            block.extend_from_slice(shards[idx].as_ref().unwrap());
+        }
+        block.truncate(len);
+        return Ok(block);
+    }
+
+    // invert the rows of the available shards with Gauss-Jordan elimination
+    let mut matrix: Vec<Vec<u8>> = available.iter().map(|idx| row(*idx, data)).collect();
+    let mut inverse: Vec<Vec<u8>> = (0..data).map(|idx| row(idx, data)).collect();
+    for column in 0..data {
+        let pivot = (column..data)
+            .find(|r| matrix[*r][column] != 0)
+            .ok_or_else(|| String::from("the shards don't form an invertible matrix"))?;
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = inv(matrix[column][column]);
+        for value in matrix[column].iter_mut().chain(inverse[column].iter_mut()) {
+            *value = mul(*value, scale);
+        }
+        for r in (0..data).filter(|r| *r != column) {
+            let factor = matrix[r][column];
+            if factor == 0 {
+                continue;
+            }
+            for c in 0..data {
+                let (pivot_value, pivot_inverse) = (matrix[column][c], inverse[column][c]);
+                matrix[r][c] ^= mul(factor, pivot_value);
+                inverse[r][c] ^= mul(factor, pivot_inverse);
+            }
+        }
+    }
+
+    for coefficients in inverse {
+        let mut shard = vec![0; shard_size];
+        for (r, coefficient) in coefficients.into_iter().enumerate() {
+            mul_add(
+                &mut shard,
+                shards[available[r]].as_ref().unwrap(),
+                coefficient,
+            );
+        }
+        block.extend_from_slice(&shard);
+    }
+    block.truncate(len);
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_data_shards_rebuild_the_block() {
+        let block: Vec<u8> = (0..1000u32).map(|x| (x * 7 + x / 13) as u8).collect();
+        let (data, parity) = (4, 3);
+        let shards = encode(&block, data, parity);
+        assert_eq!(shards.len(), data + parity);
+
+        // every way of losing `parity` shards
+        for lost in 0..1u32 << (data + parity) {
+            if lost.count_ones() as usize != parity {
+                continue;
+            }
+            let left: Vec<Option<Vec<u8>>> = shards
+                .iter()
+                .enumerate()
+                .map(|(idx, shard)| (lost & (1 << idx) == 0).then(|| shard.clone()))
+                .collect();
+            assert_eq!(
+                decode(&left, data, block.len()),
+                Ok(block.clone()),
+                "{lost:b}"
+            );
+        }
+    }
+
+    #[test]
+    fn too_few_shards_are_an_error() {
+        let block = b"short block".to_vec();
+        let mut shards: Vec<Option<Vec<u8>>> = encode(&block, 3, 1).into_iter().map(Some).collect();
+        shards[0] = None;
+        assert_eq!(decode(&shards, 3, block.len()), Ok(block.clone()));
+        shards[2] = None;
+        assert!(decode(&shards, 3, block.len()).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::{Read, Write};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    runtime::Handle,
+};
+
+// blocking adapter around an async stream, for use inside `spawn_blocking`
+pub struct SyncIo<T> {
+    inner: T,
+    handle: Handle,
+}
+
+impl<T> SyncIo<T> {
+    pub fn new(inner: T) -> Self {
+        SyncIo {
+            inner,
+            handle: Handle::current(),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> Read for SyncIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.handle.block_on(self.inner.read(buf))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for SyncIo<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.handle.block_on(self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.handle.block_on(self.inner.flush())
+    }
+}
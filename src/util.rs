@@ -1,15 +1,26 @@
-use std::time::Duration;
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, HumanCount, HumanDuration, ProgressBar, ProgressStyle};
+use regex::{Regex, RegexBuilder};
 use serenity::{
     Client,
-    all::{ChannelId, CreateMessage, EditChannel, EditMessage, GuildChannel, MessageId},
+    all::{
+        Attachment, ChannelId, CreateMessage, EditChannel, EditMessage, GetMessages, GuildChannel,
+        GuildId, Member, Message, MessageId, PartialGuild, UserId,
+    },
+    http::HttpError,
+    json::json,
 };
 
 pub fn progress_bar(limit: u64) -> ProgressBar {
     let bar = ProgressBar::new(limit).with_style(
         ProgressStyle::with_template(
-            "  [{elapsed}] {wide_bar} [{binary_bytes}/{binary_total_bytes} - {percent}%]  ",
+            "  [{elapsed}] {wide_bar} [{binary_bytes}/{binary_total_bytes} - {percent}%] {binary_bytes_per_sec}, {eta} left  ",
         )
         .unwrap()
         .progress_chars("##-"),
@@ -19,6 +30,62 @@ pub fn progress_bar(limit: u64) -> ProgressBar {
     bar
 }
 
+// totals of a finished transfer
+pub struct TransferSummary {
+    pub operation: &'static str,
+    pub bytes: u64,
+    pub blocks: usize,
+    pub elapsed: Duration,
+}
+
+impl TransferSummary {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    // milliseconds and whole bytes are precise enough
+    pub fn to_json(&self) -> String {
+        json!({
+            "operation": self.operation.to_lowercase(),
+            "bytes": self.bytes,
+            "blocks": self.blocks,
+            "elapsed_secs": self.elapsed.as_millis() as f64 / 1000.0,
+            "bytes_per_sec": self.bytes_per_sec().round() as u64,
+            "api_calls": rest_api_calls(),
+            "retries": block_retries(),
+        })
+        .to_string()
+    }
+}
+
+impl Display for TransferSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} in {} blocks, took {} ({}/s)",
+            self.operation,
+            HumanBytes(self.bytes),
+            HumanCount(self.blocks as u64),
+            HumanDuration(self.elapsed),
+            HumanBytes(self.bytes_per_sec() as u64)
+        )?;
+        match block_retries() {
+            0 => Ok(()),
+            retries => write!(f, ", retried {} block downloads", HumanCount(retries)),
+        }
+    }
+}
+
+pub fn stream_progress() -> ProgressBar {
+    let spinner = ProgressBar::new_spinner().with_style(
+        ProgressStyle::with_template("  [{elapsed}] {binary_bytes} ({binary_bytes_per_sec})  ")
+            .unwrap(),
+    );
+    spinner.enable_steady_tick(Duration::from_millis(100));
+
+    spinner
+}
+
 pub fn spinner() -> ProgressBar {
     let spinner = ProgressBar::new_spinner()
         .with_style(ProgressStyle::with_template("  {msg} {spinner}  ").unwrap());
@@ -37,10 +104,347 @@ pub fn file_delete_progress(limit: u64) -> ProgressBar {
     spinner
 }
 
+// local paths come from the user's shell, on Windows they can use either separator and start
+// with a drive letter ('C:\Users\me\photo.jpg', 'C:photo.jpg')
+pub fn local_file_name(path: &str) -> Option<String> {
+    file_name_of(path, cfg!(windows))
+}
+
+fn file_name_of(path: &str, windows: bool) -> Option<String> {
+    let is_separator = |ch: char| ch == '/' || (windows && ch == '\\');
+    let path = path.trim_end_matches(is_separator);
+    let mut name = &path[path.rfind(is_separator).map_or(0, |pos| pos + 1)..];
+    if windows && name.as_bytes().get(1) == Some(&b':') && name.as_bytes()[0].is_ascii_alphabetic()
+    {
+        name = &name[2..];
+    }
+
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+// joins a relative remote path onto a local directory, one segment at a time so the platform's
+// separator is used
+pub fn local_join(base: &str, relative: &str) -> PathBuf {
+    relative
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(PathBuf::from(base), |path, segment| path.join(segment))
+}
+
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let unit_pos = size
+        .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(unit_pos);
+
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid size '{size}', expected a number with a unit like 8MiB"))?;
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "Unknown size unit '{unit}', expected B, KB, MB, GB, TB, KiB, MiB, GiB or TiB"
+            ));
+        }
+    };
+
+    // the cast would silently saturate
+    let bytes = value * factor as f64;
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("Size '{size}' is too large"));
+    }
+
+    Ok(bytes as u64)
+}
+
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let unit_pos = duration
+        .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(unit_pos);
+
+    let value = value.parse::<f64>().map_err(|_| {
+        format!("Invalid duration '{duration}', expected a number with a unit like 30d")
+    })?;
+    let factor: u64 = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Unknown duration unit '{unit}', expected s, m, h, d or w"
+            ));
+        }
+    };
+
+    Duration::try_from_secs_f64(value * factor as f64)
+        .map_err(|_| format!("Duration '{duration}' is too long"))
+}
+
+// sed like 's/<regex>/<replacement>/[flags]', the replacement refers to groups with '$1'
+#[derive(Clone)]
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+
+    // replace every match instead of only the first one
+    global: bool,
+}
+
+impl Substitution {
+    pub fn apply(&self, text: &str) -> String {
+        if self.global {
+            self.regex.replace_all(text, &self.replacement).into_owned()
+        } else {
+            self.regex.replace(text, &self.replacement).into_owned()
+        }
+    }
+}
+
+pub fn parse_substitution(substitution: &str) -> Result<Substitution, String> {
+    const USAGE: &str = "Substitution must look like 's/<regex>/<replacement>/[flags]'";
+
+    let mut chars = substitution.chars();
+    if chars.next() != Some('s') {
+        return Err(USAGE.to_string());
+    }
+    let delimiter = chars.next().ok_or(USAGE)?;
+
+    // an escaped delimiter is part of the regex or replacement, other escapes are kept for the regex
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for ch in chars {
+        let part = parts.last_mut().unwrap();
+        if escaped {
+            if ch != delimiter {
+                part.push('\\');
+            }
+            part.push(ch);
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == delimiter {
+            parts.push(String::new());
+        } else {
+            part.push(ch);
+        }
+    }
+    if escaped || parts.len() != 3 {
+        return Err(USAGE.to_string());
+    }
+
+    let mut global = false;
+    let mut case_insensitive = false;
+    for flag in parts[2].chars() {
+        match flag {
+            'g' => global = true,
+            'i' => case_insensitive = true,
+            _ => {
+                return Err(format!(
+                    "Unknown substitution flag '{flag}', expected g or i"
+                ));
+            }
+        }
+    }
+
+    let regex = RegexBuilder::new(&parts[0])
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| format!("Invalid regex: {err}"))?;
+
+    Ok(Substitution {
+        regex,
+        replacement: parts.swap_remove(1),
+        global,
+    })
+}
+
+// names of filesystems sharing a channel, they are stored in a message separated by a space
+pub fn parse_fs_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.len() > 32 {
+        return Err(String::from(
+            "Filesystem names must have 1 to 32 characters",
+        ));
+    }
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+    {
+        return Err(format!(
+            "Invalid filesystem name '{name}', only letters, digits, '-' and '_' are allowed"
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+// shell like '*', '?' and '[...]' patterns, they match the whole name
+pub fn parse_glob(glob: &str) -> Result<Regex, String> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let rest = chars.as_str();
+                let class = match rest.find(']') {
+                    Some(end) if end > 0 => rest[..end].to_string(),
+                    _ => return Err(format!("Glob '{glob}' has an empty or unclosed '['")),
+                };
+                chars = rest[class.len() + 1..].chars();
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => format!("^{negated}"),
+                    None => class,
+                };
+                regex.push_str(&format!("[{}]", class.replace('\\', "\\\\")));
+            }
+            _ => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|err| format!("Invalid glob '{glob}': {err}"))
+}
+
+// CRC-32 (IEEE), as used by zip and PNG
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+// Discord requests made by this process, an invocation runs a single operation
+#[derive(Clone, Copy)]
+pub enum ApiCall {
+    GetChannel = 0,
+    GetGuild,
+    GetCurrentUser,
+    GetMember,
+    EditChannel,
+    SendMessage,
+    EditMessage,
+    DeleteMessage,
+    GetMessage,
+    GetMessages,
+    GetPins,
+    PinMessage,
+    // attachments are downloaded from the CDN which has no REST rate limit
+    Attachment,
+}
+
+const API_CALL_NAMES: [&str; 13] = [
+    "get channel",
+    "get guild",
+    "get current user",
+    "get member",
+    "edit channel",
+    "send message",
+    "edit message",
+    "delete message",
+    "get message",
+    "get messages",
+    "get pins",
+    "pin message",
+    "attachment",
+];
+
+static API_CALLS: [AtomicU64; 13] = [const { AtomicU64::new(0) }; 13];
+
+fn count_api_call(call: ApiCall) {
+    API_CALLS[call as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+// block downloads that failed and were tried again, the retries are counted as API calls too
+static BLOCK_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+pub fn count_block_retry() {
+    BLOCK_RETRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn block_retries() -> u64 {
+    BLOCK_RETRIES.load(Ordering::Relaxed)
+}
+
+// REST calls only, attachment downloads don't count towards the rate limits
+pub fn rest_api_calls() -> u64 {
+    API_CALLS[..ApiCall::Attachment as usize]
+        .iter()
+        .map(|calls| calls.load(Ordering::Relaxed))
+        .sum()
+}
+
+pub fn api_call_counts() -> Vec<(&'static str, u64)> {
+    API_CALL_NAMES
+        .iter()
+        .zip(&API_CALLS)
+        .map(|(name, calls)| (*name, calls.load(Ordering::Relaxed)))
+        .filter(|(_, calls)| *calls > 0)
+        .collect()
+}
+
+// Discord's answer to a failed request as (HTTP status, JSON error code)
+fn discord_error(err: &serenity::Error) -> Option<(u16, isize)> {
+    match err {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            Some((response.status_code.as_u16(), response.error.code))
+        }
+        _ => None,
+    }
+}
+
+// 'Missing Access' and 'Missing Permissions', operations that can do without the request
+// continue with a warning
+pub fn is_missing_permission(err: &serenity::Error) -> bool {
+    matches!(discord_error(err), Some((_, 50001 | 50013)))
+}
+
+// 'Unknown Message', the message was deleted
+pub fn is_unknown_message(err: &serenity::Error) -> bool {
+    matches!(discord_error(err), Some((_, 10008)))
+}
+
+// names the cause of the errors that come from a misconfigured bot or channel
+pub fn explain_error(err: &serenity::Error) -> String {
+    match discord_error(err) {
+        Some((_, 50001)) => String::from(
+            "the bot can't access the data channel, it needs the View Channel and Read Message History permissions there",
+        ),
+        Some((_, 50013)) => format!("the bot is missing a permission in the data channel ({err})"),
+        Some((_, 10003)) => String::from("the data channel doesn't exist, check DATA_CHANNEL_ID"),
+        Some((401, _)) => String::from("the bot token was rejected, check BOT_TOKEN"),
+        _ => err.to_string(),
+    }
+}
+
 pub async fn get_guild_channel(
     client: &Client,
     channel_id: ChannelId,
 ) -> serenity::Result<GuildChannel> {
+    count_api_call(ApiCall::GetChannel);
     channel_id
         .to_channel(&client.http)
         .await?
@@ -48,11 +452,31 @@ pub async fn get_guild_channel(
         .ok_or(serenity::Error::Other("Failed to get guild channel"))
 }
 
+pub async fn get_guild(client: &Client, guild_id: GuildId) -> serenity::Result<PartialGuild> {
+    count_api_call(ApiCall::GetGuild);
+    guild_id.to_partial_guild(&client.http).await
+}
+
+pub async fn get_current_user(client: &Client) -> serenity::Result<UserId> {
+    count_api_call(ApiCall::GetCurrentUser);
+    Ok(client.http.get_current_user().await?.id)
+}
+
+pub async fn get_member(
+    client: &Client,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> serenity::Result<Member> {
+    count_api_call(ApiCall::GetMember);
+    guild_id.member(&client.http, user_id).await
+}
+
 pub async fn send_message(
     client: &Client,
     channel_id: ChannelId,
     message: CreateMessage,
 ) -> serenity::Result<MessageId> {
+    count_api_call(ApiCall::SendMessage);
     Ok(channel_id.send_message(&client.http, message).await?.id)
 }
 
@@ -62,6 +486,7 @@ pub async fn edit_message(
     message_id: MessageId,
     message: EditMessage,
 ) -> serenity::Result<()> {
+    count_api_call(ApiCall::EditMessage);
     channel_id
         .edit_message(&client.http, message_id, message)
         .await?;
@@ -74,6 +499,7 @@ pub async fn delete_message(
     channel_id: ChannelId,
     message_id: MessageId,
 ) -> serenity::Result<()> {
+    count_api_call(ApiCall::DeleteMessage);
     channel_id.delete_message(&client.http, message_id).await
 }
 
@@ -82,36 +508,179 @@ pub async fn edit_channel_topic(
     channel_id: ChannelId,
     topic: String,
 ) -> serenity::Result<GuildChannel> {
+    count_api_call(ApiCall::EditChannel);
     channel_id
         .edit(&client.http, EditChannel::new().topic(topic))
         .await
 }
 
-pub async fn read_attachment(
-    client: &Client,
-    channel_id: ChannelId,
-    message_id: MessageId,
-) -> serenity::Result<Vec<u8>> {
+pub async fn message_exists(client: &Client, channel_id: ChannelId, message_id: MessageId) -> bool {
+    count_api_call(ApiCall::GetMessage);
     client
         .http
         .get_message(channel_id, message_id)
         .await
-        .unwrap_or_else(|e| {
-            panic!(
-                "Failed to get message '{}' from channel '{}': {e}",
-                message_id.get(),
-                channel_id.get()
-            )
-        })
-        .attachments
-        .first()
-        .unwrap_or_else(|| {
-            panic!(
-                "Message '{}' from channel '{}' should contain an attachment of block data",
-                message_id.get(),
-                channel_id.get()
+        .is_ok()
+}
+
+pub async fn get_message(
+    client: &Client,
+    channel_id: ChannelId,
+    message_id: MessageId,
+) -> serenity::Result<Message> {
+    count_api_call(ApiCall::GetMessage);
+    client.http.get_message(channel_id, message_id).await
+}
+
+// walks the history of a channel oldest first, 100 messages per request
+pub struct ChannelScanner {
+    channel_id: ChannelId,
+    after: MessageId,
+}
+
+impl ChannelScanner {
+    pub fn new(channel_id: ChannelId) -> Self {
+        ChannelScanner {
+            channel_id,
+            after: MessageId::new(1),
+        }
+    }
+
+    // None once the whole history was scanned
+    pub async fn next_page(&mut self, client: &Client) -> serenity::Result<Option<Vec<Message>>> {
+        count_api_call(ApiCall::GetMessages);
+        let mut messages = self
+            .channel_id
+            .messages(
+                &client.http,
+                GetMessages::new().after(self.after).limit(100),
             )
-        })
-        .download()
-        .await
+            .await?;
+        if messages.is_empty() {
+            return Ok(None);
+        }
+
+        // pages come newest first
+        messages.sort_by_key(|message| message.id);
+        self.after = messages.last().unwrap().id;
+
+        Ok(Some(messages))
+    }
+}
+
+pub async fn get_pins(client: &Client, channel_id: ChannelId) -> serenity::Result<Vec<Message>> {
+    count_api_call(ApiCall::GetPins);
+    channel_id.pins(&client.http).await
+}
+
+pub async fn pin_message(
+    client: &Client,
+    channel_id: ChannelId,
+    message_id: MessageId,
+) -> serenity::Result<()> {
+    count_api_call(ApiCall::PinMessage);
+    channel_id.pin(&client.http, message_id).await
+}
+
+pub async fn download_attachment(attachment: &Attachment) -> serenity::Result<Vec<u8>> {
+    count_api_call(ApiCall::Attachment);
+    attachment.download().await
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::json::{self, Value};
+
+    use super::*;
+
+    #[test]
+    fn transfer_summaries_parse_as_json() {
+        let summary = TransferSummary {
+            operation: "Uploaded",
+            bytes: 3 << 20,
+            blocks: 2,
+            elapsed: Duration::from_millis(1500),
+        };
+        let parsed: Value = json::from_str(summary.to_json()).unwrap();
+        assert_eq!(parsed["operation"], "uploaded");
+        assert_eq!(parsed["bytes"], 3 << 20);
+        assert_eq!(parsed["blocks"], 2);
+        assert_eq!(parsed["elapsed_secs"], 1.5);
+        assert_eq!(parsed["bytes_per_sec"], 2 << 20);
+        assert!(parsed["api_calls"].is_u64());
+        assert!(parsed["retries"].is_u64());
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        let glob = parse_glob("*.tar.[gx]z").unwrap();
+        assert!(glob.is_match("backup.tar.gz"));
+        assert!(glob.is_match("backup.tar.xz"));
+        assert!(!glob.is_match("backup.tar.zst"));
+        assert!(!glob.is_match("backup.tar.gz.part"));
+        assert!(parse_glob("photo-??.jpg").unwrap().is_match("photo-01.jpg"));
+        assert!(!parse_glob("[!a]*").unwrap().is_match("abc"));
+        assert!(parse_glob("[abc").is_err());
+    }
+
+    #[test]
+    fn finds_windows_file_names() {
+        let name = |path| file_name_of(path, true);
+        assert_eq!(
+            name("C:\\Users\\me\\photo.jpg").as_deref(),
+            Some("photo.jpg")
+        );
+        assert_eq!(name("C:/Users/me/photo.jpg").as_deref(), Some("photo.jpg"));
+        assert_eq!(name("docs\\2024\\").as_deref(), Some("2024"));
+        assert_eq!(name("C:photo.jpg").as_deref(), Some("photo.jpg"));
+        assert_eq!(name("\\\\server\\share\\a.txt").as_deref(), Some("a.txt"));
+        assert_eq!(name("C:\\"), None);
+        assert_eq!(name("..\\"), None);
+    }
+
+    #[test]
+    fn keeps_backslashes_in_unix_file_names() {
+        let name = |path| file_name_of(path, false);
+        assert_eq!(name("dir/a\\b.txt").as_deref(), Some("a\\b.txt"));
+        assert_eq!(name("/home/me/docs/").as_deref(), Some("docs"));
+        assert_eq!(name("/"), None);
+    }
+
+    #[test]
+    fn joins_remote_paths_segment_by_segment() {
+        assert_eq!(
+            local_join("restore", "photos/2024/a.jpg"),
+            PathBuf::from("restore")
+                .join("photos")
+                .join("2024")
+                .join("a.jpg")
+        );
+        assert_eq!(
+            local_join("restore", "photos/"),
+            PathBuf::from("restore").join("photos")
+        );
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("8MiB"), Ok(8 << 20));
+        assert_eq!(parse_size("1.5GB"), Ok(1_500_000_000));
+        assert_eq!(parse_size(" 512 kib "), Ok(512 << 10));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("8 parsecs").is_err());
+        assert!(parse_size("-1MiB").is_err());
+        assert!(parse_size("99999999TiB").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("45m"), Ok(Duration::from_secs(45 * 60)));
+        assert_eq!(
+            parse_duration("7d"),
+            Ok(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(90 * 60)));
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("1e400s").is_err());
+    }
 }
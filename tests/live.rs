@@ -0,0 +1,245 @@
+// end to end tests against a real Discord channel, run them with
+//
+//   DFS_TEST_BOT_TOKEN=... DFS_TEST_CHANNEL_ID=... cargo test --features live-tests
+//
+// the channel should be a dedicated sandbox, the tests create a filesystem in it if there is none,
+// 'DFS_TEST_AES_KEY' is needed if the sandbox already holds one with another key
+#![cfg(feature = "live-tests")]
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// every test edits the root directory, so they must not run at the same time
+static CHANNEL: Mutex<()> = Mutex::new(());
+
+// default key, the sandbox channel never holds real data
+const AES_KEY: &str = "live-test-key-live-test-key-live";
+
+struct Sandbox {
+    dir: PathBuf,
+    // unique remote directory of the test, removed again at the end
+    remote: String,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Self {
+        let token = std::env::var("DFS_TEST_BOT_TOKEN")
+            .expect("Live tests require a bot token in 'DFS_TEST_BOT_TOKEN'");
+        let channel = std::env::var("DFS_TEST_CHANNEL_ID")
+            .expect("Live tests require a sandbox channel ID in 'DFS_TEST_CHANNEL_ID'");
+        let key = std::env::var("DFS_TEST_AES_KEY").unwrap_or(AES_KEY.to_string());
+
+        let unique = format!(
+            "{name}-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
+        let dir = std::env::temp_dir().join(format!("dfs-live-{unique}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(".env"),
+            format!(
+                "BOT_TOKEN={token}\nDATA_CHANNEL_ID={channel}\nAES_KEY={key}\nDFS_CACHE_DIR=\n"
+            ),
+        )
+        .unwrap();
+
+        let sandbox = Sandbox {
+            dir,
+            remote: format!("/live-{unique}/"),
+        };
+        sandbox.dfs(&["mkdir", &sandbox.remote]);
+
+        sandbox
+    }
+
+    // runs dfs in the sandbox directory and expects it to succeed
+    fn dfs(&self, args: &[&str]) -> Output {
+        let output = self.try_dfs(args);
+        assert!(
+            output.status.success(),
+            "dfs {} failed:\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        output
+    }
+
+    fn try_dfs(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_dfs"))
+            .args(args)
+            .current_dir(&self.dir)
+            .output()
+            .expect("Failed to run dfs")
+    }
+
+    fn local(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn remote(&self, name: &str) -> String {
+        format!("{}{name}", self.remote)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = self.try_dfs(&["rm", "-r", &self.remote]);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// deterministic data that isn't all zeros, so no block becomes a hole
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|idx| (idx * 31 % 251) as u8 + 1).collect()
+}
+
+fn assert_same_file(a: &Path, b: &Path) {
+    assert!(
+        std::fs::read(a).unwrap() == std::fs::read(b).unwrap(),
+        "{} and {} differ",
+        a.display(),
+        b.display()
+    );
+}
+
+#[test]
+fn upload_download_round_trip() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|err| err.into_inner());
+    let sandbox = Sandbox::new("round-trip");
+
+    // more than one block with a partial last one
+    std::fs::write(sandbox.local("big.bin"), test_data((8 << 20) + 12345)).unwrap();
+    sandbox.dfs(&[
+        "upload",
+        sandbox.local("big.bin").to_str().unwrap(),
+        &sandbox.remote("big.bin"),
+    ]);
+    sandbox.dfs(&[
+        "download",
+        &sandbox.remote("big.bin"),
+        sandbox.local("big.out").to_str().unwrap(),
+    ]);
+
+    assert_same_file(&sandbox.local("big.bin"), &sandbox.local("big.out"));
+}
+
+#[test]
+fn empty_and_sparse_files() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|err| err.into_inner());
+    let sandbox = Sandbox::new("sparse");
+
+    std::fs::write(sandbox.local("empty"), []).unwrap();
+    let mut sparse = vec![0; 8 << 20];
+    sparse.extend(test_data(100));
+    std::fs::write(sandbox.local("sparse"), sparse).unwrap();
+
+    sandbox.dfs(&[
+        "upload",
+        sandbox.local("empty").to_str().unwrap(),
+        sandbox.local("sparse").to_str().unwrap(),
+        &sandbox.remote(""),
+    ]);
+    for name in ["empty", "sparse"] {
+        let out = format!("{name}.out");
+        sandbox.dfs(&[
+            "download",
+            &sandbox.remote(name),
+            sandbox.local(&out).to_str().unwrap(),
+        ]);
+        assert_same_file(&sandbox.local(name), &sandbox.local(&out));
+    }
+}
+
+#[test]
+fn rm_removes_files_and_directories() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|err| err.into_inner());
+    let sandbox = Sandbox::new("rm");
+
+    std::fs::write(sandbox.local("file"), test_data(1000)).unwrap();
+    sandbox.dfs(&["mkdir", &sandbox.remote("dir/")]);
+    sandbox.dfs(&[
+        "upload",
+        sandbox.local("file").to_str().unwrap(),
+        &sandbox.remote("dir/file"),
+    ]);
+
+    sandbox.dfs(&["rm", &sandbox.remote("dir/file")]);
+    assert!(
+        !sandbox
+            .try_dfs(&["test", "-e", &sandbox.remote("dir/file")])
+            .status
+            .success()
+    );
+
+    sandbox.dfs(&["rm", "-r", &sandbox.remote("dir/")]);
+    assert!(
+        !sandbox
+            .try_dfs(&["test", "-d", &sandbox.remote("dir/")])
+            .status
+            .success()
+    );
+}
+
+// a file used as a directory doesn't exist as one, 'test' answers with exit code 1
+#[test]
+fn test_on_files_used_as_directories() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|err| err.into_inner());
+    let sandbox = Sandbox::new("test");
+
+    std::fs::write(sandbox.local("file"), test_data(1000)).unwrap();
+    sandbox.dfs(&[
+        "upload",
+        sandbox.local("file").to_str().unwrap(),
+        &sandbox.remote("file"),
+    ]);
+
+    sandbox.dfs(&["test", "-f", &sandbox.remote("file")]);
+    let status = sandbox
+        .try_dfs(&["test", "-d", &sandbox.remote("file")])
+        .status;
+    assert_eq!(status.code(), Some(1));
+    let status = sandbox
+        .try_dfs(&["test", "-e", &sandbox.remote("file/inner")])
+        .status;
+    assert_eq!(status.code(), Some(1));
+}
+
+// many small requests in a row run into Discord's rate limits, which have to be waited out
+#[test]
+fn rate_limited_uploads_complete() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|err| err.into_inner());
+    let sandbox = Sandbox::new("rate-limit");
+
+    let names: Vec<String> = (0..40).map(|idx| format!("small-{idx}")).collect();
+    for (idx, name) in names.iter().enumerate() {
+        std::fs::write(sandbox.local(name), test_data(idx + 1)).unwrap();
+    }
+
+    let mut args = vec![String::from("upload")];
+    args.extend(
+        names
+            .iter()
+            .map(|name| sandbox.local(name).to_string_lossy().into_owned()),
+    );
+    args.push(sandbox.remote(""));
+    sandbox.dfs(&args.iter().map(String::as_str).collect::<Vec<_>>());
+
+    for name in &names {
+        let out = format!("{name}.out");
+        sandbox.dfs(&[
+            "download",
+            &sandbox.remote(name),
+            sandbox.local(&out).to_str().unwrap(),
+        ]);
+        assert_same_file(&sandbox.local(name), &sandbox.local(&out));
+    }
+}